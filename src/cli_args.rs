@@ -19,4 +19,24 @@ pub struct CliArgs {
     /// The directory where the projects are located
     #[clap(long, env = "PROJECTS_DIR", default_value = "projects")]
     pub projects_dir: String,
+
+    /// The TOML file defining the job types that can be run
+    #[clap(long, env = "JOBS_CONFIG", default_value = "jobs.toml")]
+    pub jobs_config: String,
+
+    /// How many tasks may run at the same time. Other tasks wait in a queue
+    #[clap(long, env = "MAX_CONCURRENT_TASKS", default_value_t = 4)]
+    pub max_concurrent_tasks: usize,
+
+    /// The SQLite database file where tasks are stored
+    #[clap(long, env = "DATABASE_PATH", default_value = "job_hub.db")]
+    pub database_path: String,
+
+    /// The directory where the output of the OS processes of tasks is stored
+    #[clap(long, env = "TASK_OUTPUT_DIR", default_value = "task_output")]
+    pub task_output_dir: String,
+
+    /// How many days finished tasks are kept. 0 keeps them forever
+    #[clap(long, env = "TASK_RETENTION_DAYS", default_value_t = 30)]
+    pub task_retention_days: u64,
 }
@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use anyhow::Context;
 use axum::{
@@ -6,7 +6,7 @@ use axum::{
     http::HeaderMap,
     middleware::{self, Next},
     response::IntoResponse,
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use clap::Parser;
@@ -14,7 +14,14 @@ use job_hub::{
     cli_args::CliArgs,
     openapi::build_openapi,
     routes,
-    server::{response::ApiError, state::ApiState},
+    server::{
+        db::{Database, RetentionPolicy},
+        jobs::JobRegistry,
+        output::OutputStore,
+        response::ApiError,
+        scheduler::Scheduler,
+        state::ApiState,
+    },
 };
 use tower::ServiceBuilder;
 use tower_http::{
@@ -51,7 +58,63 @@ async fn main() -> anyhow::Result<()> {
 
     let cli_args = CliArgs::parse();
 
-    let state = ApiState::new(cli_args.api_token, cli_args.projects_dir);
+    let jobs = JobRegistry::load(&cli_args.jobs_config)
+        .with_context(|| format!("Failed to load jobs config from {}", cli_args.jobs_config))?;
+
+    let db = Database::open(&cli_args.database_path)
+        .await
+        .context("Failed to open database")?;
+
+    let interrupted = db
+        .interrupt_unfinished_tasks()
+        .await
+        .context("Failed to interrupt unfinished tasks")?;
+
+    if interrupted > 0 {
+        tracing::warn!(%interrupted, "Marked tasks from a previous run as interrupted");
+    }
+
+    let interrupted = db
+        .interrupt_unfinished_pipelines()
+        .await
+        .context("Failed to interrupt unfinished pipelines")?;
+
+    if interrupted > 0 {
+        tracing::warn!(%interrupted, "Marked pipelines from a previous run as interrupted");
+    }
+
+    // OS processes run in their own working directory, so they need an absolute project dir
+    let projects_dir = std::path::absolute(&cli_args.projects_dir)
+        .context("Failed to resolve projects dir")?
+        .to_string_lossy()
+        .to_string();
+
+    let scheduler = Scheduler::new(cli_args.max_concurrent_tasks);
+
+    let state = ApiState::new(
+        cli_args.api_token,
+        projects_dir,
+        db,
+        jobs,
+        scheduler,
+        OutputStore::new(&cli_args.task_output_dir),
+    );
+
+    let retention_policy = RetentionPolicy {
+        max_age: (cli_args.task_retention_days > 0)
+            .then(|| Duration::from_secs(cli_args.task_retention_days * 24 * 60 * 60)),
+        interval: Duration::from_secs(60 * 60),
+    };
+
+    let retention_state = state.clone();
+    tokio::spawn(async move {
+        retention_state.run_retention_policy(retention_policy).await;
+    });
+
+    let schedules_state = state.clone();
+    tokio::spawn(async move {
+        schedules_state.run_schedules().await;
+    });
 
     let api = Router::new()
         .route(
@@ -60,6 +123,21 @@ async fn main() -> anyhow::Result<()> {
         )
         .route("/cancel/:id", put(routes::cancel::cancel))
         .route("/status/:id", get(routes::status::status))
+        .route("/status/:id/events", get(routes::sse::task_status_events))
+        .route(
+            "/chats/:chat_id/events",
+            get(routes::sse::chat_status_events),
+        )
+        .route("/ws", get(routes::ws::ws))
+        .route("/tasks", get(routes::tasks::list_tasks))
+        .route("/tasks/status", post(routes::tasks::tasks_status))
+        .route("/tasks/:id/output", get(routes::output::task_output))
+        .route("/tasks/:id/stdin", post(routes::stdin::send_stdin))
+        .route("/tasks/:id/events", get(routes::history::task_history))
+        .route(
+            "/tasks/:id/output/tail",
+            get(routes::output::task_output_tail),
+        )
         .route("/list_log_files", get(routes::log_files::list_log_files))
         .route(
             "/download_zip_file",
@@ -73,6 +151,27 @@ async fn main() -> anyhow::Result<()> {
             "/gs_log_to_locust_converter",
             post(routes::gs_log_to_locust_converter::gs_log_to_locust_converter),
         )
+        .route("/jobs", get(routes::jobs::list_jobs))
+        .route("/jobs/:job_type", post(routes::jobs::run_job))
+        .route("/pipelines", post(routes::pipelines::run_pipeline))
+        .route("/pipelines/:id", get(routes::pipelines::pipeline))
+        .route(
+            "/pipelines/:id/cancel",
+            put(routes::pipelines::cancel_pipeline),
+        )
+        .route(
+            "/schedules",
+            get(routes::schedules::list_schedules).post(routes::schedules::create_schedule),
+        )
+        .route("/schedules/:id", delete(routes::schedules::delete_schedule))
+        .route(
+            "/schedules/:id/pause",
+            put(routes::schedules::pause_schedule),
+        )
+        .route(
+            "/schedules/:id/resume",
+            put(routes::schedules::resume_schedule),
+        )
         .layer(middleware::from_fn_with_state(
             state.clone(),
             validate_bearer_token,
@@ -21,6 +21,20 @@ use utoipa::{
         crate::routes::download_zip_file::download_zip_file,
         crate::routes::log_files::list_log_files,
         crate::routes::log_files::get_log_file_text,
+        crate::routes::jobs::list_jobs,
+        crate::routes::jobs::run_job,
+        crate::routes::output::task_output,
+        crate::routes::ws::ws,
+        crate::routes::tasks::list_tasks,
+        crate::routes::tasks::tasks_status,
+        crate::routes::history::task_history,
+        crate::routes::sse::task_status_events,
+        crate::routes::sse::chat_status_events,
+        crate::routes::stdin::send_stdin,
+        crate::routes::output::task_output_tail,
+        crate::routes::pipelines::run_pipeline,
+        crate::routes::pipelines::pipeline,
+        crate::routes::pipelines::cancel_pipeline,
     ),
     components(schemas(
         crate::server::task::Status,
@@ -28,18 +42,80 @@ use utoipa::{
         crate::server::task::ProcessStatus,
         crate::server::task::FailOperation,
         crate::server::task::ExitedStatus,
+        crate::server::task::TerminationSignal,
+        crate::server::task::TaskKind,
+        crate::server::task::StatusName,
+        crate::server::task::TaskPhase,
+        crate::server::task::FailureReason,
+        crate::server::task::FailureCode,
+        crate::server::limits::ResourceLimit,
+        crate::server::download::ExtractMode,
+        crate::server::download::CollisionPolicy,
+        crate::server::download::Checksum,
+        crate::server::download::HashAlgorithm,
+        crate::server::scheduler::Priority,
         crate::routes::gs_log_to_locust_converter::GsLogToLocustConverterOkResponse,
         crate::routes::gs_log_to_locust_converter::GsLogToLocustConverterErrorResponse,
         crate::routes::cancel::CancelOkResponse,
         crate::routes::cancel::CancelErrorResponse,
         crate::routes::status::StatusOkResponse,
         crate::routes::status::StatusErrorResponse,
+        crate::routes::status::AttemptInfo,
+        crate::routes::status::AttemptRecord,
+        crate::routes::stdin::SendStdinBody,
+        crate::routes::stdin::SendStdinOkResponse,
+        crate::routes::stdin::SendStdinErrorResponse,
+        crate::routes::tasks::TaskRecord,
+        crate::routes::tasks::ListTasksOkResponse,
+        crate::routes::tasks::ListTasksErrorResponse,
+        crate::routes::tasks::TasksStatusBody,
+        crate::routes::tasks::TaskStatusInfo,
+        crate::routes::tasks::TasksStatusOkResponse,
+        crate::routes::tasks::TasksStatusErrorResponse,
+        crate::routes::history::HistoryEntry,
+        crate::routes::history::TaskHistoryOkResponse,
+        crate::routes::history::TaskHistoryErrorResponse,
+        crate::server::history::EventSource,
+        crate::server::history::HistoryEvent,
+        crate::routes::sse::StatusEvent,
+        crate::routes::sse::StatusEventsErrorResponse,
+        crate::routes::output::TaskOutputOkResponse,
+        crate::routes::output::TaskOutputErrorResponse,
+        crate::server::progress::Progress,
+        crate::server::output::OutputLine,
+        crate::server::output::OutputStream,
         crate::routes::request_chat_id::RequestChatIdResponse,
         crate::routes::download_zip_file::DownloadZipFileOkResponse,
         crate::routes::download_zip_file::DownloadZipFileErrorResponse,
         crate::routes::log_files::ListLogfilesOkResponse,
         crate::routes::log_files::ListLogfilesErrorResponse,
         crate::routes::log_files::GetLogFileErrorResponse,
+        crate::routes::jobs::ListJobsOkResponse,
+        crate::routes::jobs::JobInfo,
+        crate::routes::jobs::RunJobOkResponse,
+        crate::routes::jobs::RunJobErrorResponse,
+        crate::routes::jobs::RunJobBody,
+        crate::server::jobs::ParamDefinition,
+        crate::server::jobs::JobParamsError,
+        crate::routes::pipelines::RunPipelineBody,
+        crate::routes::pipelines::RunPipelineOkResponse,
+        crate::routes::pipelines::RunPipelineErrorResponse,
+        crate::routes::pipelines::PipelineOkResponse,
+        crate::routes::pipelines::PipelineStepInfo,
+        crate::routes::pipelines::PipelineErrorResponse,
+        crate::routes::pipelines::CancelPipelineOkResponse,
+        crate::routes::pipelines::CancelPipelineErrorResponse,
+        crate::server::pipeline::PipelineStepSpec,
+        crate::server::pipeline::StepAction,
+        crate::server::pipeline::PipelineStatus,
+        crate::server::pipeline::PipelineGraphError,
+        crate::server::state::PipelineStepError,
+        crate::server::source::DownloadSource,
+        crate::server::source::DownloadSourceError,
+        crate::server::source::GoogleDriveLinkError,
+        crate::server::source::DropboxLinkError,
+        crate::server::source::OneDriveLinkError,
+        crate::server::source::GithubReleaseLinkError,
     ))
 )]
 struct ApiDoc;
@@ -1,8 +1,10 @@
 use crate::server::{
+    download::{Checksum, CollisionPolicy, ExtractMode, ExtractOptions},
     extractors::{chat_id::ChatId, query::Query},
     response::ApiError,
+    scheduler::Priority,
+    source::{DownloadSource, DownloadSourceError},
     state::ApiState,
-    utils::GoogleConvertLinkError,
 };
 use axum::{
     extract::State,
@@ -24,7 +26,8 @@ pub struct DownloadZipFileOkResponse {
 #[serde(tag = "error", content = "content")]
 pub enum DownloadZipFileErrorResponse {
     InvalidUrl,
-    Convert(GoogleConvertLinkError),
+    Source(DownloadSourceError),
+    InvalidChecksum,
     ServerError(ApiError),
 }
 
@@ -40,7 +43,10 @@ impl IntoResponse for DownloadZipFileErrorResponse {
             DownloadZipFileErrorResponse::InvalidUrl => {
                 (StatusCode::BAD_REQUEST, Json(self)).into_response()
             }
-            DownloadZipFileErrorResponse::Convert(_) => {
+            DownloadZipFileErrorResponse::InvalidChecksum => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            DownloadZipFileErrorResponse::Source(_) => {
                 (StatusCode::BAD_REQUEST, Json(self)).into_response()
             }
             DownloadZipFileErrorResponse::ServerError(err) => err.into_response(),
@@ -52,11 +58,25 @@ impl IntoResponse for DownloadZipFileErrorResponse {
 pub struct DownloadZipFileQuery {
     /// Name of the project
     project_name: String,
-    /// Google Drive share link for the zip file
-    google_drive_share_link: String,
+    /// Link to the file. `google_drive_share_link` is the former name
+    #[serde(alias = "google_drive_share_link")]
+    url: String,
+    /// Priority in the task queue
+    priority: Option<Priority>,
+    /// Defaults to flatten
+    extract_mode: Option<ExtractMode>,
+    /// Defaults to overwrite
+    on_collision: Option<CollisionPolicy>,
+    /// Expected hex sha256 of the downloaded file
+    sha256: Option<String>,
+    /// Expected hex md5 of the downloaded file
+    md5: Option<String>,
 }
 
-/// Schedule a download of a zip file from a Google Drive link.
+/// Schedule a download of a zip file from a link.
+///
+/// Google Drive, Dropbox and OneDrive share links and GitHub release assets are converted to direct downloads.
+/// Other https links are downloaded as they are. Tar archives (plain, gzip, zstd or xz compressed) and single gzip compressed files are extracted as well.
 ///
 /// This endpoint will schedule a task for running. The task will be executed asynchronously.
 #[utoipa::path(
@@ -65,7 +85,12 @@ pub struct DownloadZipFileQuery {
     params(
         ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
         ("project_name" = String, Query, description = "Name of the project."),
-        ("google_drive_share_link" = String, Query, description = "Google drive share link for the zip file.")
+        ("url" = String, Query, description = "Link to the zip file. A Google Drive, Dropbox or OneDrive share link, a GitHub release asset or any https URL. `google_drive_share_link` is accepted as an alias."),
+        ("priority" = Option<Priority>, Query, description = "Priority in the task queue. Defaults to normal."),
+        ("extract_mode" = Option<ExtractMode>, Query, description = "Whether files keep their directories (`preserve`) or are extracted into the project directory (`flatten`). Defaults to flatten. Entries with absolute paths, `..` or symlinks fail the download in both modes."),
+        ("on_collision" = Option<CollisionPolicy>, Query, description = "What happens when flattened files have the same name: `overwrite`, `skip`, `rename` or `fail`. Defaults to overwrite."),
+        ("sha256" = Option<String>, Query, description = "Expected hex sha256 of the downloaded file. Checked before extracting."),
+        ("md5" = Option<String>, Query, description = "Expected hex md5 of the downloaded file. Checked before extracting. Only one of `sha256` and `md5` may be given.")
     ),
     tag = "download",
     responses(
@@ -83,18 +108,28 @@ pub async fn download_zip_file(
     Query(query): Query<DownloadZipFileQuery>,
 ) -> Result<DownloadZipFileOkResponse, DownloadZipFileErrorResponse> {
     let project_name = query.project_name;
-    let google_drive_share_link = query.google_drive_share_link;
 
-    let google_drive_share_link = url::Url::parse(&google_drive_share_link)
-        .map_err(|_| DownloadZipFileErrorResponse::InvalidUrl)?;
+    let link = url::Url::parse(&query.url).map_err(|_| DownloadZipFileErrorResponse::InvalidUrl)?;
+
+    let link = DownloadSource::resolve(link).map_err(DownloadZipFileErrorResponse::Source)?;
+
+    let expected_checksum = Checksum::expected(query.sha256.as_deref(), query.md5.as_deref())
+        .map_err(|_| DownloadZipFileErrorResponse::InvalidChecksum)?;
 
-    let download_url = crate::server::utils::convert_google_share_or_view_url_to_download_url(
-        google_drive_share_link,
-    )
-    .map_err(DownloadZipFileErrorResponse::Convert)?;
+    let options = ExtractOptions {
+        extract_mode: query.extract_mode.unwrap_or_default(),
+        on_collision: query.on_collision.unwrap_or_default(),
+    };
 
     let id = state
-        .run_download_task(chat_id, download_url, project_name)
+        .run_download_task(
+            chat_id,
+            link,
+            project_name,
+            options,
+            expected_checksum,
+            query.priority,
+        )
         .await
         .map_err(|err| DownloadZipFileErrorResponse::ServerError(err.into()))?;
 
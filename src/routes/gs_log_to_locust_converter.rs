@@ -1,6 +1,7 @@
 use crate::server::{
     extractors::{chat_id::ChatId, query::Query},
-    state::{ApiState, GsLogToLocustConverterError},
+    scheduler::Priority,
+    state::{ApiState, RunJobError},
 };
 use axum::{
     extract::State,
@@ -21,12 +22,18 @@ pub struct GsLogToLocustConverterOkResponse {
 #[derive(Serialize, ToSchema)]
 pub enum GsLogToLocustConverterErrorResponse {
     NotFound,
+    ServerError,
 }
 
-impl From<GsLogToLocustConverterError> for GsLogToLocustConverterErrorResponse {
-    fn from(err: GsLogToLocustConverterError) -> Self {
+impl From<RunJobError> for GsLogToLocustConverterErrorResponse {
+    fn from(err: RunJobError) -> Self {
         match err {
-            GsLogToLocustConverterError::NotFound => GsLogToLocustConverterErrorResponse::NotFound,
+            RunJobError::ProjectNotFound => GsLogToLocustConverterErrorResponse::NotFound,
+            err => {
+                tracing::error!(%err, "Failed to create task");
+
+                GsLogToLocustConverterErrorResponse::ServerError
+            }
         }
     }
 }
@@ -43,6 +50,9 @@ impl IntoResponse for GsLogToLocustConverterErrorResponse {
             GsLogToLocustConverterErrorResponse::NotFound => {
                 (StatusCode::NOT_FOUND, Json(self)).into_response()
             }
+            GsLogToLocustConverterErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
         }
     }
 }
@@ -51,15 +61,18 @@ impl IntoResponse for GsLogToLocustConverterErrorResponse {
 pub struct GsLogToLocustConverterQuery {
     /// Name of the project
     project_name: String,
+    /// Priority in the task queue
+    priority: Option<Priority>,
 }
 
-/// Converts the format of log files given in the GS log format to the format used by locust (Locust log format). 
+/// Converts the format of log files given in the GS log format to the format used by locust (Locust log format).
 #[utoipa::path(
     post,
     path = "/api/gs_log_to_locust_converter", 
     params(
         ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint"),
-        ("project_name" = String, Query, description = "Name of the project.")
+        ("project_name" = String, Query, description = "Name of the project."),
+        ("priority" = Option<Priority>, Query, description = "Priority in the task queue. Defaults to normal.")
     ),
     tag = "convert",
     responses(
@@ -79,7 +92,7 @@ pub async fn gs_log_to_locust_converter(
     let project_name = query.project_name;
 
     let id = state
-        .run_gs_log_to_locust_converter_task(chat_id, project_name)
+        .run_gs_log_to_locust_converter_task(chat_id, project_name, query.priority)
         .await?;
 
     Ok(GsLogToLocustConverterOkResponse { id })
@@ -0,0 +1,185 @@
+//! Routes and responses for the history of tasks
+use crate::server::{
+    db::{DbError, HistoryRow},
+    extractors::chat_id::ChatId,
+    history::{EventSource, HistoryEvent},
+    state::ApiState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct HistoryEntry {
+    /// Increases with every entry. Not contiguous within a task
+    #[schema(example = 7)]
+    seq: u64,
+    #[schema(value_type = String, format = DateTime)]
+    at: DateTime<Utc>,
+    source: EventSource,
+    #[serde(flatten)]
+    event: HistoryEvent,
+}
+
+impl From<HistoryRow> for HistoryEntry {
+    fn from(row: HistoryRow) -> Self {
+        Self {
+            seq: row.seq,
+            at: row.at,
+            source: row.source,
+            event: row.event,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TaskHistoryOkResponse {
+    /// Oldest first
+    events: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum TaskHistoryErrorResponse {
+    NotFound,
+    ServerError,
+}
+
+impl IntoResponse for TaskHistoryOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for TaskHistoryErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            TaskHistoryErrorResponse::NotFound => {
+                (StatusCode::NOT_FOUND, Json(self)).into_response()
+            }
+            TaskHistoryErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<DbError> for TaskHistoryErrorResponse {
+    fn from(err: DbError) -> Self {
+        tracing::error!(%err, "Failed to get task history");
+
+        TaskHistoryErrorResponse::ServerError
+    }
+}
+
+/// Get the history of a task
+///
+/// Every status change, cancel request, signal sent to the OS process and failed attempt,
+/// with the time it happened and what caused it. Entries are never changed or removed while the task exists.
+#[utoipa::path(
+    get,
+    path = "/api/tasks/{id}/events",
+    params(
+        ("id" = String, Path, description = "Task id."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "History of the task", body = TaskHistoryOkResponse, example = json!({
+            "events": [
+                {"seq": 1, "at": "2024-01-01T12:00:00Z", "source": "system", "event": "StatusChanged", "content": {"status": {"type": "Process", "content": {"status": "Running"}}}},
+                {"seq": 2, "at": "2024-01-01T12:00:05Z", "source": "user", "event": "CancelRequested"},
+                {"seq": 3, "at": "2024-01-01T12:00:05Z", "source": "user", "event": "SignalSent", "content": {"signal": "Sigterm"}}
+            ]
+        })),
+        (status = 404, description = "Task not found for this chat id", body = TaskHistoryErrorResponse, example = json!(TaskHistoryErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing."),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = TaskHistoryErrorResponse, example = json!(TaskHistoryErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn task_history(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<TaskHistoryOkResponse, TaskHistoryErrorResponse> {
+    let rows = state
+        .task_history(&id, &chat_id)
+        .await?
+        .ok_or(TaskHistoryErrorResponse::NotFound)?;
+
+    Ok(TaskHistoryOkResponse {
+        events: rows.into_iter().map(HistoryEntry::from).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::task::{ProcessStatus, Status, TerminationSignal};
+
+    #[tokio::test]
+    async fn history_lists_every_entry_with_its_source_oldest_first() {
+        let state = ApiState::in_memory();
+        let db = state.db();
+        let id = db.insert_test_task("chat").await;
+
+        db.update_task_status(
+            &id,
+            &Status::Process(ProcessStatus::Running),
+            EventSource::System,
+        )
+        .await
+        .unwrap();
+        db.append_task_event(&id, EventSource::User, &HistoryEvent::CancelRequested)
+            .await
+            .unwrap();
+        let signal = HistoryEvent::SignalSent {
+            signal: TerminationSignal::Sigterm,
+        };
+        db.append_task_event(&id, EventSource::User, &signal)
+            .await
+            .unwrap();
+
+        let Ok(response) = task_history(
+            State(state.clone()),
+            Path(id.clone()),
+            ChatId(String::from("chat")),
+        )
+        .await
+        else {
+            panic!("Failed to get the history");
+        };
+
+        let response = serde_json::to_value(response).unwrap();
+        let events: Vec<_> = response["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| (entry["source"].clone(), entry["event"].clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("system", "StatusChanged"),
+                ("system", "StatusChanged"),
+                ("user", "CancelRequested"),
+                ("user", "SignalSent"),
+            ]
+            .map(|(source, event)| (source.into(), event.into()))
+        );
+
+        assert!(matches!(
+            task_history(State(state), Path(id), ChatId(String::from("other"))).await,
+            Err(TaskHistoryErrorResponse::NotFound)
+        ));
+    }
+}
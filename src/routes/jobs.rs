@@ -0,0 +1,188 @@
+//! Routes and responses for the job types defined in the jobs config
+use crate::server::{
+    extractors::{chat_id::ChatId, json::Json as JsonBody, query::Query},
+    jobs::{JobParamsError, ParamDefinition},
+    scheduler::Priority,
+    state::{ApiState, RunJobError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct ListJobsOkResponse {
+    /// Available job types
+    jobs: Vec<JobInfo>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobInfo {
+    /// Job type. Used in the `/api/jobs/{job_type}` endpoint
+    #[schema(example = "gs_log_to_locust_converter")]
+    job_type: String,
+    /// What the job does
+    description: Option<String>,
+    /// Parameters accepted by the job. The key is the name of the parameter
+    params: BTreeMap<String, ParamDefinition>,
+    /// Whether input can be sent to the job with `/api/tasks/{id}/stdin`
+    interactive: bool,
+}
+
+impl IntoResponse for ListJobsOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// List the available job types and their parameters
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "job",
+    responses(
+        (status = 200, description = "Available job types", body = ListJobsOkResponse),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn list_jobs(
+    State(state): State<ApiState>,
+    ChatId(_chat_id): ChatId,
+) -> ListJobsOkResponse {
+    let jobs = state
+        .jobs()
+        .iter()
+        .map(|(job_type, job)| JobInfo {
+            job_type: job_type.clone(),
+            description: job.description.clone(),
+            params: job.params.clone(),
+            interactive: job.interactive,
+        })
+        .collect();
+
+    ListJobsOkResponse { jobs }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RunJobOkResponse {
+    /// Task id that was scheduled for running
+    #[schema(example = "0")]
+    id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum RunJobErrorResponse {
+    JobNotFound,
+    ProjectNotFound,
+    InvalidParams(JobParamsError),
+    ServerError,
+}
+
+impl From<RunJobError> for RunJobErrorResponse {
+    fn from(err: RunJobError) -> Self {
+        match err {
+            RunJobError::JobNotFound => RunJobErrorResponse::JobNotFound,
+            RunJobError::ProjectNotFound => RunJobErrorResponse::ProjectNotFound,
+            RunJobError::Params(err) => RunJobErrorResponse::InvalidParams(err),
+            RunJobError::DbError(err) => {
+                tracing::error!(%err, "Failed to create task");
+
+                RunJobErrorResponse::ServerError
+            }
+        }
+    }
+}
+
+impl IntoResponse for RunJobOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::CREATED, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for RunJobErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            RunJobErrorResponse::JobNotFound | RunJobErrorResponse::ProjectNotFound => {
+                (StatusCode::NOT_FOUND, Json(self)).into_response()
+            }
+            RunJobErrorResponse::InvalidParams(_) => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            RunJobErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RunJobQuery {
+    /// Name of the project
+    project_name: String,
+    /// Priority in the task queue. Overrides the priority of the job type
+    priority: Option<Priority>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RunJobBody {
+    /// Parameters of the job. See `/api/jobs` for the accepted parameters
+    #[serde(default)]
+    params: HashMap<String, String>,
+}
+
+/// Run a job on a project.
+///
+/// This endpoint will schedule a task for running. The task will be executed asynchronously.
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{job_type}",
+    params(
+        ("job_type" = String, Path, description = "Job type. See the `/api/jobs` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("project_name" = String, Query, description = "Name of the project."),
+        ("priority" = Option<Priority>, Query, description = "Priority in the task queue. Defaults to the priority of the job type.")
+    ),
+    request_body = RunJobBody,
+    tag = "job",
+    responses(
+        (status = 201, description = "Task was scheduled for running", body = RunJobOkResponse, example = json!(RunJobOkResponse{id: String::from("some-id")})),
+        (status = 404, description = "Job type or project not found", body = RunJobErrorResponse, example = json!(RunJobErrorResponse::JobNotFound)),
+        (status = 400, description = "Invalid params. Chat id missing. Api key missing. Body invalid", body = RunJobErrorResponse, example = json!(RunJobErrorResponse::InvalidParams(JobParamsError::Missing(String::from("level"))))),
+        (status = 401, description = "Api key invalid"),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn run_job(
+    State(state): State<ApiState>,
+    Path(job_type): Path<String>,
+    ChatId(chat_id): ChatId,
+    Query(query): Query<RunJobQuery>,
+    JsonBody(body): JsonBody<RunJobBody>,
+) -> Result<RunJobOkResponse, RunJobErrorResponse> {
+    let id = state
+        .run_job_task(
+            chat_id,
+            &job_type,
+            query.project_name,
+            body.params,
+            query.priority,
+        )
+        .await?;
+
+    Ok(RunJobOkResponse { id })
+}
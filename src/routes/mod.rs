@@ -1,6 +1,15 @@
 pub mod cancel;
 pub mod download_zip_file;
 pub mod gs_log_to_locust_converter;
+pub mod history;
+pub mod jobs;
 pub mod log_files;
+pub mod output;
+pub mod pipelines;
 pub mod request_chat_id;
+pub mod schedules;
+pub mod sse;
 pub mod status;
+pub mod stdin;
+pub mod tasks;
+pub mod ws;
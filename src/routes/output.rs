@@ -0,0 +1,146 @@
+//! Routes and responses for the output of the OS processes of tasks
+use crate::server::{
+    extractors::{chat_id::ChatId, query::Query},
+    output::{OutputLine, OutputStream},
+    state::{ApiState, TaskOutputError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct TaskOutputOkResponse {
+    /// Oldest first. Empty for tasks without an OS process
+    lines: Vec<OutputLine>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum TaskOutputErrorResponse {
+    NotFound,
+    ServerError,
+}
+
+impl IntoResponse for TaskOutputOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for TaskOutputErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            TaskOutputErrorResponse::NotFound => {
+                (StatusCode::NOT_FOUND, Json(self)).into_response()
+            }
+            TaskOutputErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<TaskOutputError> for TaskOutputErrorResponse {
+    fn from(err: TaskOutputError) -> Self {
+        tracing::error!(%err, "Failed to read task output");
+
+        TaskOutputErrorResponse::ServerError
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TaskOutputQuery {
+    /// Both streams if missing
+    #[serde(default)]
+    stream: Option<OutputStream>,
+}
+
+#[derive(Deserialize)]
+pub struct TaskOutputTailQuery {
+    /// Both streams if missing
+    #[serde(default)]
+    stream: Option<OutputStream>,
+    #[serde(default = "default_tail_lines")]
+    lines: usize,
+}
+
+fn default_tail_lines() -> usize {
+    100
+}
+
+/// Get the complete output of a task
+///
+/// Lines are timestamped and ordered by time. The output stays available after the task finished.
+#[utoipa::path(
+    get,
+    path = "/api/tasks/{id}/output",
+    params(
+        ("id" = String, Path, description = "Task id."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("stream" = Option<OutputStream>, Query, description = "Only lines of this stream. Both streams if missing.")
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "Output of the task", body = TaskOutputOkResponse),
+        (status = 404, description = "Task not found for this chat id", body = TaskOutputErrorResponse, example = json!(TaskOutputErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing. Query invalid."),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = TaskOutputErrorResponse, example = json!(TaskOutputErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn task_output(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+    Query(query): Query<TaskOutputQuery>,
+) -> Result<TaskOutputOkResponse, TaskOutputErrorResponse> {
+    let lines = state
+        .task_output(&id, &chat_id, query.stream, None)
+        .await?
+        .ok_or(TaskOutputErrorResponse::NotFound)?;
+
+    Ok(TaskOutputOkResponse { lines })
+}
+
+/// Get the last lines of the output of a task
+#[utoipa::path(
+    get,
+    path = "/api/tasks/{id}/output/tail",
+    params(
+        ("id" = String, Path, description = "Task id."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("stream" = Option<OutputStream>, Query, description = "Only lines of this stream. Both streams if missing."),
+        ("lines" = Option<usize>, Query, description = "Number of lines. Defaults to 100.")
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "Last lines of the output of the task", body = TaskOutputOkResponse),
+        (status = 404, description = "Task not found for this chat id", body = TaskOutputErrorResponse, example = json!(TaskOutputErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing. Query invalid."),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = TaskOutputErrorResponse, example = json!(TaskOutputErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn task_output_tail(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+    Query(query): Query<TaskOutputTailQuery>,
+) -> Result<TaskOutputOkResponse, TaskOutputErrorResponse> {
+    let lines = state
+        .task_output(&id, &chat_id, query.stream, Some(query.lines))
+        .await?
+        .ok_or(TaskOutputErrorResponse::NotFound)?;
+
+    Ok(TaskOutputOkResponse { lines })
+}
@@ -0,0 +1,273 @@
+//! Routes and responses for pipelines of tasks
+use crate::server::{
+    extractors::{chat_id::ChatId, json::Json as JsonBody},
+    pipeline::{PipelineGraphError, PipelineStatus, PipelineStepSpec},
+    scheduler::Priority,
+    state::{ApiState, PipelineStepError, RunPipelineError},
+    task::Status,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RunPipelineBody {
+    /// Steps of the pipeline. A step runs once all the steps in its `depends_on` succeeded
+    steps: Vec<PipelineStepSpec>,
+    /// Priority in the task queue for every step. Defaults to the priority of each step
+    priority: Option<Priority>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RunPipelineOkResponse {
+    /// Pipeline id that was scheduled for running
+    #[schema(example = "0")]
+    id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum RunPipelineErrorResponse {
+    InvalidPipeline(PipelineGraphError),
+    InvalidStep {
+        step: String,
+        error: PipelineStepError,
+    },
+    ServerError,
+}
+
+impl From<RunPipelineError> for RunPipelineErrorResponse {
+    fn from(err: RunPipelineError) -> Self {
+        match err {
+            RunPipelineError::Graph(err) => RunPipelineErrorResponse::InvalidPipeline(err),
+            RunPipelineError::Step { step, err } => {
+                RunPipelineErrorResponse::InvalidStep { step, error: err }
+            }
+            err => {
+                tracing::error!(%err, "Failed to create pipeline");
+
+                RunPipelineErrorResponse::ServerError
+            }
+        }
+    }
+}
+
+impl IntoResponse for RunPipelineOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::CREATED, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for RunPipelineErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            RunPipelineErrorResponse::InvalidStep {
+                error: PipelineStepError::JobNotFound | PipelineStepError::ProjectNotFound,
+                ..
+            } => (StatusCode::NOT_FOUND, Json(self)).into_response(),
+            RunPipelineErrorResponse::InvalidPipeline(_)
+            | RunPipelineErrorResponse::InvalidStep { .. } => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            RunPipelineErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+/// Run a pipeline of downloads and jobs.
+///
+/// A task is created for every step. Steps whose upstream steps did not succeed are skipped.
+#[utoipa::path(
+    post,
+    path = "/api/pipelines",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    request_body = RunPipelineBody,
+    tag = "pipeline",
+    responses(
+        (status = 201, description = "Pipeline was scheduled for running", body = RunPipelineOkResponse, example = json!(RunPipelineOkResponse{id: String::from("some-id")})),
+        (status = 404, description = "Job type or project of a step not found", body = RunPipelineErrorResponse, example = json!(RunPipelineErrorResponse::InvalidStep{step: String::from("convert"), error: PipelineStepError::ProjectNotFound})),
+        (status = 400, description = "Invalid pipeline or step. Chat id missing. Api key missing. Body invalid", body = RunPipelineErrorResponse, example = json!(RunPipelineErrorResponse::InvalidPipeline(PipelineGraphError::Cycle))),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = RunPipelineErrorResponse, example = json!(RunPipelineErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn run_pipeline(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+    JsonBody(body): JsonBody<RunPipelineBody>,
+) -> Result<RunPipelineOkResponse, RunPipelineErrorResponse> {
+    let id = state
+        .run_pipeline(chat_id, body.steps, body.priority)
+        .await?;
+
+    Ok(RunPipelineOkResponse { id })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PipelineOkResponse {
+    #[schema(example = "0")]
+    id: String,
+    status: PipelineStatus,
+    /// In the order they were submitted
+    steps: Vec<PipelineStepInfo>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PipelineStepInfo {
+    name: String,
+    /// Use the `/api/status/{id}` endpoint for more details
+    task_id: String,
+    depends_on: Vec<String>,
+    /// Never ran because an upstream step did not succeed or the pipeline was canceled
+    skipped: bool,
+    /// Missing if the task was already deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum PipelineErrorResponse {
+    NotFound,
+    ServerError,
+}
+
+impl IntoResponse for PipelineOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for PipelineErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            PipelineErrorResponse::NotFound => (StatusCode::NOT_FOUND, Json(self)).into_response(),
+            PipelineErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+/// Get the status of a pipeline and its steps
+#[utoipa::path(
+    get,
+    path = "/api/pipelines/{id}",
+    params(
+        ("id" = String, Path, description = "Pipeline id. generated using the `/api/pipelines` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "pipeline",
+    responses(
+        (status = 200, description = "Pipeline found", body = PipelineOkResponse),
+        (status = 404, description = "Pipeline not found for this chat id", body = PipelineErrorResponse, example = json!(PipelineErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = PipelineErrorResponse, example = json!(PipelineErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn pipeline(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<PipelineOkResponse, PipelineErrorResponse> {
+    let pipeline = state
+        .pipeline(&id, &chat_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(%err, %id, "Failed to get pipeline");
+
+            PipelineErrorResponse::ServerError
+        })?
+        .ok_or(PipelineErrorResponse::NotFound)?;
+
+    let steps = pipeline
+        .steps
+        .into_iter()
+        .map(|step| PipelineStepInfo {
+            name: step.name,
+            task_id: step.task_id,
+            depends_on: step.depends_on,
+            skipped: step.skipped,
+            status: step.status,
+        })
+        .collect();
+
+    Ok(PipelineOkResponse {
+        id: pipeline.id,
+        status: pipeline.status,
+        steps,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CancelPipelineOkResponse {
+    /// Pipeline id that was scheduled for cancellation
+    #[schema(example = "0")]
+    id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum CancelPipelineErrorResponse {
+    NotFound,
+}
+
+impl IntoResponse for CancelPipelineOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for CancelPipelineErrorResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::NOT_FOUND, Json(self)).into_response()
+    }
+}
+
+/// Schedule a running pipeline for cancellation.
+///
+/// Running steps are canceled and steps that did not start yet are skipped.
+#[utoipa::path(
+    put,
+    path = "/api/pipelines/{id}/cancel",
+    params(
+        ("id" = String, Path, description = "Pipeline id. generated using the `/api/pipelines` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "pipeline",
+    responses(
+        (status = 200, description = "Pipeline was scheduled for cancellation", body = CancelPipelineOkResponse, example = json!(CancelPipelineOkResponse{id: String::from("some-id")})),
+        (status = 404, description = "Running pipeline not found for this chat id", body = CancelPipelineErrorResponse, example = json!(CancelPipelineErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn cancel_pipeline(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<CancelPipelineOkResponse, CancelPipelineErrorResponse> {
+    let _ = state
+        .cancel_pipeline(&id, &chat_id)
+        .await
+        .ok_or(CancelPipelineErrorResponse::NotFound)?;
+
+    Ok(CancelPipelineOkResponse { id })
+}
@@ -0,0 +1,397 @@
+//! Routes and responses for cron schedules of jobs
+use crate::server::{
+    db::{DbError, ScheduleRow},
+    extractors::{chat_id::ChatId, json::Json as JsonBody},
+    jobs::JobParamsError,
+    schedule,
+    scheduler::Priority,
+    state::{ApiState, CreateScheduleError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct ScheduleInfo {
+    #[schema(example = "0")]
+    id: String,
+    /// Cron expression in UTC
+    #[schema(example = "0 2 * * *")]
+    cron: String,
+    #[schema(example = "gs_log_to_locust_converter")]
+    job_type: String,
+    project_name: String,
+    params: HashMap<String, String>,
+    /// Missing if the priority of the job type is used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<Priority>,
+    paused: bool,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: DateTime<Utc>,
+    /// Missing while paused
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    next_fire_at: Option<DateTime<Utc>>,
+    /// Missing if the schedule never fired
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_run: Option<LastRun>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LastRun {
+    #[schema(value_type = String, format = DateTime)]
+    fired_at: DateTime<Utc>,
+    /// Task created by the run. Missing if no task could be created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_id: Option<String>,
+    /// Status endpoint of the task
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "/api/status/0")]
+    task_status_url: Option<String>,
+    /// Why no task could be created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<ScheduleRow> for ScheduleInfo {
+    fn from(row: ScheduleRow) -> Self {
+        let next_fire_at = schedule::next_fire_at(&row);
+
+        let last_run = row.last_run_at.map(|fired_at| LastRun {
+            fired_at,
+            task_status_url: row
+                .last_task_id
+                .as_ref()
+                .map(|task_id| format!("/api/status/{task_id}")),
+            task_id: row.last_task_id,
+            error: row.last_error,
+        });
+
+        Self {
+            id: row.id,
+            cron: row.cron,
+            job_type: row.job_type,
+            project_name: row.project_name,
+            params: row.params,
+            priority: row.priority,
+            paused: row.paused,
+            created_at: row.created_at,
+            next_fire_at,
+            last_run,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateScheduleBody {
+    /// Cron expression in UTC. 5 fields, or 6 to 7 fields with leading seconds and trailing years
+    #[schema(example = "0 2 * * *")]
+    cron: String,
+    /// Job type. See the `/api/jobs` endpoint
+    job_type: String,
+    /// Name of the project
+    project_name: String,
+    /// Parameters of the job
+    #[serde(default)]
+    params: HashMap<String, String>,
+    /// Priority in the task queue. Defaults to the priority of the job type
+    priority: Option<Priority>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum CreateScheduleErrorResponse {
+    InvalidCron(String),
+    JobNotFound,
+    ProjectNotFound,
+    InvalidParams(JobParamsError),
+    ServerError,
+}
+
+impl From<CreateScheduleError> for CreateScheduleErrorResponse {
+    fn from(err: CreateScheduleError) -> Self {
+        match err {
+            CreateScheduleError::InvalidCron(err) => {
+                CreateScheduleErrorResponse::InvalidCron(err.0)
+            }
+            CreateScheduleError::JobNotFound => CreateScheduleErrorResponse::JobNotFound,
+            CreateScheduleError::ProjectNotFound => CreateScheduleErrorResponse::ProjectNotFound,
+            CreateScheduleError::Params(err) => CreateScheduleErrorResponse::InvalidParams(err),
+            CreateScheduleError::DbError(err) => {
+                tracing::error!(%err, "Failed to create schedule");
+
+                CreateScheduleErrorResponse::ServerError
+            }
+        }
+    }
+}
+
+impl IntoResponse for CreateScheduleErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            CreateScheduleErrorResponse::JobNotFound
+            | CreateScheduleErrorResponse::ProjectNotFound => {
+                (StatusCode::NOT_FOUND, Json(self)).into_response()
+            }
+            CreateScheduleErrorResponse::InvalidCron(_)
+            | CreateScheduleErrorResponse::InvalidParams(_) => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            CreateScheduleErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+/// Created schedule
+#[derive(Serialize, ToSchema)]
+pub struct CreateScheduleOkResponse(ScheduleInfo);
+
+impl IntoResponse for CreateScheduleOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::CREATED, Json(self)).into_response()
+    }
+}
+
+/// Create a schedule that runs a job on a project.
+///
+/// Every time the schedule fires, a task is created just like with the `/api/jobs/{job_type}` endpoint.
+#[utoipa::path(
+    post,
+    path = "/api/schedules",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    request_body = CreateScheduleBody,
+    tag = "schedule",
+    responses(
+        (status = 201, description = "Schedule was created", body = ScheduleInfo),
+        (status = 404, description = "Job type or project not found", body = CreateScheduleErrorResponse, example = json!(CreateScheduleErrorResponse::JobNotFound)),
+        (status = 400, description = "Invalid cron expression or params. Chat id missing. Api key missing. Body invalid", body = CreateScheduleErrorResponse, example = json!(CreateScheduleErrorResponse::InvalidCron(String::from("Invalid cron expression")))),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = CreateScheduleErrorResponse, example = json!(CreateScheduleErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn create_schedule(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+    JsonBody(body): JsonBody<CreateScheduleBody>,
+) -> Result<CreateScheduleOkResponse, CreateScheduleErrorResponse> {
+    let schedule = state
+        .create_schedule(
+            &chat_id,
+            &body.cron,
+            &body.job_type,
+            &body.project_name,
+            body.params,
+            body.priority,
+        )
+        .await?;
+
+    Ok(CreateScheduleOkResponse(schedule.into()))
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum ScheduleErrorResponse {
+    NotFound,
+    ServerError,
+}
+
+impl From<DbError> for ScheduleErrorResponse {
+    fn from(err: DbError) -> Self {
+        tracing::error!(%err, "Failed to access schedules");
+
+        ScheduleErrorResponse::ServerError
+    }
+}
+
+impl IntoResponse for ScheduleErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            ScheduleErrorResponse::NotFound => (StatusCode::NOT_FOUND, Json(self)).into_response(),
+            ScheduleErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListSchedulesOkResponse {
+    /// Schedules of the chat, oldest first
+    schedules: Vec<ScheduleInfo>,
+}
+
+impl IntoResponse for ListSchedulesOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// List the schedules of a chat
+#[utoipa::path(
+    get,
+    path = "/api/schedules",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Schedules of the chat", body = ListSchedulesOkResponse),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn list_schedules(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+) -> Result<ListSchedulesOkResponse, ScheduleErrorResponse> {
+    let schedules = state
+        .schedules(&chat_id)
+        .await?
+        .into_iter()
+        .map(ScheduleInfo::from)
+        .collect();
+
+    Ok(ListSchedulesOkResponse { schedules })
+}
+
+/// Updated schedule
+#[derive(Serialize, ToSchema)]
+pub struct ScheduleOkResponse(ScheduleInfo);
+
+impl IntoResponse for ScheduleOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// Pause a schedule. A paused schedule does not fire
+#[utoipa::path(
+    put,
+    path = "/api/schedules/{id}/pause",
+    params(
+        ("id" = String, Path, description = "Schedule id. generated using the `/api/schedules` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Schedule was paused", body = ScheduleInfo),
+        (status = 404, description = "Schedule not found for this chat id", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn pause_schedule(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<ScheduleOkResponse, ScheduleErrorResponse> {
+    set_paused(state, id, chat_id, true).await
+}
+
+/// Resume a paused schedule. Runs missed while paused are not caught up
+#[utoipa::path(
+    put,
+    path = "/api/schedules/{id}/resume",
+    params(
+        ("id" = String, Path, description = "Schedule id. generated using the `/api/schedules` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Schedule was resumed", body = ScheduleInfo),
+        (status = 404, description = "Schedule not found for this chat id", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn resume_schedule(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<ScheduleOkResponse, ScheduleErrorResponse> {
+    set_paused(state, id, chat_id, false).await
+}
+
+async fn set_paused(
+    state: ApiState,
+    id: String,
+    chat_id: String,
+    paused: bool,
+) -> Result<ScheduleOkResponse, ScheduleErrorResponse> {
+    let schedule = state
+        .set_schedule_paused(&id, &chat_id, paused)
+        .await?
+        .ok_or(ScheduleErrorResponse::NotFound)?;
+
+    Ok(ScheduleOkResponse(schedule.into()))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteScheduleOkResponse {
+    /// Schedule id that was deleted
+    #[schema(example = "0")]
+    id: String,
+}
+
+impl IntoResponse for DeleteScheduleOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// Delete a schedule. Tasks created by the schedule are kept
+#[utoipa::path(
+    delete,
+    path = "/api/schedules/{id}",
+    params(
+        ("id" = String, Path, description = "Schedule id. generated using the `/api/schedules` endpoint."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Schedule was deleted", body = DeleteScheduleOkResponse, example = json!(DeleteScheduleOkResponse{id: String::from("some-id")})),
+        (status = 404, description = "Schedule not found for this chat id", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing"),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = ScheduleErrorResponse, example = json!(ScheduleErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn delete_schedule(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+) -> Result<DeleteScheduleOkResponse, ScheduleErrorResponse> {
+    if !state.delete_schedule(&id, &chat_id).await? {
+        return Err(ScheduleErrorResponse::NotFound);
+    }
+
+    Ok(DeleteScheduleOkResponse { id })
+}
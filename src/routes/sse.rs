@@ -0,0 +1,427 @@
+//! Server-Sent Events with the status changes of tasks
+//!
+//! Every status change is sent as a `status` event, except for the final status of a task,
+//! which is sent as a `terminal` event. Reconnecting clients resume with the `Last-Event-ID` header.
+use crate::server::{
+    db::{DbError, TransitionRow},
+    events::TaskEvent,
+    extractors::chat_id::ChatId,
+    state::ApiState,
+    task::Status,
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Response, Sse,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::Serialize;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::{broadcast, broadcast::error::RecvError, mpsc};
+use utoipa::ToSchema;
+
+/// Data of `status` and `terminal` events
+#[derive(Serialize, ToSchema)]
+pub struct StatusEvent {
+    /// Task id
+    #[schema(example = "0")]
+    id: String,
+    status: Status,
+    /// Incremented with every status change of the task
+    #[schema(example = 3)]
+    version: u64,
+    #[schema(value_type = String, format = DateTime)]
+    at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum StatusEventsErrorResponse {
+    NotFound,
+    ServerError,
+}
+
+impl IntoResponse for StatusEventsErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            StatusEventsErrorResponse::NotFound => {
+                (StatusCode::NOT_FOUND, Json(self)).into_response()
+            }
+            StatusEventsErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<DbError> for StatusEventsErrorResponse {
+    fn from(err: DbError) -> Self {
+        tracing::error!(%err, "Failed to get status changes");
+
+        StatusEventsErrorResponse::ServerError
+    }
+}
+
+/// Stream the status changes of a task
+///
+/// Starts with the current status, or with the changes after `Last-Event-ID`.
+/// Event ids are the version of the task. The stream ends after the `terminal` event.
+#[utoipa::path(
+    get,
+    path = "/api/status/{id}/events",
+    params(
+        ("id" = String, Path, description = "Task id."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Version of the last received event. Changes after it are sent first.")
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "`text/event-stream` of `status` and `terminal` events", body = StatusEvent, content_type = "text/event-stream"),
+        (status = 204, description = "The task already finished and the client has seen its terminal event"),
+        (status = 404, description = "Task not found for this chat id", body = StatusEventsErrorResponse, example = json!(StatusEventsErrorResponse::NotFound)),
+        (status = 400, description = "Chat id missing. Api key missing."),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = StatusEventsErrorResponse, example = json!(StatusEventsErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn task_status_events(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+    headers: HeaderMap,
+) -> Result<Response, StatusEventsErrorResponse> {
+    // Subscribed before reading the database, so that no change is missed in between
+    let events = state.subscribe_task_events();
+
+    let task = state
+        .task(&id, &chat_id)
+        .await?
+        .ok_or(StatusEventsErrorResponse::NotFound)?;
+
+    let last_version = last_event_id(&headers).unwrap_or(task.version.saturating_sub(1));
+
+    // Tells EventSource clients to stop reconnecting
+    if task.status.is_terminal() && last_version >= task.version {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    let missed = state.task_transitions_after(&id, last_version).await?;
+
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(send_task_events(
+        state,
+        id,
+        last_version,
+        missed,
+        events,
+        tx,
+    ));
+
+    Ok(sse(rx, |transition| transition.version))
+}
+
+/// Stream the status changes of every task of a chat
+///
+/// Starts with the changes after `Last-Event-ID` if given, otherwise with the next change. Event ids increase across all tasks.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/events",
+    params(
+        ("chat_id" = String, Path, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Id of the last received event. Changes after it are sent first.")
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "`text/event-stream` of `status` and `terminal` events", body = StatusEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Api key missing."),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = StatusEventsErrorResponse, example = json!(StatusEventsErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn chat_status_events(
+    State(state): State<ApiState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusEventsErrorResponse> {
+    let events = state.subscribe_task_events();
+
+    let last_seq = match last_event_id(&headers) {
+        Some(last_seq) => last_seq,
+        None => state.latest_chat_seq(&chat_id).await?,
+    };
+
+    let missed = state.chat_transitions_after(&chat_id, last_seq).await?;
+
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(send_chat_events(
+        state, chat_id, last_seq, missed, events, tx,
+    ));
+
+    Ok(sse(rx, |transition| transition.seq))
+}
+
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// `event_id` is the id the client resumes from.
+fn sse(rx: mpsc::Receiver<TransitionRow>, event_id: fn(&TransitionRow) -> u64) -> Response {
+    let stream = futures::stream::unfold(rx, move |mut rx| async move {
+        let transition = rx.recv().await?;
+
+        Some((
+            Ok::<_, Infallible>(event(&transition, event_id(&transition))),
+            rx,
+        ))
+    });
+
+    Sse::new(Box::pin(stream) as std::pin::Pin<Box<dyn Stream<Item = _> + Send>>)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn event(transition: &TransitionRow, id: u64) -> Event {
+    let name = if transition.status.is_terminal() {
+        "terminal"
+    } else {
+        "status"
+    };
+
+    let data = StatusEvent {
+        id: transition.task_id.clone(),
+        status: transition.status.clone(),
+        version: transition.version,
+        at: transition.at,
+    };
+
+    Event::default()
+        .event(name)
+        .id(id.to_string())
+        .json_data(data)
+        .expect("Status events are serializable")
+}
+
+/// Sends the missed changes, then the live ones until the task reached a terminal status.
+#[tracing::instrument(skip_all, fields(id))]
+async fn send_task_events(
+    state: ApiState,
+    id: String,
+    mut last_version: u64,
+    mut missed: Vec<TransitionRow>,
+    mut events: broadcast::Receiver<Arc<TaskEvent>>,
+    tx: mpsc::Sender<TransitionRow>,
+) {
+    loop {
+        for transition in missed.drain(..) {
+            if transition.version <= last_version {
+                continue;
+            }
+
+            last_version = transition.version;
+
+            let terminal = transition.status.is_terminal();
+
+            if tx.send(transition).await.is_err() || terminal {
+                return;
+            }
+        }
+
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = tx.closed() => return,
+        };
+
+        match event {
+            Ok(event) if event.task_id == id => {
+                if let Some((_, transition)) = event.transition() {
+                    missed.push(transition);
+                }
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => {
+                match state.task_transitions_after(&id, last_version).await {
+                    Ok(transitions) => missed = transitions,
+                    Err(err) => {
+                        tracing::error!(%err, "Failed to catch up with status changes");
+                        return;
+                    }
+                }
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Sends the missed changes, then the live ones until the client disconnects.
+///
+/// Tasks publish their changes independently, so the events of the bus may be out of [`TransitionRow::seq`] order.
+/// Every event of the chat is therefore only a signal to read the changes after `last_seq` from the database.
+#[tracing::instrument(skip_all, fields(chat_id))]
+async fn send_chat_events(
+    state: ApiState,
+    chat_id: String,
+    mut last_seq: u64,
+    mut missed: Vec<TransitionRow>,
+    mut events: broadcast::Receiver<Arc<TaskEvent>>,
+    tx: mpsc::Sender<TransitionRow>,
+) {
+    loop {
+        for transition in missed.drain(..) {
+            if transition.seq <= last_seq {
+                continue;
+            }
+
+            last_seq = transition.seq;
+
+            if tx.send(transition).await.is_err() {
+                return;
+            }
+        }
+
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = tx.closed() => return,
+        };
+
+        match event {
+            Ok(event) => {
+                let Some((event_chat_id, transition)) = event.transition() else {
+                    continue;
+                };
+
+                // Already read together with a later change
+                if event_chat_id != chat_id || transition.seq <= last_seq {
+                    continue;
+                }
+            }
+            // The changes may be among the skipped events
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+
+        match state.chat_transitions_after(&chat_id, last_seq).await {
+            Ok(transitions) => missed = transitions,
+            Err(err) => {
+                tracing::error!(%err, "Failed to catch up with status changes");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::task::ProcessStatus;
+
+    #[tokio::test]
+    async fn task_stream_resumes_after_last_event_id_and_ends_with_terminal_event() {
+        let state = ApiState::in_memory();
+        let id = state.db().insert_test_task("chat").await;
+
+        state
+            .persist_status(&id, Status::Process(ProcessStatus::Running))
+            .await;
+        state
+            .persist_status(
+                &id,
+                Status::Process(ProcessStatus::Canceled { signal: None }),
+            )
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", "1".parse().unwrap());
+
+        let Ok(response) = task_status_events(
+            State(state.clone()),
+            Path(id.clone()),
+            ChatId(String::from("chat")),
+            headers.clone(),
+        )
+        .await
+        else {
+            panic!("Failed to stream the status changes");
+        };
+
+        // Ends after the terminal event
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        let events: Vec<_> = body
+            .split("\n\n")
+            .filter(|event| !event.is_empty())
+            .map(|event| event.lines().take(2).collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(events, ["event: status id: 2", "event: terminal id: 3"]);
+
+        headers.insert("last-event-id", "3".parse().unwrap());
+
+        let Ok(response) = task_status_events(
+            State(state),
+            Path(id),
+            ChatId(String::from("chat")),
+            headers,
+        )
+        .await
+        else {
+            panic!("Failed to stream the status changes");
+        };
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn chat_stream_sends_changes_published_out_of_order_in_seq_order() {
+        let state = ApiState::in_memory();
+        let first = state.db().insert_test_task("chat").await;
+        let second = state.db().insert_test_task("chat").await;
+
+        let last_seq = state.latest_chat_seq("chat").await.unwrap();
+        let events = state.subscribe_task_events();
+        let (tx, mut rx) = mpsc::channel(16);
+
+        tokio::spawn(send_chat_events(
+            state.clone(),
+            String::from("chat"),
+            last_seq,
+            Vec::new(),
+            events,
+            tx,
+        ));
+
+        let running = Status::Process(ProcessStatus::Running);
+        let first_event = state.persist_status(&first, running.clone()).await;
+        let second_event = state.persist_status(&second, running).await;
+
+        state.events().publish(&second, second_event);
+        state.events().publish(&first, first_event);
+
+        let timeout = std::time::Duration::from_millis(200);
+        let mut received = Vec::new();
+
+        while let Ok(Some(transition)) = tokio::time::timeout(timeout, rx.recv()).await {
+            received.push((transition.task_id, transition.seq));
+        }
+
+        assert_eq!(received, [(first, last_seq + 1), (second, last_seq + 2)]);
+    }
+}
@@ -1,7 +1,13 @@
-use crate::server::{
-    extractors::chat_id::ChatId,
-    state::ApiState,
-    task::{ProcessStatus, Status},
+use crate::{
+    routes::tasks::TaskRecord,
+    server::{
+        db::{AttemptRow, DbError},
+        events::TaskEvent,
+        extractors::{chat_id::ChatId, query::Query},
+        progress::Progress,
+        state::ApiState,
+        task::Status,
+    },
 };
 use axum::{
     extract::{Path, State},
@@ -9,18 +15,80 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
 use utoipa::ToSchema;
 
+/// Upper bound for `wait_for_change`, below the timeouts of typical HTTP clients
+const MAX_WAIT_FOR_CHANGE_SECS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct StatusQuery {
+    /// Seconds to wait for a status change before responding
+    #[serde(default)]
+    wait_for_change: Option<u64>,
+    /// The version the client already knows. The current version if missing
+    #[serde(default)]
+    since: Option<u64>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct StatusOkResponse {
-    /// Status of a given task
-    status: Status,
+    #[serde(flatten)]
+    task: TaskRecord,
+    /// Progress of the running attempt. Only present while the task runs and reports progress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+    /// 1-based position in the task queue. Only present while the task is queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2)]
+    queue_position: Option<usize>,
+    /// Current attempt of the task. Only present once the task started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempt: Option<AttemptInfo>,
+    /// Every attempt of the task, oldest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attempts: Vec<AttemptRecord>,
+}
+
+/// Attempt `attempt` of `max_attempts`
+#[derive(Serialize, ToSchema)]
+pub struct AttemptInfo {
+    #[schema(example = 2)]
+    attempt: u32,
+    #[schema(example = 3)]
+    max_attempts: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AttemptRecord {
+    attempt: u32,
+    #[schema(value_type = String, format = DateTime)]
+    started_at: DateTime<Utc>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    finished_at: Option<DateTime<Utc>>,
+    /// Final status of the attempt. Missing while the attempt runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+impl From<AttemptRow> for AttemptRecord {
+    fn from(row: AttemptRow) -> Self {
+        Self {
+            attempt: row.attempt,
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+            status: row.status,
+        }
+    }
 }
 
 #[derive(Serialize, ToSchema)]
 pub enum StatusErrorResponse {
     NotFound,
+    ServerError,
 }
 
 impl IntoResponse for StatusOkResponse {
@@ -31,24 +99,58 @@ impl IntoResponse for StatusOkResponse {
 
 impl IntoResponse for StatusErrorResponse {
     fn into_response(self) -> Response {
-        (StatusCode::NOT_FOUND, Json(self)).into_response()
+        match self {
+            StatusErrorResponse::NotFound => (StatusCode::NOT_FOUND, Json(self)).into_response(),
+            StatusErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<DbError> for StatusErrorResponse {
+    fn from(err: DbError) -> Self {
+        tracing::error!(%err, "Failed to get task status");
+
+        StatusErrorResponse::ServerError
     }
 }
 
 /// Get the status of a task
+///
+/// With `wait_for_change`, the request is held until the version of the task is greater than `since`,
+/// the task finished or the wait expired. The current status is returned in every case.
 #[utoipa::path(
     get,
     path = "/api/status/{id}", 
     params(
         ("id" = String, Path, description = "Task id. generated using the `/api/download_zip_file` endpoint."),
-        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("wait_for_change" = Option<u64>, Query, description = "Seconds to wait for a status change. At most 30."),
+        ("since" = Option<u64>, Query, description = "Version the client already knows. Defaults to the current version.")
     ),
     tag = "task",
     responses(
-        (status = 200, description = "Status of a given task", body = StatusOkResponse, example = json!(StatusOkResponse{status: Status::Process(ProcessStatus::Running)})),
+        (status = 200, description = "Status of a given task", body = StatusOkResponse, example = json!({
+            "id": "0",
+            "kind": "process",
+            "job_type": "gs_log_to_locust_converter",
+            "project_name": "project",
+            "params": {"level": "info"},
+            "status": {"type": "Process", "content": {"status": "Running"}},
+            "phase": "running",
+            "version": 3,
+            "created_at": "2024-01-01T12:00:00Z",
+            "updated_at": "2024-01-01T12:00:02Z",
+            "started_at": "2024-01-01T12:00:01Z",
+            "duration_ms": 1500,
+            "progress": {"phase": "converting", "current": 42, "total": 100, "percent": 42.0, "eta_secs": 2},
+            "attempt": {"attempt": 1, "max_attempts": 3}
+        })),
         (status = 404, description = "Task not found for this chat id", body = StatusErrorResponse, example = json!(StatusErrorResponse::NotFound)),
         (status = 400, description = "Chat id missing. Api key missing."),
         (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = StatusErrorResponse, example = json!(StatusErrorResponse::ServerError)),
     ),
     security(
         ("api_key" = []),
@@ -58,11 +160,158 @@ pub async fn status(
     State(state): State<ApiState>,
     Path(id): Path<String>,
     ChatId(chat_id): ChatId,
+    Query(query): Query<StatusQuery>,
 ) -> Result<StatusOkResponse, StatusErrorResponse> {
-    let status = state
-        .task_status(&id, &chat_id)
-        .await
+    // Subscribed before reading the database, so that no change is missed in between
+    let events = state.subscribe_task_events();
+
+    let (mut task, mut attempts) = state
+        .task_with_attempts(&id, &chat_id)
+        .await?
         .ok_or(StatusErrorResponse::NotFound)?;
 
-    Ok(StatusOkResponse { status })
+    if let Some(wait) = query.wait_for_change {
+        let since = query.since.unwrap_or(task.version);
+        let wait = Duration::from_secs(wait.min(MAX_WAIT_FOR_CHANGE_SECS));
+
+        if task.version <= since && !task.status.is_terminal() {
+            // Either way, the status is read again below
+            let _ = tokio::time::timeout(wait, changed(events, &id, since)).await;
+
+            (task, attempts) = state
+                .task_with_attempts(&id, &chat_id)
+                .await?
+                .ok_or(StatusErrorResponse::NotFound)?;
+        }
+    }
+
+    let queue_position = task
+        .status
+        .is_queued()
+        .then(|| state.queue_position(&id))
+        .flatten();
+
+    let attempt = attempts.last().map(|last| AttemptInfo {
+        attempt: last.attempt,
+        max_attempts: task.max_attempts,
+    });
+
+    let progress = state.task_progress(&id).await;
+
+    Ok(StatusOkResponse {
+        task: TaskRecord::from(task),
+        progress,
+        queue_position,
+        attempt,
+        attempts: attempts.into_iter().map(AttemptRecord::from).collect(),
+    })
+}
+
+/// Returns once the task has a version greater than `since`, or might have one.
+async fn changed(mut events: broadcast::Receiver<Arc<TaskEvent>>, id: &str, since: u64) {
+    loop {
+        match events.recv().await {
+            Ok(event) if event.task_id == id => {
+                if event
+                    .transition()
+                    .is_some_and(|(_, transition)| transition.version > since)
+                {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            // The change may be among the skipped events
+            Err(RecvError::Lagged(_)) => return,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::task::ProcessStatus;
+    use std::time::Instant;
+
+    /// Persists and publishes the status like a running task does
+    async fn set_status(state: &ApiState, id: &str, status: Status) {
+        let event = state.persist_status(id, status).await;
+
+        state.events().publish(id, event);
+    }
+
+    /// The response as the client sees it
+    async fn long_poll(state: &ApiState, id: &str, since: Option<u64>) -> serde_json::Value {
+        let query = StatusQuery {
+            wait_for_change: Some(5),
+            since,
+        };
+
+        let Ok(response) = status(
+            State(state.clone()),
+            Path(id.to_string()),
+            ChatId(String::from("chat")),
+            Query(query),
+        )
+        .await
+        else {
+            panic!("Failed to get the status");
+        };
+
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn long_poll_waits_for_a_version_after_since() {
+        let state = ApiState::in_memory();
+        let id = state.db().insert_test_task("chat").await;
+        let other = state.db().insert_test_task("chat").await;
+
+        let changes = {
+            let (state, id) = (state.clone(), id.clone());
+
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+
+                // Does not wake up the request
+                set_status(&state, &other, Status::Process(ProcessStatus::Running)).await;
+
+                set_status(&state, &id, Status::Process(ProcessStatus::Running)).await;
+            })
+        };
+
+        let started = Instant::now();
+        let response = long_poll(&state, &id, None).await;
+
+        assert_eq!(response["version"], 2);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        changes.await.unwrap();
+
+        // The client missed the change, so there is nothing to wait for
+        let started = Instant::now();
+        let response = long_poll(&state, &id, Some(1)).await;
+
+        assert_eq!(response["version"], 2);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn long_poll_returns_at_once_for_finished_tasks() {
+        let state = ApiState::in_memory();
+        let id = state.db().insert_test_task("chat").await;
+
+        set_status(
+            &state,
+            &id,
+            Status::Process(ProcessStatus::Canceled { signal: None }),
+        )
+        .await;
+
+        let started = Instant::now();
+        let response = long_poll(&state, &id, None).await;
+
+        assert_eq!(response["phase"], "canceled");
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,119 @@
+//! Routes and responses for sending input to interactive tasks
+use crate::server::{
+    extractors::{chat_id::ChatId, json::Json as JsonBody},
+    state::ApiState,
+    task::{StdinError, StdinInput},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendStdinBody {
+    /// Written to stdin as is. Include a trailing newline to answer a prompt
+    #[serde(default)]
+    #[schema(example = "yes\n")]
+    data: Option<String>,
+    /// Closes stdin after writing `data`. No more input can be sent afterwards
+    #[serde(default)]
+    eof: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SendStdinOkResponse {
+    /// Task id the input was queued for
+    #[schema(example = "0")]
+    id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum SendStdinErrorResponse {
+    /// Task is not running or queued for this chat id
+    NotFound,
+    NotInteractive,
+    /// Too much input is waiting to be read. Try again later
+    Full,
+    /// Stdin was closed
+    Closed,
+}
+
+impl From<StdinError> for SendStdinErrorResponse {
+    fn from(err: StdinError) -> Self {
+        match err {
+            StdinError::NotInteractive => SendStdinErrorResponse::NotInteractive,
+            StdinError::Full => SendStdinErrorResponse::Full,
+            StdinError::Closed => SendStdinErrorResponse::Closed,
+        }
+    }
+}
+
+impl IntoResponse for SendStdinOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for SendStdinErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            SendStdinErrorResponse::NotFound => (StatusCode::NOT_FOUND, Json(self)).into_response(),
+            SendStdinErrorResponse::NotInteractive | SendStdinErrorResponse::Closed => {
+                (StatusCode::CONFLICT, Json(self)).into_response()
+            }
+            SendStdinErrorResponse::Full => {
+                (StatusCode::TOO_MANY_REQUESTS, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+/// Send input to the stdin of an interactive task
+///
+/// Input sent while the task is queued is written once the OS process starts.
+#[utoipa::path(
+    post,
+    path = "/api/tasks/{id}/stdin",
+    params(
+        ("id" = String, Path, description = "Task id."),
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    request_body = SendStdinBody,
+    tag = "task",
+    responses(
+        (status = 200, description = "Input was queued", body = SendStdinOkResponse, example = json!(SendStdinOkResponse{id: String::from("some-id")})),
+        (status = 404, description = "Task not running or queued for this chat id", body = SendStdinErrorResponse, example = json!(SendStdinErrorResponse::NotFound)),
+        (status = 409, description = "Task is not interactive or stdin was closed", body = SendStdinErrorResponse, example = json!(SendStdinErrorResponse::Closed)),
+        (status = 429, description = "Too much input is waiting to be read", body = SendStdinErrorResponse, example = json!(SendStdinErrorResponse::Full)),
+        (status = 400, description = "Chat id missing. Api key missing. Body invalid"),
+        (status = 401, description = "Api key invalid"),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn send_stdin(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ChatId(chat_id): ChatId,
+    JsonBody(body): JsonBody<SendStdinBody>,
+) -> Result<SendStdinOkResponse, SendStdinErrorResponse> {
+    let inputs = body
+        .data
+        .map(|data| StdinInput::Data(data.into_bytes()))
+        .into_iter()
+        .chain(body.eof.then_some(StdinInput::Eof));
+
+    for input in inputs {
+        state
+            .send_stdin(&id, &chat_id, input)
+            .await
+            .ok_or(SendStdinErrorResponse::NotFound)??;
+    }
+
+    Ok(SendStdinOkResponse { id })
+}
@@ -0,0 +1,402 @@
+//! Routes and responses for listing and bulk-querying the tasks of a chat
+use crate::server::{
+    db::{self, DbError, TaskFilter, TaskRow},
+    download::Checksum,
+    extractors::{chat_id::ChatId, json::Json as JsonBody, query::Query},
+    progress::Progress,
+    state::ApiState,
+    task::{FailureReason, Status, StatusName, TaskKind, TaskPhase},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Upper bound for `limit`
+const MAX_LIMIT: usize = 200;
+
+/// Upper bound for the ids of a bulk status request
+const MAX_IDS: usize = 100;
+
+/// A task as returned by the API. The same shape for every kind of task
+#[derive(Serialize, ToSchema)]
+pub struct TaskRecord {
+    #[schema(example = "0")]
+    id: String,
+    kind: TaskKind,
+    /// Missing for downloads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "gs_log_to_locust_converter")]
+    job_type: Option<String>,
+    project_name: String,
+    /// Parameters the task was submitted with
+    #[schema(value_type = Object)]
+    params: serde_json::Value,
+    /// Detailed status. Depends on the kind
+    status: Status,
+    phase: TaskPhase,
+    /// Only present if the phase is `failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure: Option<FailureReason>,
+    /// Incremented with every status change
+    #[schema(example = 3)]
+    version: u64,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: DateTime<Utc>,
+    #[schema(value_type = String, format = DateTime)]
+    updated_at: DateTime<Utc>,
+    /// When the first attempt started. Missing while the task did not start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    started_at: Option<DateTime<Utc>>,
+    /// Missing while the task is not finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    finished_at: Option<DateTime<Utc>>,
+    /// Milliseconds from the start until the end, or until now while the task is not finished.
+    /// Missing while the task did not start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 1500)]
+    duration_ms: Option<u64>,
+    /// Of the downloaded file. Only present for succeeded downloads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksum: Option<Checksum>,
+}
+
+impl From<TaskRow> for TaskRecord {
+    fn from(row: TaskRow) -> Self {
+        let duration_ms = row.started_at.map(|started_at| {
+            let end = row.finished_at.unwrap_or_else(Utc::now);
+
+            (end - started_at).num_milliseconds().max(0) as u64
+        });
+
+        Self {
+            id: row.id,
+            kind: row.kind,
+            job_type: row.job_type,
+            project_name: row.project_name,
+            params: row.params,
+            phase: row.status.phase(),
+            failure: row.status.failure(),
+            status: row.status,
+            version: row.version,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+            duration_ms,
+            checksum: row.checksum,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListTasksOkResponse {
+    /// Newest first
+    tasks: Vec<TaskRecord>,
+    /// Pass as `cursor` to get the next page. Missing on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "42")]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub enum ListTasksErrorResponse {
+    InvalidCursor,
+    ServerError,
+}
+
+impl IntoResponse for ListTasksOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for ListTasksErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            ListTasksErrorResponse::InvalidCursor => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            ListTasksErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<DbError> for ListTasksErrorResponse {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::InvalidId => ListTasksErrorResponse::InvalidCursor,
+            err => {
+                tracing::error!(%err, "Failed to list tasks");
+
+                ListTasksErrorResponse::ServerError
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListTasksQuery {
+    kind: Option<TaskKind>,
+    job_type: Option<String>,
+    status: Option<StatusName>,
+    finished: Option<bool>,
+    project_name: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    cursor: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// List the tasks of a chat
+///
+/// Newest first. Every given filter must match. Pages are continued with `next_cursor`.
+#[utoipa::path(
+    get,
+    path = "/api/tasks",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint."),
+        ("kind" = Option<TaskKind>, Query, description = "Only downloads or only processes."),
+        ("job_type" = Option<String>, Query, description = "Only tasks of this job type. See the `/api/jobs` endpoint."),
+        ("status" = Option<StatusName>, Query, description = "Only tasks with this status, e.g. `Running`."),
+        ("finished" = Option<bool>, Query, description = "Only finished or only unfinished tasks."),
+        ("project_name" = Option<String>, Query, description = "Only tasks of this project."),
+        ("created_after" = Option<String>, Query, description = "Only tasks created at or after this RFC 3339 timestamp."),
+        ("created_before" = Option<String>, Query, description = "Only tasks created before this RFC 3339 timestamp."),
+        ("cursor" = Option<String>, Query, description = "`next_cursor` of the previous page."),
+        ("limit" = Option<usize>, Query, description = "Tasks per page. Defaults to 50, at most 200."),
+    ),
+    tag = "task",
+    responses(
+        (status = 200, description = "Tasks of the chat", body = ListTasksOkResponse),
+        (status = 400, description = "Invalid cursor. Invalid query. Chat id missing. Api key missing.", body = ListTasksErrorResponse, example = json!(ListTasksErrorResponse::InvalidCursor)),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = ListTasksErrorResponse, example = json!(ListTasksErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn list_tasks(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+    Query(query): Query<ListTasksQuery>,
+) -> Result<ListTasksOkResponse, ListTasksErrorResponse> {
+    let limit = query.limit.clamp(1, MAX_LIMIT);
+
+    let filter = TaskFilter {
+        kind: query.kind,
+        job_type: query.job_type,
+        status: query.status,
+        finished: query.finished,
+        project_name: query.project_name,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        before_id: query.cursor,
+    };
+
+    // One more than requested, to tell whether there is a next page
+    let mut tasks = state.tasks(&chat_id, filter, limit + 1).await?;
+
+    let next_cursor = (tasks.len() > limit).then(|| {
+        tasks.truncate(limit);
+
+        tasks.last().map(|task| task.id.clone())
+    });
+
+    Ok(ListTasksOkResponse {
+        tasks: tasks.into_iter().map(TaskRecord::from).collect(),
+        next_cursor: next_cursor.flatten(),
+    })
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TasksStatusBody {
+    /// At most 100 task ids
+    #[schema(example = json!(["0", "1"]))]
+    ids: Vec<String>,
+}
+
+/// A [`TaskRecord`] with the live state of the task
+#[derive(Serialize, ToSchema)]
+pub struct TaskStatusInfo {
+    #[serde(flatten)]
+    task: TaskRecord,
+    /// Progress of the running attempt. Only present while the task runs and reports progress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+    /// 1-based position in the task queue. Only present while the task is queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2)]
+    queue_position: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TasksStatusOkResponse {
+    /// In the order of the requested ids. Repeated ids are listed once
+    tasks: Vec<TaskStatusInfo>,
+    /// Requested ids without a task in this chat
+    not_found: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum TasksStatusErrorResponse {
+    /// More ids than allowed. Contains the maximum
+    TooManyIds(usize),
+    ServerError,
+}
+
+impl IntoResponse for TasksStatusOkResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for TasksStatusErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            TasksStatusErrorResponse::TooManyIds(_) => {
+                (StatusCode::BAD_REQUEST, Json(self)).into_response()
+            }
+            TasksStatusErrorResponse::ServerError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+impl From<DbError> for TasksStatusErrorResponse {
+    fn from(err: DbError) -> Self {
+        tracing::error!(%err, "Failed to get task statuses");
+
+        TasksStatusErrorResponse::ServerError
+    }
+}
+
+/// Get the status of several tasks at once
+#[utoipa::path(
+    post,
+    path = "/api/tasks/status",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    request_body = TasksStatusBody,
+    tag = "task",
+    responses(
+        (status = 200, description = "Status of the given tasks", body = TasksStatusOkResponse, example = json!({
+            "tasks": [{
+                "id": "0",
+                "kind": "process",
+                "job_type": "gs_log_to_locust_converter",
+                "project_name": "project",
+                "params": {"level": "info"},
+                "status": {"type": "Process", "content": {"status": "Queued"}},
+                "phase": "queued",
+                "version": 2,
+                "created_at": "2024-01-01T12:00:00Z",
+                "updated_at": "2024-01-01T12:00:00Z",
+                "queue_position": 2
+            }],
+            "not_found": ["1"]
+        })),
+        (status = 400, description = "Too many ids. Chat id missing. Api key missing. Body invalid", body = TasksStatusErrorResponse, example = json!(TasksStatusErrorResponse::TooManyIds(MAX_IDS))),
+        (status = 401, description = "Api key invalid"),
+        (status = 500, description = "Internal server error", body = TasksStatusErrorResponse, example = json!(TasksStatusErrorResponse::ServerError)),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn tasks_status(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+    JsonBody(body): JsonBody<TasksStatusBody>,
+) -> Result<TasksStatusOkResponse, TasksStatusErrorResponse> {
+    if body.ids.len() > MAX_IDS {
+        return Err(TasksStatusErrorResponse::TooManyIds(MAX_IDS));
+    }
+
+    let mut found: HashMap<String, TaskRow> = state
+        .tasks_by_ids(&body.ids, &chat_id)
+        .await?
+        .into_iter()
+        .map(|task| (task.id.clone(), task))
+        .collect();
+
+    let mut tasks = Vec::new();
+    let mut not_found = Vec::new();
+    let mut seen = HashSet::new();
+
+    for id in body.ids {
+        // `01` and `1` are the same task
+        let key = db::parse_id(&id).map_or_else(|_| id.clone(), |id| id.to_string());
+
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+
+        let Some(task) = found.remove(&key) else {
+            not_found.push(id);
+            continue;
+        };
+
+        let queue_position = task
+            .status
+            .is_queued()
+            .then(|| state.queue_position(&task.id))
+            .flatten();
+
+        let progress = state.task_progress(&task.id).await;
+
+        tasks.push(TaskStatusInfo {
+            task: TaskRecord::from(task),
+            progress,
+            queue_position,
+        });
+    }
+
+    Ok(TasksStatusOkResponse { tasks, not_found })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn repeated_and_non_canonical_ids_are_found_once() {
+        let state = ApiState::in_memory();
+
+        let id = state.db().insert_test_task("chat").await;
+
+        let ids = [&id, &format!("0{id}"), &id, "nope", "nope"];
+        let body = TasksStatusBody {
+            ids: ids.map(ToString::to_string).to_vec(),
+        };
+
+        let Ok(response) =
+            tasks_status(State(state), ChatId(String::from("chat")), JsonBody(body)).await
+        else {
+            panic!("Failed to get the task statuses");
+        };
+
+        assert_eq!(response.tasks.len(), 1);
+        assert_eq!(response.tasks[0].task.id, id);
+        assert_eq!(response.not_found, ["nope"]);
+    }
+}
@@ -0,0 +1,357 @@
+//! Live status changes and output of tasks over a WebSocket
+use crate::server::{
+    events::{TaskEvent, TaskEventKind},
+    extractors::chat_id::ChatId,
+    output::OutputStream,
+    state::ApiState,
+    task::StdinInput,
+    ws::{ClientMessage, ServerMessage, TaskIoChunk, TaskStatus, WsError},
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use std::collections::HashMap;
+use tokio::sync::broadcast::error::RecvError;
+
+/// What a connection already sent for a subscribed task
+#[derive(Default)]
+struct Subscription {
+    version: u64,
+    stdout_lines: u64,
+    stderr_lines: u64,
+}
+
+impl Subscription {
+    fn lines(&mut self, stream: OutputStream) -> &mut u64 {
+        match stream {
+            OutputStream::Stdout => &mut self.stdout_lines,
+            OutputStream::Stderr => &mut self.stderr_lines,
+        }
+    }
+}
+
+/// Stream status changes and output of tasks
+///
+/// Send `{"client_message": "Subscribe", "content": {"ids": ["1"]}}` to subscribe to tasks of the chat.
+/// The current status and the output so far are sent first, followed by every change as it happens.
+#[utoipa::path(
+    get,
+    path = "/api/ws",
+    params(
+        ("chat_id" = String, Query, description = "Chat id. generated using the `/api/request_chat_id` endpoint.")
+    ),
+    tag = "task",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 400, description = "Chat id missing. Api key missing. Not a WebSocket upgrade."),
+        (status = 401, description = "Api key invalid"),
+    ),
+    security(
+        ("api_key" = []),
+    ),
+)]
+pub async fn ws(
+    State(state): State<ApiState>,
+    ChatId(chat_id): ChatId,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, chat_id, socket))
+}
+
+#[tracing::instrument(skip_all, fields(chat_id=%chat_id))]
+async fn handle_socket(state: ApiState, chat_id: String, mut socket: WebSocket) {
+    // Subscribed before any output is replayed, so that nothing is missed in between
+    let mut events = state.subscribe_task_events();
+    let mut subscriptions: HashMap<String, Subscription> = HashMap::new();
+
+    loop {
+        let messages = tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        tracing::debug!(%err, "WebSocket error");
+                        break;
+                    }
+                };
+
+                match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe { ids }) => {
+                        let mut messages = Vec::new();
+
+                        for id in ids {
+                            let replies = subscribe(&state, &chat_id, id, &mut subscriptions).await;
+                            messages.extend(replies);
+                        }
+
+                        messages
+                    }
+                    Ok(ClientMessage::Unsubscribe { ids }) => {
+                        for id in ids.iter() {
+                            subscriptions.remove(id);
+                        }
+
+                        continue;
+                    }
+                    Ok(ClientMessage::Stdin { id, data }) => {
+                        let input = StdinInput::Data(data.into_bytes());
+
+                        match send_stdin(&state, &chat_id, id, input).await {
+                            Some(message) => vec![message],
+                            None => continue,
+                        }
+                    }
+                    Ok(ClientMessage::StdinEof { id }) => {
+                        match send_stdin(&state, &chat_id, id, StdinInput::Eof).await {
+                            Some(message) => vec![message],
+                            None => continue,
+                        }
+                    }
+                    Err(err) => {
+                        vec![ServerMessage::Error(WsError::InvalidMessage(err.to_string()))]
+                    }
+                }
+            },
+            event = events.recv() => {
+                match event {
+                    Ok(event) => match forward(&event, &mut subscriptions) {
+                        Some(message) => vec![message],
+                        None => continue,
+                    },
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(%skipped, "WebSocket lagged behind");
+
+                        vec![ServerMessage::Error(WsError::Lagged { skipped })]
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+
+        for message in messages {
+            if send(&mut socket, &message).await.is_err() {
+                tracing::debug!("Failed to send message. Client is probably gone");
+                return;
+            }
+        }
+    }
+
+    tracing::debug!("WebSocket closed");
+}
+
+/// Sends the status and the output the connection has not seen yet.
+/// Subscribing again to a task catches up after lagging behind.
+async fn subscribe(
+    state: &ApiState,
+    chat_id: &str,
+    id: String,
+    subscriptions: &mut HashMap<String, Subscription>,
+) -> Vec<ServerMessage> {
+    let task = match state.task(&id, chat_id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return vec![ServerMessage::Error(WsError::TaskNotFound { id })],
+        Err(err) => {
+            tracing::error!(%err, "Failed to get task");
+            return vec![ServerMessage::Error(WsError::ServerError)];
+        }
+    };
+
+    let lines = match state.task_output(&id, chat_id, None, None).await {
+        Ok(lines) => lines.unwrap_or_default(),
+        Err(err) => {
+            tracing::error!(%err, "Failed to read task output");
+            return vec![ServerMessage::Error(WsError::ServerError)];
+        }
+    };
+
+    let subscription = subscriptions.entry(id.clone()).or_default();
+    let mut messages = Vec::new();
+
+    if task.version > subscription.version {
+        subscription.version = task.version;
+
+        messages.push(ServerMessage::TaskStatus(TaskStatus {
+            id: id.clone(),
+            status: task.status,
+            version: task.version,
+        }));
+    }
+
+    let mut line_numbers = Subscription::default();
+
+    for line in lines {
+        let line_number = line_numbers.lines(line.stream);
+        *line_number += 1;
+
+        let sent = subscription.lines(line.stream);
+
+        if *line_number > *sent {
+            *sent = *line_number;
+
+            messages.push(ServerMessage::TaskIoChunk(TaskIoChunk {
+                id: id.clone(),
+                chunk: format!("{}\n", line.line),
+                io_type: line.stream.into(),
+            }));
+        }
+    }
+
+    messages
+}
+
+/// An error message if the input was rejected.
+async fn send_stdin(
+    state: &ApiState,
+    chat_id: &str,
+    id: String,
+    input: StdinInput,
+) -> Option<ServerMessage> {
+    match state.send_stdin(&id, chat_id, input).await {
+        Some(Ok(())) => None,
+        Some(Err(error)) => Some(ServerMessage::Error(WsError::Stdin { id, error })),
+        None => Some(ServerMessage::Error(WsError::TaskNotFound { id })),
+    }
+}
+
+/// The message for an event of a subscribed task, unless the connection has already seen it.
+fn forward(
+    event: &TaskEvent,
+    subscriptions: &mut HashMap<String, Subscription>,
+) -> Option<ServerMessage> {
+    let subscription = subscriptions.get_mut(&event.task_id)?;
+
+    match &event.kind {
+        TaskEventKind::Status {
+            status, version, ..
+        } => {
+            if *version <= subscription.version {
+                return None;
+            }
+
+            subscription.version = *version;
+
+            Some(ServerMessage::TaskStatus(TaskStatus {
+                id: event.task_id.clone(),
+                status: status.clone(),
+                version: *version,
+            }))
+        }
+        TaskEventKind::Output {
+            stream,
+            line_number,
+            line,
+        } => {
+            let sent = subscription.lines(*stream);
+
+            if *line_number <= *sent {
+                return None;
+            }
+
+            *sent = *line_number;
+
+            Some(ServerMessage::TaskIoChunk(TaskIoChunk {
+                id: event.task_id.clone(),
+                chunk: format!("{line}\n"),
+                io_type: (*stream).into(),
+            }))
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).expect("Server messages are serializable");
+
+    socket.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::task::{ProcessStatus, Status};
+
+    fn output(stream: OutputStream, line_number: u64, line: &str) -> TaskEventKind {
+        TaskEventKind::Output {
+            stream,
+            line_number,
+            line: line.to_string(),
+        }
+    }
+
+    fn event(task_id: &str, kind: TaskEventKind) -> TaskEvent {
+        TaskEvent {
+            task_id: task_id.to_string(),
+            kind,
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_replays_then_forwards_only_what_was_not_sent() {
+        let state = ApiState::in_memory();
+        let id = state.db().insert_test_task("chat").await;
+
+        let mut stdout = state
+            .output()
+            .writer(&id, OutputStream::Stdout)
+            .await
+            .unwrap();
+        stdout.write_line("first").await.unwrap();
+
+        let mut subscriptions = HashMap::new();
+        let replayed = subscribe(&state, "chat", id.clone(), &mut subscriptions).await;
+
+        assert!(matches!(
+            replayed.as_slice(),
+            [
+                ServerMessage::TaskStatus(TaskStatus { version: 1, .. }),
+                ServerMessage::TaskIoChunk(TaskIoChunk { chunk, .. }),
+            ] if chunk == "first\n"
+        ));
+
+        // Published while the output was replayed
+        let first = event(&id, output(OutputStream::Stdout, 1, "first"));
+        assert!(forward(&first, &mut subscriptions).is_none());
+
+        let second = event(&id, output(OutputStream::Stdout, 2, "second"));
+        assert!(forward(&second, &mut subscriptions).is_some());
+        assert!(forward(&second, &mut subscriptions).is_none());
+
+        let stderr = event(&id, output(OutputStream::Stderr, 1, "error"));
+        assert!(forward(&stderr, &mut subscriptions).is_some());
+
+        let running = event(
+            &id,
+            TaskEventKind::Status {
+                status: Status::Process(ProcessStatus::Running),
+                chat_id: String::from("chat"),
+                seq: 2,
+                version: 2,
+                at: chrono::Utc::now(),
+            },
+        );
+        assert!(forward(&running, &mut subscriptions).is_some());
+        assert!(forward(&running, &mut subscriptions).is_none());
+
+        // Subscribing again only catches up
+        assert!(subscribe(&state, "chat", id.clone(), &mut subscriptions)
+            .await
+            .is_empty());
+
+        let other = event("other", output(OutputStream::Stdout, 1, "other"));
+        assert!(forward(&other, &mut subscriptions).is_none());
+
+        assert!(matches!(
+            subscribe(&state, "other chat", id.clone(), &mut subscriptions)
+                .await
+                .as_slice(),
+            [ServerMessage::Error(WsError::TaskNotFound { .. })]
+        ));
+
+        state.output().delete(&id).await.unwrap();
+    }
+}
@@ -0,0 +1,1654 @@
+use super::{
+    download::Checksum,
+    history::{EventSource, HistoryEvent},
+    pipeline::PipelineStatus,
+    scheduler::Priority,
+    task::{Status, StatusName, TaskKind},
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, ToSql};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Schema migrations. Applied in order, tracked with `PRAGMA user_version`.
+///
+/// Never edit an existing migration, append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE tasks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        project_name TEXT NOT NULL,
+        params TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        finished_at TEXT
+    );
+
+    CREATE INDEX tasks_chat_id ON tasks (chat_id);
+
+    CREATE TABLE task_status_transitions (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+        status TEXT NOT NULL,
+        at TEXT NOT NULL
+    );
+
+    CREATE INDEX task_status_transitions_task_id ON task_status_transitions (task_id);
+    "#,
+    r#"
+    ALTER TABLE tasks ADD COLUMN job_type TEXT;
+    "#,
+    r#"
+    CREATE TABLE pipelines (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        finished_at TEXT
+    );
+
+    -- No foreign key on task_id. Tasks and pipelines expire independently
+    CREATE TABLE pipeline_steps (
+        pipeline_id INTEGER NOT NULL REFERENCES pipelines (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        task_id INTEGER NOT NULL,
+        depends_on TEXT NOT NULL,
+        skipped INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (pipeline_id, position)
+    );
+    "#,
+    r#"
+    ALTER TABLE tasks ADD COLUMN max_attempts INTEGER NOT NULL DEFAULT 1;
+
+    CREATE TABLE task_attempts (
+        task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+        attempt INTEGER NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT,
+        status TEXT,
+        PRIMARY KEY (task_id, attempt)
+    );
+    "#,
+    r#"
+    -- No foreign key on last_task_id. Tasks expire independently
+    CREATE TABLE schedules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id TEXT NOT NULL,
+        cron TEXT NOT NULL,
+        job_type TEXT NOT NULL,
+        project_name TEXT NOT NULL,
+        params TEXT NOT NULL,
+        priority TEXT,
+        paused INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT NOT NULL,
+        active_since TEXT NOT NULL,
+        last_run_at TEXT,
+        last_task_id INTEGER,
+        last_error TEXT
+    );
+
+    CREATE INDEX schedules_chat_id ON schedules (chat_id);
+    "#,
+    // Canceled and Timeout of processes record the signal that ended the OS process.
+    // Before, OS processes were always killed with SIGKILL
+    r#"
+    UPDATE tasks SET status = replace(replace(status,
+        '{"type":"Process","content":{"status":"Canceled"}}',
+        '{"type":"Process","content":{"status":"Canceled","content":{"signal":"Sigkill"}}}'),
+        '{"type":"Process","content":{"status":"Timeout"}}',
+        '{"type":"Process","content":{"status":"Timeout","content":{"signal":"Sigkill"}}}');
+
+    UPDATE task_status_transitions SET status = replace(replace(status,
+        '{"type":"Process","content":{"status":"Canceled"}}',
+        '{"type":"Process","content":{"status":"Canceled","content":{"signal":"Sigkill"}}}'),
+        '{"type":"Process","content":{"status":"Timeout"}}',
+        '{"type":"Process","content":{"status":"Timeout","content":{"signal":"Sigkill"}}}');
+
+    UPDATE task_attempts SET status = replace(replace(status,
+        '{"type":"Process","content":{"status":"Canceled"}}',
+        '{"type":"Process","content":{"status":"Canceled","content":{"signal":"Sigkill"}}}'),
+        '{"type":"Process","content":{"status":"Timeout"}}',
+        '{"type":"Process","content":{"status":"Timeout","content":{"signal":"Sigkill"}}}')
+    WHERE status IS NOT NULL;
+    "#,
+    // Incremented with every status change, so that clients can tell whether they missed one
+    r#"
+    ALTER TABLE tasks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+    UPDATE tasks SET version = (
+        SELECT COUNT(*) FROM task_status_transitions WHERE task_id = tasks.id
+    );
+    "#,
+    // Tasks remember when their first attempt started.
+    // Failed downloads carry a code and a message instead of only a message
+    r#"
+    ALTER TABLE tasks ADD COLUMN started_at TEXT;
+
+    UPDATE tasks SET started_at = (
+        SELECT MIN(started_at) FROM task_attempts WHERE task_id = tasks.id
+    );
+
+    UPDATE tasks SET status = json_set(status, '$.content.content.reason', json_object(
+        'code', CASE json_extract(status, '$.content.content.reason')
+            WHEN 'Interrupted by a server restart' THEN 'interrupted'
+            ELSE 'download'
+        END,
+        'message', json_extract(status, '$.content.content.reason')))
+    WHERE json_extract(status, '$.type') = 'Download'
+        AND json_extract(status, '$.content.status') = 'Failed';
+
+    UPDATE task_status_transitions SET status = json_set(status, '$.content.content.reason', json_object(
+        'code', CASE json_extract(status, '$.content.content.reason')
+            WHEN 'Interrupted by a server restart' THEN 'interrupted'
+            ELSE 'download'
+        END,
+        'message', json_extract(status, '$.content.content.reason')))
+    WHERE json_extract(status, '$.type') = 'Download'
+        AND json_extract(status, '$.content.status') = 'Failed';
+
+    UPDATE task_attempts SET status = json_set(status, '$.content.content.reason', json_object(
+        'code', CASE json_extract(status, '$.content.content.reason')
+            WHEN 'Interrupted by a server restart' THEN 'interrupted'
+            ELSE 'download'
+        END,
+        'message', json_extract(status, '$.content.content.reason')))
+    WHERE json_extract(status, '$.type') = 'Download'
+        AND json_extract(status, '$.content.status') = 'Failed';
+    "#,
+    // History of every task. Tasks that existed before keep their status transitions
+    r#"
+    CREATE TABLE task_events (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+        at TEXT NOT NULL,
+        source TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+
+    CREATE INDEX task_events_task_id ON task_events (task_id);
+
+    INSERT INTO task_events (task_id, at, source, event)
+    SELECT task_id, at, '"system"', json_object('event', 'StatusChanged', 'content', json_object('status', json(status)))
+    FROM task_status_transitions ORDER BY seq;
+    "#,
+    // Downloads store the checksum of the downloaded file
+    r#"
+    ALTER TABLE tasks ADD COLUMN checksum TEXT;
+    "#,
+];
+
+/// Columns selected for a [`ScheduleRow`], in the order [`RawScheduleRow::from_row`] reads them.
+const SCHEDULE_COLUMNS: &str = "id, chat_id, cron, job_type, project_name, params, priority, \
+    paused, created_at, active_since, last_run_at, last_task_id, last_error";
+
+/// Columns selected for a [`TaskRow`], in the order [`RawTaskRow::from_row`] reads them.
+const TASK_COLUMNS: &str = "id, chat_id, kind, job_type, project_name, params, status, \
+    created_at, updated_at, finished_at, max_attempts, version, started_at, checksum";
+
+/// How long finished tasks are kept in the database.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Finished tasks older than this are removed. [`None`] keeps them forever.
+    pub max_age: Option<Duration>,
+    /// How often the policy is applied.
+    pub interval: Duration,
+}
+
+/// Which tasks of a chat to list. Every given field must match.
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub kind: Option<TaskKind>,
+    pub job_type: Option<String>,
+    pub status: Option<StatusName>,
+    /// Only finished or only unfinished tasks
+    pub finished: Option<bool>,
+    pub project_name: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only tasks with a smaller id, i.e. created earlier
+    pub before_id: Option<String>,
+}
+
+/// A new task to be inserted into the database.
+pub struct NewTask<'a> {
+    pub chat_id: &'a str,
+    pub kind: TaskKind,
+    /// Only set for tasks running a job from the [`crate::server::jobs::JobRegistry`]
+    pub job_type: Option<&'a str>,
+    pub project_name: &'a str,
+    pub params: serde_json::Value,
+    pub status: &'a Status,
+    /// From the retry policy of the task
+    pub max_attempts: u32,
+}
+
+/// A task as stored in the database.
+#[derive(Debug, Clone)]
+pub struct TaskRow {
+    pub id: String,
+    pub chat_id: String,
+    pub kind: TaskKind,
+    pub job_type: Option<String>,
+    pub project_name: String,
+    pub params: serde_json::Value,
+    pub status: Status,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub max_attempts: u32,
+    /// Starts at 1 and is incremented with every status change
+    pub version: u64,
+    /// When the first attempt started. Missing if the task never started
+    pub started_at: Option<DateTime<Utc>>,
+    /// Of the downloaded file. Only set for succeeded downloads
+    pub checksum: Option<Checksum>,
+}
+
+/// A single run of a task. Tasks are run again if their retry policy allows it.
+#[derive(Debug, Clone)]
+pub struct AttemptRow {
+    /// Counted from 1
+    pub attempt: u32,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Final status of the attempt. [`None`] while the attempt runs
+    pub status: Option<Status>,
+}
+
+/// A persisted status change of a task.
+#[derive(Debug, Clone)]
+pub struct TransitionRow {
+    pub task_id: String,
+    /// Increases with every transition of any task
+    pub seq: u64,
+    /// Version of the task after the transition. See [`TaskRow::version`]
+    pub version: u64,
+    pub status: Status,
+    pub at: DateTime<Utc>,
+}
+
+/// Where a status change was persisted. Returned by [`Database::update_task_status`].
+#[derive(Debug, Clone)]
+pub struct StatusUpdate {
+    pub chat_id: String,
+    pub seq: u64,
+    pub version: u64,
+    pub at: DateTime<Utc>,
+}
+
+/// An entry of the history of a task. See [`crate::server::history`]
+#[derive(Debug, Clone)]
+pub struct HistoryRow {
+    /// Increases across all tasks
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub source: EventSource,
+    pub event: HistoryEvent,
+}
+
+/// A new schedule to be inserted into the database.
+pub struct NewSchedule<'a> {
+    pub chat_id: &'a str,
+    /// Must be a valid [`crate::server::schedule::CronExpression`]
+    pub cron: &'a str,
+    pub job_type: &'a str,
+    pub project_name: &'a str,
+    pub params: &'a HashMap<String, String>,
+    /// [`None`] uses the priority of the job type
+    pub priority: Option<Priority>,
+}
+
+/// A schedule as stored in the database.
+#[derive(Debug, Clone)]
+pub struct ScheduleRow {
+    pub id: String,
+    pub chat_id: String,
+    pub cron: String,
+    pub job_type: String,
+    pub project_name: String,
+    pub params: HashMap<String, String>,
+    pub priority: Option<Priority>,
+    pub paused: bool,
+    pub created_at: DateTime<Utc>,
+    /// When the schedule was created or last resumed
+    pub active_since: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// [`None`] if the schedule never ran or its last run failed to create a task
+    pub last_task_id: Option<String>,
+    /// Why the last run failed to create a task
+    pub last_error: Option<String>,
+}
+
+/// A step of a new pipeline. The task of the step must already exist.
+pub struct NewPipelineStep<'a> {
+    pub name: &'a str,
+    pub task_id: &'a str,
+    pub depends_on: &'a [String],
+}
+
+/// A pipeline and its steps as stored in the database.
+#[derive(Debug, Clone)]
+pub struct PipelineRow {
+    pub id: String,
+    pub chat_id: String,
+    pub status: PipelineStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// In the order they were submitted
+    pub steps: Vec<PipelineStepRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineStepRow {
+    pub name: String,
+    pub task_id: String,
+    pub depends_on: Vec<String>,
+    /// Never ran because an upstream step did not succeed or the pipeline was canceled
+    pub skipped: bool,
+    /// [`None`] if the task was already deleted by the retention policy
+    pub status: Option<Status>,
+}
+
+/// SQLite backed storage.
+///
+/// [`rusqlite::Connection`] is blocking, so every call is moved to [`tokio::task::spawn_blocking`].
+/// A single connection behind a [`Mutex`] is plenty for the load we have.
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, DbError> {
+        let path = path.as_ref().to_path_buf();
+
+        let conn = tokio::task::spawn_blocking(move || {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).map_err(DbError::Io)?;
+            }
+
+            let conn = Connection::open(&path).map_err(DbError::Sqlite)?;
+
+            Self::init(conn)
+        })
+        .await
+        .map_err(|_| DbError::BlockingTask)??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Opens a fresh in-memory database. Everything is lost on drop.
+    pub fn open_in_memory() -> Result<Self, DbError> {
+        let conn = Connection::open_in_memory().map_err(DbError::Sqlite)?;
+        let conn = Self::init(conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn init(mut conn: Connection) -> Result<Connection, DbError> {
+        conn.pragma_update(None, "foreign_keys", "ON")
+            .map_err(DbError::Sqlite)?;
+        // Not supported for in-memory databases, sqlite will just keep its memory journal
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(DbError::Sqlite)?;
+
+        Self::migrate(&mut conn).map_err(DbError::Sqlite)?;
+
+        Ok(conn)
+    }
+
+    fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            tracing::info!(version = i + 1, "Applying database migration");
+
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool.
+    async fn call<F, T>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().map_err(|_| DbError::Poisoned)?;
+
+            f(&mut conn)
+        })
+        .await
+        .map_err(|_| DbError::BlockingTask)?
+    }
+
+    /// Inserts a task and its initial status. Returns the generated task id.
+    pub async fn insert_task(&self, task: NewTask<'_>) -> Result<String, DbError> {
+        let chat_id = task.chat_id.to_string();
+        let kind = serde_json::to_string(&task.kind).map_err(DbError::Json)?;
+        let job_type = task.job_type.map(ToString::to_string);
+        let project_name = task.project_name.to_string();
+        let params = task.params.to_string();
+        let status = serde_json::to_string(task.status).map_err(DbError::Json)?;
+        let event = status_changed(task.status)?;
+        let max_attempts = task.max_attempts;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+            let tx = conn.transaction().map_err(DbError::Sqlite)?;
+
+            tx.execute(
+                "INSERT INTO tasks (chat_id, kind, job_type, project_name, params, status, created_at, updated_at, max_attempts)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7, ?8)",
+                params![chat_id, kind, job_type, project_name, params, status, now, max_attempts],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            let id = tx.last_insert_rowid();
+
+            tx.execute(
+                "INSERT INTO task_status_transitions (task_id, status, at) VALUES (?1, ?2, ?3)",
+                params![id, status, now],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            insert_history(&tx, id, now, EventSource::System, &event)?;
+
+            tx.commit().map_err(DbError::Sqlite)?;
+
+            Ok(id.to_string())
+        })
+        .await
+    }
+
+    /// A created process task of the chat. Returns the generated task id.
+    #[cfg(test)]
+    pub async fn insert_test_task(&self, chat_id: &str) -> String {
+        self.insert_task(NewTask {
+            chat_id,
+            kind: TaskKind::Process,
+            job_type: Some("job"),
+            project_name: "project",
+            params: serde_json::json!({}),
+            status: &Status::created(TaskKind::Process),
+            max_attempts: 1,
+        })
+        .await
+        .expect("Failed to insert task")
+    }
+
+    /// Stores the new status of a task and records the transition in its history.
+    pub async fn update_task_status(
+        &self,
+        id: &str,
+        status: &Status,
+        source: EventSource,
+    ) -> Result<StatusUpdate, DbError> {
+        let id = parse_id(id)?;
+        let finished = status.is_terminal();
+        let event = status_changed(status)?;
+        let status = serde_json::to_string(status).map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+            let finished_at = finished.then_some(now);
+            let tx = conn.transaction().map_err(DbError::Sqlite)?;
+
+            let (chat_id, version) = tx
+                .query_row(
+                    "UPDATE tasks SET status = ?2, updated_at = ?3, finished_at = ?4, version = version + 1
+                     WHERE id = ?1 RETURNING chat_id, version",
+                    params![id, status, now, finished_at],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(DbError::Sqlite)?;
+
+            tx.execute(
+                "INSERT INTO task_status_transitions (task_id, status, at) VALUES (?1, ?2, ?3)",
+                params![id, status, now],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            let seq = tx.last_insert_rowid() as u64;
+
+            insert_history(&tx, id, now, source, &event)?;
+
+            tx.commit().map_err(DbError::Sqlite)?;
+
+            Ok(StatusUpdate {
+                chat_id,
+                seq,
+                version,
+                at: now,
+            })
+        })
+        .await
+    }
+
+    /// Returns the task with the given id if it belongs to the given chat.
+    pub async fn task(&self, id: &str, chat_id: &str) -> Result<Option<TaskRow>, DbError> {
+        let Ok(id) = parse_id(id) else {
+            return Ok(None);
+        };
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let row = conn
+                .query_row(
+                    &format!("SELECT {TASK_COLUMNS} FROM tasks WHERE id = ?1 AND chat_id = ?2"),
+                    params![id, chat_id],
+                    RawTaskRow::from_row,
+                )
+                .optional()
+                .map_err(DbError::Sqlite)?;
+
+            row.map(RawTaskRow::parse).transpose()
+        })
+        .await
+    }
+
+    /// Tasks of a chat matching the filter, newest first. At most `limit` tasks.
+    pub async fn tasks(
+        &self,
+        chat_id: &str,
+        filter: TaskFilter,
+        limit: usize,
+    ) -> Result<Vec<TaskRow>, DbError> {
+        let mut conditions = vec!["chat_id = ?"];
+        let mut values: Vec<Box<dyn ToSql + Send>> = vec![Box::new(chat_id.to_string())];
+
+        if let Some(kind) = filter.kind {
+            conditions.push("kind = ?");
+            values.push(Box::new(
+                serde_json::to_string(&kind).map_err(DbError::Json)?,
+            ));
+        }
+
+        if let Some(job_type) = filter.job_type {
+            conditions.push("job_type = ?");
+            values.push(Box::new(job_type));
+        }
+
+        if let Some(status) = filter.status {
+            conditions.push("json_extract(status, '$.content.status') = ?");
+            values.push(Box::new(status.as_str()));
+        }
+
+        match filter.finished {
+            Some(true) => conditions.push("finished_at IS NOT NULL"),
+            Some(false) => conditions.push("finished_at IS NULL"),
+            None => {}
+        }
+
+        if let Some(project_name) = filter.project_name {
+            conditions.push("project_name = ?");
+            values.push(Box::new(project_name));
+        }
+
+        // Timestamps are stored as text in UTC, which sorts chronologically
+        if let Some(created_after) = filter.created_after {
+            conditions.push("created_at >= ?");
+            values.push(Box::new(created_after));
+        }
+
+        if let Some(created_before) = filter.created_before {
+            conditions.push("created_at < ?");
+            values.push(Box::new(created_before));
+        }
+
+        if let Some(before_id) = filter.before_id {
+            conditions.push("id < ?");
+            values.push(Box::new(parse_id(&before_id)?));
+        }
+
+        values.push(Box::new(limit as i64));
+
+        let sql = format!(
+            "SELECT {TASK_COLUMNS} FROM tasks WHERE {} ORDER BY id DESC LIMIT ?",
+            conditions.join(" AND ")
+        );
+
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(&sql).map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params_from_iter(values), RawTaskRow::from_row)
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter().map(RawTaskRow::parse).collect()
+        })
+        .await
+    }
+
+    /// The tasks with the given ids that belong to the given chat, in no particular order.
+    /// Invalid and unknown ids are ignored.
+    pub async fn tasks_by_ids(
+        &self,
+        ids: &[String],
+        chat_id: &str,
+    ) -> Result<Vec<TaskRow>, DbError> {
+        let mut values = vec![Value::Text(chat_id.to_string())];
+        values.extend(
+            ids.iter()
+                .filter_map(|id| parse_id(id).ok())
+                .map(Value::Integer),
+        );
+
+        if values.len() == 1 {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; values.len() - 1].join(", ");
+        let sql = format!(
+            "SELECT {TASK_COLUMNS} FROM tasks WHERE chat_id = ? AND id IN ({placeholders})"
+        );
+
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(&sql).map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params_from_iter(values), RawTaskRow::from_row)
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter().map(RawTaskRow::parse).collect()
+        })
+        .await
+    }
+
+    /// Status changes of a task after the given version, oldest first.
+    pub async fn task_transitions_after(
+        &self,
+        id: &str,
+        version: u64,
+    ) -> Result<Vec<TransitionRow>, DbError> {
+        let id = parse_id(id)?;
+
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT seq, task_id, status, at, version FROM (
+                         SELECT seq, task_id, status, at, ROW_NUMBER() OVER (ORDER BY seq) AS version
+                         FROM task_status_transitions
+                         WHERE task_id = ?1
+                     )
+                     WHERE version > ?2 ORDER BY seq",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params![id, version], RawTransitionRow::from_row)
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter().map(RawTransitionRow::parse).collect()
+        })
+        .await
+    }
+
+    /// The [`TransitionRow::seq`] of the latest status change of a chat. 0 if there is none.
+    pub async fn latest_chat_seq(&self, chat_id: &str) -> Result<u64, DbError> {
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT COALESCE(MAX(transitions.seq), 0)
+                 FROM task_status_transitions AS transitions
+                 JOIN tasks ON tasks.id = transitions.task_id
+                 WHERE tasks.chat_id = ?1",
+                params![chat_id],
+                |row| row.get(0),
+            )
+            .map_err(DbError::Sqlite)
+        })
+        .await
+    }
+
+    /// Status changes of all tasks of a chat after the given [`TransitionRow::seq`], oldest first.
+    pub async fn chat_transitions_after(
+        &self,
+        chat_id: &str,
+        seq: u64,
+    ) -> Result<Vec<TransitionRow>, DbError> {
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT seq, task_id, status, at, version FROM (
+                         SELECT transitions.seq, transitions.task_id, transitions.status, transitions.at,
+                             ROW_NUMBER() OVER (PARTITION BY transitions.task_id ORDER BY transitions.seq) AS version
+                         FROM task_status_transitions AS transitions
+                         JOIN tasks ON tasks.id = transitions.task_id
+                         WHERE tasks.chat_id = ?1
+                     )
+                     WHERE seq > ?2 ORDER BY seq",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params![chat_id, seq], RawTransitionRow::from_row)
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter().map(RawTransitionRow::parse).collect()
+        })
+        .await
+    }
+
+    pub async fn set_task_checksum(&self, id: &str, checksum: &Checksum) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+        let checksum = serde_json::to_string(checksum).map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE tasks SET checksum = ?2 WHERE id = ?1",
+                params![id, checksum],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records the start of an attempt of a task. The first attempt also starts the task.
+    pub async fn start_task_attempt(&self, id: &str, attempt: u32) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+            let tx = conn.transaction().map_err(DbError::Sqlite)?;
+
+            tx.execute(
+                "INSERT INTO task_attempts (task_id, attempt, started_at) VALUES (?1, ?2, ?3)",
+                params![id, attempt, now],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            tx.execute(
+                "UPDATE tasks SET started_at = ?2 WHERE id = ?1 AND started_at IS NULL",
+                params![id, now],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            tx.commit().map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records the final status of an attempt of a task.
+    pub async fn finish_task_attempt(
+        &self,
+        id: &str,
+        attempt: u32,
+        status: &Status,
+    ) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+        let status = serde_json::to_string(status).map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE task_attempts SET finished_at = ?3, status = ?4 WHERE task_id = ?1 AND attempt = ?2",
+                params![id, attempt, Utc::now(), status],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Appends an entry to the history of a task.
+    pub async fn append_task_event(
+        &self,
+        id: &str,
+        source: EventSource,
+        event: &HistoryEvent,
+    ) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+        let event = serde_json::to_string(event).map_err(DbError::Json)?;
+
+        self.call(move |conn| insert_history(conn, id, Utc::now(), source, &event))
+            .await
+    }
+
+    /// History of a task, oldest first. The task must be checked for the chat beforehand.
+    pub async fn task_history(&self, id: &str) -> Result<Vec<HistoryRow>, DbError> {
+        let id = parse_id(id)?;
+
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT seq, at, source, event FROM task_events WHERE task_id = ?1 ORDER BY seq",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params![id], |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, DateTime<Utc>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                })
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter()
+                .map(|(seq, at, source, event)| {
+                    Ok(HistoryRow {
+                        seq,
+                        at,
+                        source: serde_json::from_str(&source).map_err(DbError::Json)?,
+                        event: serde_json::from_str(&event).map_err(DbError::Json)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Attempts of a task, oldest first. The task must be checked for the chat beforehand.
+    pub async fn task_attempts(&self, id: &str) -> Result<Vec<AttemptRow>, DbError> {
+        let id = parse_id(id)?;
+
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT attempt, started_at, finished_at, status FROM task_attempts
+                     WHERE task_id = ?1 ORDER BY attempt",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params![id], |row| {
+                    Ok((
+                        row.get::<_, u32>(0)?,
+                        row.get::<_, DateTime<Utc>>(1)?,
+                        row.get::<_, Option<DateTime<Utc>>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter()
+                .map(|(attempt, started_at, finished_at, status)| {
+                    Ok(AttemptRow {
+                        attempt,
+                        started_at,
+                        finished_at,
+                        status: status
+                            .map(|status| serde_json::from_str(&status))
+                            .transpose()
+                            .map_err(DbError::Json)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Tasks that were not finished when the server stopped can never finish.
+    /// Marks them as interrupted and returns how many were affected.
+    pub async fn interrupt_unfinished_tasks(&self) -> Result<usize, DbError> {
+        self.call(|conn| {
+            let now = Utc::now();
+            let tx = conn.transaction().map_err(DbError::Sqlite)?;
+
+            let unfinished = {
+                let mut stmt = tx
+                    .prepare("SELECT id, kind FROM tasks WHERE finished_at IS NULL")
+                    .map_err(DbError::Sqlite)?;
+
+                let rows = stmt
+                    .query_map([], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                    })
+                    .map_err(DbError::Sqlite)?;
+
+                rows.collect::<rusqlite::Result<Vec<_>>>()
+                    .map_err(DbError::Sqlite)?
+            };
+
+            for (id, kind) in unfinished.iter() {
+                let kind: TaskKind = serde_json::from_str(kind).map_err(DbError::Json)?;
+                let interrupted = Status::interrupted(kind);
+                let event = status_changed(&interrupted)?;
+                let status = serde_json::to_string(&interrupted).map_err(DbError::Json)?;
+
+                tx.execute(
+                    "UPDATE tasks SET status = ?2, updated_at = ?3, finished_at = ?3, version = version + 1 WHERE id = ?1",
+                    params![id, status, now],
+                )
+                .map_err(DbError::Sqlite)?;
+
+                tx.execute(
+                    "INSERT INTO task_status_transitions (task_id, status, at) VALUES (?1, ?2, ?3)",
+                    params![id, status, now],
+                )
+                .map_err(DbError::Sqlite)?;
+
+                insert_history(&tx, *id, now, EventSource::System, &event)?;
+            }
+
+            tx.commit().map_err(DbError::Sqlite)?;
+
+            Ok(unfinished.len())
+        })
+        .await
+    }
+
+    /// Inserts a running pipeline. Returns the generated pipeline id.
+    pub async fn insert_pipeline(
+        &self,
+        chat_id: &str,
+        steps: &[NewPipelineStep<'_>],
+    ) -> Result<String, DbError> {
+        let chat_id = chat_id.to_string();
+        let status = serde_json::to_string(&PipelineStatus::Running).map_err(DbError::Json)?;
+        let steps = steps
+            .iter()
+            .map(|step| {
+                Ok((
+                    step.name.to_string(),
+                    parse_id(step.task_id)?,
+                    serde_json::to_string(step.depends_on).map_err(DbError::Json)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+            let tx = conn.transaction().map_err(DbError::Sqlite)?;
+
+            tx.execute(
+                "INSERT INTO pipelines (chat_id, status, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+                params![chat_id, status, now],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            let id = tx.last_insert_rowid();
+
+            for (position, (name, task_id, depends_on)) in steps.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO pipeline_steps (pipeline_id, position, name, task_id, depends_on)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![id, position, name, task_id, depends_on],
+                )
+                .map_err(DbError::Sqlite)?;
+            }
+
+            tx.commit().map_err(DbError::Sqlite)?;
+
+            Ok(id.to_string())
+        })
+        .await
+    }
+
+    pub async fn update_pipeline_status(
+        &self,
+        id: &str,
+        status: PipelineStatus,
+    ) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+        let finished = status.is_terminal();
+        let status = serde_json::to_string(&status).map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+            let finished_at = finished.then_some(now);
+
+            conn.execute(
+                "UPDATE pipelines SET status = ?2, updated_at = ?3, finished_at = ?4 WHERE id = ?1",
+                params![id, status, now, finished_at],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// `position` is the index of the step in the submitted pipeline.
+    pub async fn mark_pipeline_step_skipped(
+        &self,
+        id: &str,
+        position: usize,
+    ) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE pipeline_steps SET skipped = 1 WHERE pipeline_id = ?1 AND position = ?2",
+                params![id, position],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the pipeline with the given id if it belongs to the given chat.
+    pub async fn pipeline(&self, id: &str, chat_id: &str) -> Result<Option<PipelineRow>, DbError> {
+        let Ok(id) = parse_id(id) else {
+            return Ok(None);
+        };
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let pipeline = conn
+                .query_row(
+                    "SELECT id, chat_id, status, created_at, updated_at, finished_at
+                     FROM pipelines WHERE id = ?1 AND chat_id = ?2",
+                    params![id, chat_id],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, DateTime<Utc>>(3)?,
+                            row.get::<_, DateTime<Utc>>(4)?,
+                            row.get::<_, Option<DateTime<Utc>>>(5)?,
+                        ))
+                    },
+                )
+                .optional()
+                .map_err(DbError::Sqlite)?;
+
+            let Some((id, chat_id, status, created_at, updated_at, finished_at)) = pipeline else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn
+                .prepare(
+                    "SELECT s.name, s.task_id, s.depends_on, s.skipped, t.status
+                     FROM pipeline_steps s LEFT JOIN tasks t ON t.id = s.task_id
+                     WHERE s.pipeline_id = ?1 ORDER BY s.position",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let steps = stmt
+                .query_map(params![id], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, bool>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
+                })
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?
+                .into_iter()
+                .map(|(name, task_id, depends_on, skipped, status)| {
+                    Ok(PipelineStepRow {
+                        name,
+                        task_id: task_id.to_string(),
+                        depends_on: serde_json::from_str(&depends_on).map_err(DbError::Json)?,
+                        skipped,
+                        status: status
+                            .map(|status| serde_json::from_str(&status))
+                            .transpose()
+                            .map_err(DbError::Json)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            Ok(Some(PipelineRow {
+                id: id.to_string(),
+                chat_id,
+                status: serde_json::from_str(&status).map_err(DbError::Json)?,
+                created_at,
+                updated_at,
+                finished_at,
+                steps,
+            }))
+        })
+        .await
+    }
+
+    /// Pipelines that were not finished when the server stopped can never finish.
+    /// Marks them as interrupted and returns how many were affected.
+    pub async fn interrupt_unfinished_pipelines(&self) -> Result<usize, DbError> {
+        let status = serde_json::to_string(&PipelineStatus::Interrupted).map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            let now = Utc::now();
+
+            conn.execute(
+                "UPDATE pipelines SET status = ?1, updated_at = ?2, finished_at = ?2 WHERE finished_at IS NULL",
+                params![status, now],
+            )
+            .map_err(DbError::Sqlite)
+        })
+        .await
+    }
+
+    /// Deletes finished pipelines that finished before `before`. Returns how many were deleted.
+    pub async fn delete_pipelines_finished_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<usize, DbError> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM pipelines WHERE finished_at IS NOT NULL AND finished_at < ?1",
+                params![before],
+            )
+            .map_err(DbError::Sqlite)
+        })
+        .await
+    }
+
+    /// Inserts an active schedule. Returns the generated schedule id.
+    pub async fn insert_schedule(&self, schedule: NewSchedule<'_>) -> Result<String, DbError> {
+        let chat_id = schedule.chat_id.to_string();
+        let cron = schedule.cron.to_string();
+        let job_type = schedule.job_type.to_string();
+        let project_name = schedule.project_name.to_string();
+        let params = serde_json::to_string(schedule.params).map_err(DbError::Json)?;
+        let priority = schedule
+            .priority
+            .map(|priority| serde_json::to_string(&priority))
+            .transpose()
+            .map_err(DbError::Json)?;
+
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO schedules (chat_id, cron, job_type, project_name, params, priority, created_at, active_since)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+                params![chat_id, cron, job_type, project_name, params, priority, Utc::now()],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(conn.last_insert_rowid().to_string())
+        })
+        .await
+    }
+
+    /// Returns the schedule with the given id if it belongs to the given chat.
+    pub async fn schedule(&self, id: &str, chat_id: &str) -> Result<Option<ScheduleRow>, DbError> {
+        let Ok(id) = parse_id(id) else {
+            return Ok(None);
+        };
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let row = conn
+                .query_row(
+                    &format!(
+                        "SELECT {SCHEDULE_COLUMNS} FROM schedules WHERE id = ?1 AND chat_id = ?2"
+                    ),
+                    params![id, chat_id],
+                    RawScheduleRow::from_row,
+                )
+                .optional()
+                .map_err(DbError::Sqlite)?;
+
+            row.map(RawScheduleRow::parse).transpose()
+        })
+        .await
+    }
+
+    /// Schedules of the given chat, oldest first. All chats if [`None`].
+    pub async fn schedules(&self, chat_id: Option<&str>) -> Result<Vec<ScheduleRow>, DbError> {
+        let chat_id = chat_id.map(ToString::to_string);
+
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {SCHEDULE_COLUMNS} FROM schedules
+                     WHERE ?1 IS NULL OR chat_id = ?1 ORDER BY id"
+                ))
+                .map_err(DbError::Sqlite)?;
+
+            let rows = stmt
+                .query_map(params![chat_id], RawScheduleRow::from_row)
+                .map_err(DbError::Sqlite)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            rows.into_iter().map(RawScheduleRow::parse).collect()
+        })
+        .await
+    }
+
+    /// Resuming a schedule does not catch up on runs missed while it was paused.
+    ///
+    /// Returns `false` if the schedule does not exist for the given chat.
+    pub async fn set_schedule_paused(
+        &self,
+        id: &str,
+        chat_id: &str,
+        paused: bool,
+    ) -> Result<bool, DbError> {
+        let Ok(id) = parse_id(id) else {
+            return Ok(false);
+        };
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let updated = conn
+                .execute(
+                    "UPDATE schedules
+                     SET active_since = CASE WHEN paused AND NOT ?3 THEN ?4 ELSE active_since END,
+                         paused = ?3
+                     WHERE id = ?1 AND chat_id = ?2",
+                    params![id, chat_id, paused, Utc::now()],
+                )
+                .map_err(DbError::Sqlite)?;
+
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    /// Returns `false` if the schedule does not exist for the given chat.
+    pub async fn delete_schedule(&self, id: &str, chat_id: &str) -> Result<bool, DbError> {
+        let Ok(id) = parse_id(id) else {
+            return Ok(false);
+        };
+        let chat_id = chat_id.to_string();
+
+        self.call(move |conn| {
+            let deleted = conn
+                .execute(
+                    "DELETE FROM schedules WHERE id = ?1 AND chat_id = ?2",
+                    params![id, chat_id],
+                )
+                .map_err(DbError::Sqlite)?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    /// Records a run of a schedule and the task it created, or why no task was created.
+    pub async fn record_schedule_run(
+        &self,
+        id: &str,
+        at: DateTime<Utc>,
+        result: Result<&str, &str>,
+    ) -> Result<(), DbError> {
+        let id = parse_id(id)?;
+        let (task_id, error) = match result {
+            Ok(task_id) => (Some(parse_id(task_id)?), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE schedules SET last_run_at = ?2, last_task_id = ?3, last_error = ?4 WHERE id = ?1",
+                params![id, at, task_id, error],
+            )
+            .map_err(DbError::Sqlite)?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes finished tasks that finished before `before`. Returns the ids of the deleted tasks.
+    pub async fn delete_tasks_finished_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, DbError> {
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "DELETE FROM tasks WHERE finished_at IS NOT NULL AND finished_at < ?1 RETURNING id",
+                )
+                .map_err(DbError::Sqlite)?;
+
+            let ids = stmt
+                .query_map(params![before], |row| row.get::<_, i64>(0))
+                .map_err(DbError::Sqlite)?
+                .map(|id| id.map(|id| id.to_string()))
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(DbError::Sqlite)?;
+
+            Ok(ids)
+        })
+        .await
+    }
+}
+
+/// The serialized [`HistoryEvent::StatusChanged`] of a status
+fn status_changed(status: &Status) -> Result<String, DbError> {
+    let event = HistoryEvent::StatusChanged {
+        status: status.clone(),
+    };
+
+    serde_json::to_string(&event).map_err(DbError::Json)
+}
+
+/// `event` is a serialized [`HistoryEvent`]
+fn insert_history(
+    conn: &Connection,
+    task_id: i64,
+    at: DateTime<Utc>,
+    source: EventSource,
+    event: &str,
+) -> Result<(), DbError> {
+    let source = serde_json::to_string(&source).map_err(DbError::Json)?;
+
+    conn.execute(
+        "INSERT INTO task_events (task_id, at, source, event) VALUES (?1, ?2, ?3, ?4)",
+        params![task_id, at, source, event],
+    )
+    .map_err(DbError::Sqlite)?;
+
+    Ok(())
+}
+
+/// Task ids are exposed as strings but stored as integers.
+pub fn parse_id(id: &str) -> Result<i64, DbError> {
+    id.parse().map_err(|_| DbError::InvalidId)
+}
+
+/// Columns of a `tasks` row before the json columns are parsed.
+struct RawTaskRow {
+    id: i64,
+    chat_id: String,
+    kind: String,
+    job_type: Option<String>,
+    project_name: String,
+    params: String,
+    status: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    max_attempts: u32,
+    version: u64,
+    started_at: Option<DateTime<Utc>>,
+    checksum: Option<String>,
+}
+
+impl RawTaskRow {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            chat_id: row.get(1)?,
+            kind: row.get(2)?,
+            job_type: row.get(3)?,
+            project_name: row.get(4)?,
+            params: row.get(5)?,
+            status: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+            finished_at: row.get(9)?,
+            max_attempts: row.get(10)?,
+            version: row.get(11)?,
+            started_at: row.get(12)?,
+            checksum: row.get(13)?,
+        })
+    }
+
+    fn parse(self) -> Result<TaskRow, DbError> {
+        Ok(TaskRow {
+            id: self.id.to_string(),
+            chat_id: self.chat_id,
+            kind: serde_json::from_str(&self.kind).map_err(DbError::Json)?,
+            job_type: self.job_type,
+            project_name: self.project_name,
+            params: serde_json::from_str(&self.params).map_err(DbError::Json)?,
+            status: serde_json::from_str(&self.status).map_err(DbError::Json)?,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            finished_at: self.finished_at,
+            max_attempts: self.max_attempts,
+            version: self.version,
+            started_at: self.started_at,
+            checksum: self
+                .checksum
+                .map(|checksum| serde_json::from_str(&checksum))
+                .transpose()
+                .map_err(DbError::Json)?,
+        })
+    }
+}
+
+/// Columns of a `task_status_transitions` row before the json columns are parsed.
+struct RawTransitionRow {
+    seq: u64,
+    task_id: i64,
+    status: String,
+    at: DateTime<Utc>,
+    version: u64,
+}
+
+impl RawTransitionRow {
+    /// Reads `seq, task_id, status, at, version`. The version is not stored, it is the row number
+    /// of the transition among the transitions of its task.
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            seq: row.get(0)?,
+            task_id: row.get(1)?,
+            status: row.get(2)?,
+            at: row.get(3)?,
+            version: row.get(4)?,
+        })
+    }
+
+    fn parse(self) -> Result<TransitionRow, DbError> {
+        Ok(TransitionRow {
+            task_id: self.task_id.to_string(),
+            seq: self.seq,
+            version: self.version,
+            status: serde_json::from_str(&self.status).map_err(DbError::Json)?,
+            at: self.at,
+        })
+    }
+}
+
+/// Columns of a `schedules` row before the json columns are parsed.
+struct RawScheduleRow {
+    id: i64,
+    chat_id: String,
+    cron: String,
+    job_type: String,
+    project_name: String,
+    params: String,
+    priority: Option<String>,
+    paused: bool,
+    created_at: DateTime<Utc>,
+    active_since: DateTime<Utc>,
+    last_run_at: Option<DateTime<Utc>>,
+    last_task_id: Option<i64>,
+    last_error: Option<String>,
+}
+
+impl RawScheduleRow {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            chat_id: row.get(1)?,
+            cron: row.get(2)?,
+            job_type: row.get(3)?,
+            project_name: row.get(4)?,
+            params: row.get(5)?,
+            priority: row.get(6)?,
+            paused: row.get(7)?,
+            created_at: row.get(8)?,
+            active_since: row.get(9)?,
+            last_run_at: row.get(10)?,
+            last_task_id: row.get(11)?,
+            last_error: row.get(12)?,
+        })
+    }
+
+    fn parse(self) -> Result<ScheduleRow, DbError> {
+        Ok(ScheduleRow {
+            id: self.id.to_string(),
+            chat_id: self.chat_id,
+            cron: self.cron,
+            job_type: self.job_type,
+            project_name: self.project_name,
+            params: serde_json::from_str(&self.params).map_err(DbError::Json)?,
+            priority: self
+                .priority
+                .map(|priority| serde_json::from_str(&priority))
+                .transpose()
+                .map_err(DbError::Json)?,
+            paused: self.paused,
+            created_at: self.created_at,
+            active_since: self.active_since,
+            last_run_at: self.last_run_at,
+            last_task_id: self.last_task_id.map(|id| id.to_string()),
+            last_error: self.last_error,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("Sqlite error: {0}")]
+    Sqlite(rusqlite::Error),
+    #[error("Json error: {0}")]
+    Json(serde_json::Error),
+    #[error("Io error: {0}")]
+    Io(std::io::Error),
+    #[error("Invalid task id")]
+    InvalidId,
+    #[error("Database lock poisoned")]
+    Poisoned,
+    #[error("Failed to spawn blocking task")]
+    BlockingTask,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::task::ProcessStatus;
+
+    #[tokio::test]
+    async fn task_is_scoped_to_chat_id() {
+        let db = Database::open_in_memory().expect("Failed to open database");
+        let id = db.insert_test_task("chat").await;
+
+        assert!(db.task(&id, "chat").await.unwrap().is_some());
+        assert!(db.task(&id, "other").await.unwrap().is_none());
+        assert!(db.task("not-a-number", "chat").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn tasks_are_filtered_and_paginated() {
+        let db = Database::open_in_memory().expect("Failed to open database");
+        let first = db.insert_test_task("chat").await;
+        let second = db.insert_test_task("chat").await;
+        let third = db.insert_test_task("chat").await;
+        let other = db.insert_test_task("other").await;
+
+        db.update_task_status(
+            &second,
+            &Status::Process(ProcessStatus::Running),
+            EventSource::System,
+        )
+        .await
+        .unwrap();
+
+        let ids = |tasks: Vec<TaskRow>| tasks.into_iter().map(|task| task.id).collect::<Vec<_>>();
+
+        let page = db.tasks("chat", TaskFilter::default(), 2).await.unwrap();
+        assert_eq!(ids(page), [third.clone(), second.clone()]);
+
+        let filter = TaskFilter {
+            before_id: Some(second.clone()),
+            ..Default::default()
+        };
+        let page = db.tasks("chat", filter, 2).await.unwrap();
+        assert_eq!(ids(page), [first.as_str()]);
+
+        let filter = TaskFilter {
+            status: Some(StatusName::Running),
+            ..Default::default()
+        };
+        let running = db.tasks("chat", filter, 10).await.unwrap();
+        assert_eq!(ids(running), [second.as_str()]);
+
+        let found = db
+            .tasks_by_ids(&[first.clone(), other, String::from("nope")], "chat")
+            .await
+            .unwrap();
+        assert_eq!(ids(found), [first]);
+    }
+
+    #[tokio::test]
+    async fn unfinished_tasks_are_interrupted_and_finished_tasks_expire() {
+        let db = Database::open_in_memory().expect("Failed to open database");
+        let running = db.insert_test_task("chat").await;
+        let finished = db.insert_test_task("chat").await;
+
+        db.update_task_status(
+            &running,
+            &Status::Process(ProcessStatus::Running),
+            EventSource::System,
+        )
+        .await
+        .unwrap();
+        db.update_task_status(
+            &finished,
+            &Status::Process(ProcessStatus::Canceled { signal: None }),
+            EventSource::User,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(db.interrupt_unfinished_tasks().await.unwrap(), 1);
+
+        let running = db.task(&running, "chat").await.unwrap().unwrap();
+        assert!(matches!(
+            running.status,
+            Status::Process(ProcessStatus::Failed {
+                operation: crate::server::task::FailOperation::Interrupted
+            })
+        ));
+        assert!(running.finished_at.is_some());
+
+        let history = db.task_history(&running.id).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history
+            .iter()
+            .all(|entry| entry.source == EventSource::System));
+
+        let deleted = db
+            .delete_tasks_finished_before(Utc::now() + chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 2);
+    }
+}
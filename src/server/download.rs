@@ -0,0 +1,1521 @@
+//! Downloading archives and extracting them into projects.
+//!
+//! The response body is streamed to a temporary file through a bounded buffer and extracted from there,
+//! so that memory stays flat regardless of the size of the archive.
+//! The temporary file is kept across the attempts of a task. A retry resumes it with a `Range` request
+//! if the server supports it, and the file did not change in between.
+//!
+//! Supported are zip, tar (plain, gzip, zstd and xz compressed) and single compressed files, e.g. `app.log.gz`.
+//! The format is detected from the magic bytes of the download, and from its `Content-Type` if they are inconclusive.
+//!
+//! Archives are checked against [`ArchiveLimits`] while they are streamed, to protect the server
+//! from zip bombs and downloads that are not archives at all, e.g. an HTML page from Google Drive.
+//!
+//! ```toml
+//! [download.limits]
+//! download_mb = 2048
+//! uncompressed_mb = 8192
+//! entries = 10000
+//! compression_ratio = 250
+//! ```
+use super::{
+    google_drive::{self, GoogleDriveError},
+    progress::ProgressReporter,
+    retry::RetryableFailure,
+    source::{DownloadSource, ResolvedLink},
+    task::FailureCode,
+};
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE,
+    LAST_MODIFIED, RANGE,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncWriteExt, BufWriter},
+    sync::watch,
+};
+use utoipa::ToSchema;
+
+const MB: u64 = 1024 * 1024;
+
+/// Size of the buffer between the response body and the temporary file
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of leading bytes used to tell what was downloaded. Covers the header of a tar entry
+const SNIFF_LEN: usize = 512;
+
+/// Name of a single compressed file if neither the file nor the response name it
+const DEFAULT_FILE_NAME: &str = "download";
+
+/// Entries smaller than this are not checked against the compression ratio.
+/// Small files of repeated bytes compress well without being a threat
+const RATIO_CHECK_MIN_SIZE: u64 = MB;
+
+/// File type bits of a unix mode
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// How the paths of the entries in an archive map to files in the project
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractMode {
+    /// Every file is extracted into the project directory. Directories are dropped
+    #[default]
+    Flatten,
+    /// Files keep their paths relative to the project directory
+    Preserve,
+}
+
+/// What happens when two entries are flattened to the same file name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// The last entry wins
+    #[default]
+    Overwrite,
+    /// The first entry wins
+    Skip,
+    /// Later entries get a numeric suffix, e.g. `app_1.log`
+    Rename,
+    /// The download fails
+    Fail,
+}
+
+/// How an archive is extracted, chosen per download.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtractOptions {
+    pub extract_mode: ExtractMode,
+    /// Only applies to [`ExtractMode::Flatten`]
+    pub on_collision: CollisionPolicy,
+}
+
+/// Caps on downloaded archives. Exceeding one fails the download without a retry.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ArchiveLimits {
+    /// Size of the downloaded archive
+    pub download_mb: u64,
+    /// Total size of the extracted files
+    pub uncompressed_mb: u64,
+    /// Number of entries in the archive
+    pub entries: u64,
+    /// Uncompressed size of an entry divided by its compressed size.
+    /// For compressed tar archives and single files the whole download counts as one entry
+    pub compression_ratio: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            download_mb: 2048,
+            uncompressed_mb: 8192,
+            entries: 10_000,
+            compression_ratio: 250,
+        }
+    }
+}
+
+impl ArchiveLimits {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let limits = [
+            self.download_mb,
+            self.uncompressed_mb,
+            self.entries,
+            self.compression_ratio,
+        ];
+
+        if limits.contains(&0) {
+            return Err("limits must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a download can be extracted from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    /// A tar archive or a single file
+    Gzip,
+    /// A tar archive or a single file
+    Zstd,
+    /// A tar archive or a single file
+    Xz,
+}
+
+/// A download in the temporary file
+struct Downloaded {
+    format: Format,
+    size: u64,
+    /// From the `Content-Disposition` header
+    file_name: Option<String>,
+}
+
+/// What a download task downloads and where it extracts it to
+#[derive(Debug, Clone)]
+pub struct DownloadRequest {
+    pub link: ResolvedLink,
+    pub project_dir: PathBuf,
+    pub limits: ArchiveLimits,
+    pub options: ExtractOptions,
+    /// Checked before extracting
+    pub expected_checksum: Option<Checksum>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+}
+
+impl HashAlgorithm {
+    fn hex_len(&self) -> usize {
+        match self {
+            Self::Sha256 => 64,
+            Self::Md5 => 32,
+        }
+    }
+}
+
+/// Hash of a downloaded file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    /// Lowercase hex
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub value: String,
+}
+
+impl Checksum {
+    /// The checksum a client expects, given as hex. At most one of the algorithms may be given.
+    pub fn expected(
+        sha256: Option<&str>,
+        md5: Option<&str>,
+    ) -> Result<Option<Self>, InvalidChecksum> {
+        let (algorithm, value) = match (sha256, md5) {
+            (None, None) => return Ok(None),
+            (Some(sha256), None) => (HashAlgorithm::Sha256, sha256),
+            (None, Some(md5)) => (HashAlgorithm::Md5, md5),
+            (Some(_), Some(_)) => return Err(InvalidChecksum),
+        };
+
+        if value.len() != algorithm.hex_len() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(InvalidChecksum);
+        }
+
+        Ok(Some(Self {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        }))
+    }
+
+    fn compute(
+        algorithm: HashAlgorithm,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> std::io::Result<Self> {
+        use sha2::Digest;
+
+        let file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut reader = ProgressRead::new(file, "verifying", size, progress);
+
+        let value = match algorithm {
+            HashAlgorithm::Sha256 => {
+                let mut hasher = sha2::Sha256::new();
+                std::io::copy(&mut reader, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+            HashAlgorithm::Md5 => {
+                let mut context = md5::Context::new();
+                std::io::copy(&mut reader, &mut context)?;
+                format!("{:x}", context.compute())
+            }
+        };
+
+        Ok(Self { algorithm, value })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Expected the hex of either a sha256 or an md5 checksum")]
+pub struct InvalidChecksum;
+
+/// A download kept across the attempts of a task, so that an attempt resumes where the last one stopped.
+/// The file is deleted on drop.
+pub struct PartialDownload {
+    file: TempFile,
+    /// `ETag` or `Last-Modified` of the response the file was written from.
+    /// Downloads are only resumed if the file did not change in between
+    validator: Option<HeaderValue>,
+}
+
+impl PartialDownload {
+    pub fn new() -> Self {
+        Self {
+            file: TempFile::new(),
+            validator: None,
+        }
+    }
+}
+
+impl Default for PartialDownload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Downloads the file of the request's link and extracts it into the request's project directory.
+/// Returns the checksum of the downloaded file, computed with the expected checksum's algorithm or sha256.
+///
+/// Once `canceled` is `true`, the download stops at once and the extraction before its next entry or write.
+/// The extraction runs on a blocking thread, which is not stopped by dropping the returned future.
+/// Callers must therefore await the future to the end to know that no more files are written.
+pub async fn download_and_extract(
+    request: &DownloadRequest,
+    partial: &mut PartialDownload,
+    progress: ProgressReporter,
+    mut canceled: watch::Receiver<bool>,
+) -> Result<Checksum, DownloadError> {
+    let downloaded = tokio::select! {
+        downloaded = download(&request.link, partial, &request.limits, &progress) => downloaded?,
+        _ = canceled.wait_for(|canceled| *canceled) => return Err(DownloadError::Canceled),
+    };
+
+    tracing::debug!(format=?downloaded.format, size=downloaded.size, "File downloaded");
+
+    let path = partial.file.path().to_path_buf();
+
+    let DownloadRequest {
+        project_dir,
+        limits,
+        options,
+        expected_checksum,
+        ..
+    } = request.clone();
+
+    // ZipFile is not Send -> spawn_blocking
+    tokio::task::spawn_blocking(move || {
+        let algorithm = expected_checksum
+            .as_ref()
+            .map_or(HashAlgorithm::Sha256, |expected| expected.algorithm);
+
+        let checksum = Checksum::compute(algorithm, &path, &progress).map_err(DownloadError::Io)?;
+
+        if let Some(expected) = expected_checksum {
+            if expected != checksum {
+                return Err(DownloadError::ChecksumMismatch { expected, checksum });
+            }
+        }
+
+        tracing::debug!("Extracting files");
+
+        let file = std::fs::File::open(&path).map_err(DownloadError::Io)?;
+        let mut extractor = Extractor::new(&project_dir, &limits, &options, canceled);
+
+        extract(file, &downloaded, &mut extractor, &progress)?;
+
+        Ok(checksum)
+    })
+    .await
+    .map_err(|_| DownloadError::BlockingTask)?
+}
+
+/// Downloads into the partial download. Resumes it with a `Range` request if a previous attempt left some of it.
+async fn download(
+    link: &ResolvedLink,
+    partial: &mut PartialDownload,
+    limits: &ArchiveLimits,
+    progress: &ProgressReporter,
+) -> Result<Downloaded, DownloadError> {
+    let max_size = limits.download_mb.saturating_mul(MB);
+    let too_large = DownloadError::DownloadTooLarge {
+        limit_mb: limits.download_mb,
+    };
+
+    let path = partial.file.path().to_path_buf();
+
+    let offset = match &partial.validator {
+        Some(_) => tokio::fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0),
+        None => 0,
+    };
+
+    let mut range = HeaderMap::new();
+
+    if let Some(validator) = partial.validator.as_ref().filter(|_| offset > 0) {
+        let bytes = HeaderValue::from_str(&format!("bytes={offset}-")).expect("Only ascii");
+
+        range.insert(RANGE, bytes);
+        range.insert(IF_RANGE, validator.clone());
+    }
+
+    let client = reqwest::Client::new();
+
+    let mut response = match send(&client, link, range).await {
+        // The partial download is larger than the file
+        Err(DownloadError::Reqwest(err))
+            if err.status() == Some(reqwest::StatusCode::RANGE_NOT_SATISFIABLE) =>
+        {
+            send(&client, link, HeaderMap::new()).await?
+        }
+        result => result?,
+    };
+
+    let resumed = offset > 0
+        && response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+        && content_range_start(&response) == Some(offset);
+
+    // Some other range. Written from the start, it would corrupt the download
+    if !resumed && response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+        tracing::debug!(%offset, "Unexpected range, downloading the whole file");
+
+        response = send(&client, link, HeaderMap::new()).await?;
+
+        if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(DownloadError::UnexpectedPartialContent);
+        }
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let content_type = header(CONTENT_TYPE);
+    let file_name = header(CONTENT_DISPOSITION)
+        .as_deref()
+        .and_then(content_disposition_file_name);
+    let validator = response
+        .headers()
+        .get(ETAG)
+        .or_else(|| response.headers().get(LAST_MODIFIED))
+        .cloned();
+
+    let mut size = 0u64;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+
+    let file = if resumed {
+        use tokio::io::AsyncReadExt;
+
+        tracing::debug!(%offset, "Resuming download");
+
+        size = offset;
+
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(DownloadError::Io)?;
+        (&mut file)
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(DownloadError::Io)?;
+
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(DownloadError::Io)?
+    } else {
+        tokio::fs::File::create(&path)
+            .await
+            .map_err(DownloadError::Io)?
+    };
+
+    // A response without a validator keeps the one of the response the download started with
+    if !resumed || validator.is_some() {
+        partial.validator = validator;
+    }
+
+    let total = response.content_length().map(|length| length + size);
+
+    if total.is_some_and(|total| total > max_size) {
+        return Err(too_large);
+    }
+
+    let mut file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, file);
+
+    progress.report("downloading", size, total);
+
+    let mut format = None;
+
+    if head.len() == SNIFF_LEN {
+        format = Some(detect_format(&head, content_type.as_deref())?);
+    }
+
+    while let Some(chunk) = response.chunk().await.map_err(DownloadError::Bytes)? {
+        size += chunk.len() as u64;
+
+        if size > max_size {
+            return Err(too_large);
+        }
+
+        if head.len() < SNIFF_LEN {
+            let sniffed = head.len();
+            head.extend(chunk.iter().take(SNIFF_LEN - sniffed));
+
+            if head.len() == SNIFF_LEN {
+                format = Some(detect_format(&head, content_type.as_deref())?);
+            }
+        }
+
+        file.write_all(&chunk).await.map_err(DownloadError::Io)?;
+        progress.advance(chunk.len() as u64);
+    }
+
+    let format = match format {
+        Some(format) => format,
+        None => detect_format(&head, content_type.as_deref())?,
+    };
+
+    file.flush().await.map_err(DownloadError::Io)?;
+
+    Ok(Downloaded {
+        format,
+        size,
+        file_name,
+    })
+}
+
+/// Requests the file of a link. `headers` are sent with every request
+async fn send(
+    client: &reqwest::Client,
+    link: &ResolvedLink,
+    headers: HeaderMap,
+) -> Result<reqwest::Response, DownloadError> {
+    match link.source {
+        DownloadSource::GoogleDrive => {
+            google_drive::get(client, link.download_url.clone(), headers).await
+        }
+        _ => client
+            .get(link.download_url.clone())
+            .headers(headers)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(DownloadError::Reqwest),
+    }
+}
+
+/// First byte of a `Content-Range: bytes 100-999/1000` header
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The `filename` parameter of a `Content-Disposition` header
+fn content_disposition_file_name(value: &str) -> Option<String> {
+    value
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("filename="))
+        .map(|name| name.trim_matches('"').to_owned())
+        .find(|name| !name.is_empty())
+}
+
+/// Tells the format from `head`, the first bytes of a download.
+/// The `Content-Type` is only used if there is no known signature, e.g. for tar archives without the ustar header.
+fn detect_format(head: &[u8], content_type: Option<&str>) -> Result<Format, DownloadError> {
+    const SIGNATURES: [(&[u8], Format); 6] = [
+        (b"PK\x03\x04", Format::Zip),
+        (b"PK\x05\x06", Format::Zip),
+        (b"PK\x07\x08", Format::Zip),
+        (b"\x1f\x8b", Format::Gzip),
+        (b"\x28\xb5\x2f\xfd", Format::Zstd),
+        (b"\xfd7zXZ\x00", Format::Xz),
+    ];
+
+    if let Some((_, format)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+    {
+        return Ok(*format);
+    }
+
+    if is_tar(head) {
+        return Ok(Format::Tar);
+    }
+
+    if head.is_empty() {
+        return Err(DownloadError::NotAnArchive("empty"));
+    }
+
+    let text = String::from_utf8_lossy(&head[..head.len().min(32)]);
+    let text = text.trim_start().to_ascii_lowercase();
+
+    if text.starts_with("<!doctype html") || text.starts_with("<html") {
+        return Err(DownloadError::NotAnArchive("an HTML page"));
+    }
+
+    let essence = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|essence| essence.trim().to_ascii_lowercase());
+
+    match essence.as_deref() {
+        Some("application/zip" | "application/x-zip-compressed") => Ok(Format::Zip),
+        Some("application/x-tar") => Ok(Format::Tar),
+        Some("application/gzip" | "application/x-gzip") => Ok(Format::Gzip),
+        Some("application/zstd") => Ok(Format::Zstd),
+        Some("application/x-xz") => Ok(Format::Xz),
+        _ => Err(DownloadError::NotAnArchive("of an unknown format")),
+    }
+}
+
+/// Whether `head` starts with the header of a ustar or GNU tar entry
+fn is_tar(head: &[u8]) -> bool {
+    head.get(257..262) == Some(b"ustar")
+}
+
+fn extract<R: Read + Seek>(
+    file: R,
+    downloaded: &Downloaded,
+    extractor: &mut Extractor<'_>,
+    progress: &ProgressReporter,
+) -> Result<(), DownloadError> {
+    let file_name = downloaded.file_name.as_deref();
+    let input = ProgressRead::new(file, "extracting", downloaded.size, progress);
+
+    match downloaded.format {
+        Format::Zip => {
+            let zip = zip::ZipArchive::new(input.into_inner()).map_err(DownloadError::Zip)?;
+
+            unzip(zip, extractor, progress)
+        }
+        Format::Tar => untar(input, extractor, None),
+        Format::Gzip => {
+            let mut decoder = flate2::read::MultiGzDecoder::new(input);
+            let head = peek(&mut decoder)?;
+
+            // The gzip header may name the compressed file
+            let file_name = decoder
+                .header()
+                .and_then(|header| header.filename())
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .or_else(|| file_name.map(strip_compression_extension));
+
+            decompress(head, decoder, file_name, downloaded.size, extractor)
+        }
+        Format::Zstd => {
+            let mut decoder = zstd::Decoder::new(input).map_err(DownloadError::Io)?;
+            let head = peek(&mut decoder)?;
+            let file_name = file_name.map(strip_compression_extension);
+
+            decompress(head, decoder, file_name, downloaded.size, extractor)
+        }
+        Format::Xz => {
+            let mut decoder = xz2::read::XzDecoder::new(input);
+            let head = peek(&mut decoder)?;
+            let file_name = file_name.map(strip_compression_extension);
+
+            decompress(head, decoder, file_name, downloaded.size, extractor)
+        }
+    }
+}
+
+/// Reads the first bytes of a decompressed stream to tell a tar archive from a single file
+fn peek<R: Read>(reader: &mut R) -> Result<Vec<u8>, DownloadError> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+
+    reader
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .map_err(DownloadError::Io)?;
+
+    Ok(head)
+}
+
+/// Extracts a decompressed stream that starts with the already read `head`.
+fn decompress<R: Read>(
+    head: Vec<u8>,
+    rest: R,
+    file_name: Option<String>,
+    compressed_size: u64,
+    extractor: &mut Extractor<'_>,
+) -> Result<(), DownloadError> {
+    let tar = is_tar(&head);
+    let reader = Cursor::new(head).chain(rest);
+
+    if tar {
+        return untar(reader, extractor, Some(compressed_size));
+    }
+
+    let file_name = file_name.unwrap_or_else(|| String::from(DEFAULT_FILE_NAME));
+
+    extractor.single_file(reader, &file_name, compressed_size)
+}
+
+fn strip_compression_extension(file_name: &str) -> String {
+    [".gz", ".zst", ".xz"]
+        .iter()
+        .find_map(|extension| file_name.strip_suffix(extension))
+        .unwrap_or(file_name)
+        .to_owned()
+}
+
+fn unzip<R: Read + Seek>(
+    mut zip: zip::ZipArchive<R>,
+    extractor: &mut Extractor<'_>,
+    progress: &ProgressReporter,
+) -> Result<(), DownloadError> {
+    let total = zip.len() as u64;
+
+    // The central directory lists every entry, so the count is checked before extracting anything
+    if total > extractor.limits.entries {
+        return Err(DownloadError::TooManyEntries {
+            limit: extractor.limits.entries,
+        });
+    }
+
+    for i in 0..zip.len() {
+        progress.report("extracting", i as u64, Some(total));
+
+        let mut file = zip.by_index(i).map_err(DownloadError::Zip)?;
+        let name = file.name().to_owned();
+
+        let is_symlink = file
+            .unix_mode()
+            .is_some_and(|mode| mode & S_IFMT == S_IFLNK);
+
+        let kind = if is_symlink {
+            EntryKind::Link
+        } else if file.is_dir() {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        };
+
+        extractor.count_entry()?;
+
+        let Some(path) = extractor.target(&name, kind)? else {
+            continue;
+        };
+
+        let max_size = ratio_cap(file.compressed_size(), extractor.limits);
+
+        extractor.write(&mut file, &name, &path, max_size)?;
+    }
+
+    Ok(())
+}
+
+/// `compressed_size` is the size of the compressed download for compressed archives, which are checked as a whole
+fn untar<R: Read>(
+    reader: R,
+    extractor: &mut Extractor<'_>,
+    compressed_size: Option<u64>,
+) -> Result<(), DownloadError> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries().map_err(DownloadError::Tar)? {
+        let mut entry = entry.map_err(DownloadError::Tar)?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+
+        let kind = match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Dir,
+            tar::EntryType::Symlink | tar::EntryType::Link => EntryKind::Link,
+            entry_type => {
+                tracing::debug!(entry = name, ?entry_type, "Skipped tar entry");
+                continue;
+            }
+        };
+
+        extractor.count_entry()?;
+
+        let Some(path) = extractor.target(&name, kind)? else {
+            continue;
+        };
+
+        let max_size = match compressed_size {
+            Some(compressed_size) => {
+                ratio_cap(compressed_size, extractor.limits).saturating_sub(extractor.written())
+            }
+            None => u64::MAX,
+        };
+
+        extractor.write(&mut entry, &name, &path, max_size)?;
+    }
+
+    Ok(())
+}
+
+/// Largest uncompressed size allowed by the compression ratio for `compressed_size` bytes
+fn ratio_cap(compressed_size: u64, limits: &ArchiveLimits) -> u64 {
+    compressed_size
+        .max(1)
+        .saturating_mul(limits.compression_ratio)
+        .max(RATIO_CHECK_MIN_SIZE)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Dir,
+    /// Symbolic or hard link
+    Link,
+}
+
+/// Writes the entries of one archive into a project and enforces the limits across them.
+struct Extractor<'a> {
+    project_dir: &'a Path,
+    limits: &'a ArchiveLimits,
+    options: &'a ExtractOptions,
+    /// Uncompressed bytes left for the archive
+    budget: u64,
+    entries: u64,
+    /// Names of the files written so far in flatten mode
+    taken: HashSet<PathBuf>,
+    canceled: watch::Receiver<bool>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(
+        project_dir: &'a Path,
+        limits: &'a ArchiveLimits,
+        options: &'a ExtractOptions,
+        canceled: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            project_dir,
+            limits,
+            options,
+            budget: limits.uncompressed_mb.saturating_mul(MB),
+            entries: 0,
+            taken: HashSet::new(),
+            canceled,
+        }
+    }
+
+    fn check_canceled(&self) -> Result<(), DownloadError> {
+        if *self.canceled.borrow() {
+            return Err(DownloadError::Canceled);
+        }
+
+        Ok(())
+    }
+
+    /// Uncompressed bytes written so far
+    fn written(&self) -> u64 {
+        self.limits.uncompressed_mb.saturating_mul(MB) - self.budget
+    }
+
+    fn count_entry(&mut self) -> Result<(), DownloadError> {
+        self.check_canceled()?;
+
+        self.entries += 1;
+
+        if self.entries > self.limits.entries {
+            return Err(DownloadError::TooManyEntries {
+                limit: self.limits.entries,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The file an entry is written to, or `None` if there is nothing to write.
+    /// Directories are created in preserve mode.
+    fn target(&mut self, name: &str, kind: EntryKind) -> Result<Option<PathBuf>, DownloadError> {
+        if kind == EntryKind::Link {
+            return Err(DownloadError::UnsafePath {
+                entry: name.to_owned(),
+                reason: "links are not allowed",
+            });
+        }
+
+        let relative_path =
+            safe_relative_path(name).map_err(|reason| DownloadError::UnsafePath {
+                entry: name.to_owned(),
+                reason,
+            })?;
+
+        match self.options.extract_mode {
+            ExtractMode::Preserve => {
+                let path = self.project_dir.join(&relative_path);
+
+                if kind == EntryKind::Dir {
+                    std::fs::create_dir_all(&path).map_err(DownloadError::Io)?;
+                    return Ok(None);
+                }
+
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(DownloadError::Io)?;
+                }
+
+                Ok(Some(path))
+            }
+            ExtractMode::Flatten => {
+                if kind == EntryKind::Dir {
+                    return Ok(None);
+                }
+
+                // Strip all directories
+                let file_name = relative_path
+                    .file_name()
+                    .map(PathBuf::from)
+                    .unwrap_or_default();
+
+                match resolve_collision(file_name, self.options.on_collision, &mut self.taken)? {
+                    Some(file_name) => Ok(Some(self.project_dir.join(file_name))),
+                    None => {
+                        tracing::debug!(entry = name, "Skipped colliding file");
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Extracts a compressed file that is not an archive
+    fn single_file<R: Read>(
+        &mut self,
+        mut reader: R,
+        file_name: &str,
+        compressed_size: u64,
+    ) -> Result<(), DownloadError> {
+        // Only the name of the file is used, it is never extracted into a directory
+        let file_name = Path::new(file_name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(DEFAULT_FILE_NAME);
+
+        self.count_entry()?;
+
+        let Some(path) = self.target(file_name, EntryKind::File)? else {
+            return Ok(());
+        };
+
+        let max_size = ratio_cap(compressed_size, self.limits);
+
+        self.write(&mut reader, file_name, &path, max_size)
+    }
+
+    /// Copies an entry while counting the bytes that are actually written.
+    /// The sizes in the archive's headers are not trusted.
+    fn write<R: Read>(
+        &mut self,
+        entry: &mut R,
+        name: &str,
+        path: &Path,
+        max_size: u64,
+    ) -> Result<(), DownloadError> {
+        let mut out = std::fs::File::create(path).map_err(DownloadError::Io)?;
+
+        let mut buf = vec![0; WRITE_BUFFER_SIZE];
+        let mut written = 0u64;
+
+        loop {
+            self.check_canceled()?;
+
+            let read = entry.read(&mut buf).map_err(DownloadError::Io)?;
+
+            if read == 0 {
+                break;
+            }
+
+            written += read as u64;
+
+            if read as u64 > self.budget {
+                return Err(DownloadError::UncompressedTooLarge {
+                    limit_mb: self.limits.uncompressed_mb,
+                });
+            }
+
+            if written > max_size {
+                return Err(DownloadError::CompressionRatio {
+                    entry: name.to_owned(),
+                    limit: self.limits.compression_ratio,
+                });
+            }
+
+            self.budget -= read as u64;
+
+            out.write_all(&buf[..read]).map_err(DownloadError::Io)?;
+        }
+
+        tracing::debug!(?path, "Extracted file");
+
+        Ok(())
+    }
+}
+
+/// Reports how much of the downloaded file was read, e.g. while extracting it
+struct ProgressRead<'a, R> {
+    inner: R,
+    label: &'static str,
+    read: u64,
+    total: u64,
+    progress: &'a ProgressReporter,
+}
+
+impl<'a, R> ProgressRead<'a, R> {
+    fn new(inner: R, label: &'static str, total: u64, progress: &'a ProgressReporter) -> Self {
+        Self {
+            inner,
+            label,
+            read: 0,
+            total,
+            progress,
+        }
+    }
+
+    fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ProgressRead<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        self.read += read as u64;
+        self.progress
+            .report(self.label, self.read, Some(self.total));
+
+        Ok(read)
+    }
+}
+
+/// The path of an entry relative to the project.
+/// Entries must not escape the project, so absolute paths and `..` are rejected.
+fn safe_relative_path(name: &str) -> Result<PathBuf, &'static str> {
+    let mut path = PathBuf::new();
+
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => return Err("parent directories are not allowed"),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err("absolute paths are not allowed")
+            }
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        return Err("the path is empty");
+    }
+
+    Ok(path)
+}
+
+/// The name a flattened file is written to, or `None` if it is skipped.
+/// `taken` holds the names already written by this archive.
+fn resolve_collision(
+    file_name: PathBuf,
+    policy: CollisionPolicy,
+    taken: &mut HashSet<PathBuf>,
+) -> Result<Option<PathBuf>, DownloadError> {
+    if taken.insert(file_name.clone()) {
+        return Ok(Some(file_name));
+    }
+
+    match policy {
+        CollisionPolicy::Overwrite => Ok(Some(file_name)),
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::Fail => Err(DownloadError::NameCollision {
+            file_name: file_name.to_string_lossy().into_owned(),
+        }),
+        CollisionPolicy::Rename => {
+            let stem = file_name
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let extension = file_name
+                .extension()
+                .map(|extension| format!(".{}", extension.to_string_lossy()))
+                .unwrap_or_default();
+
+            let renamed = (1..)
+                .map(|n| PathBuf::from(format!("{stem}_{n}{extension}")))
+                .find(|renamed| !taken.contains(renamed))
+                .expect("Infinite iterator");
+
+            taken.insert(renamed.clone());
+
+            Ok(Some(renamed))
+        }
+    }
+}
+
+/// A file in the temporary directory, deleted on drop.
+/// Downloads that time out or are canceled are dropped and leave nothing behind.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new() -> Self {
+        let name = format!("job_hub_download_{}", uuid::Uuid::new_v4());
+
+        Self {
+            path: std::env::temp_dir().join(name),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(%err, path=?self.path, "Failed to delete temporary file");
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Reqwest error: {0}")]
+    Reqwest(reqwest::Error),
+    #[error("Failed to extract bytes: {0}")]
+    Bytes(reqwest::Error),
+    #[error("Zip error: {0}")]
+    Zip(zip::result::ZipError),
+    #[error("Tar error: {0}")]
+    Tar(std::io::Error),
+    #[error("Io error: {0}")]
+    Io(std::io::Error),
+    #[error("Failed to spawn blocking task")]
+    BlockingTask,
+    #[error("Canceled")]
+    Canceled,
+    #[error("The server sent a part of the file instead of the whole file")]
+    UnexpectedPartialContent,
+    #[error("The download exceeds the limit of {limit_mb} MB")]
+    DownloadTooLarge { limit_mb: u64 },
+    #[error("The extracted files exceed the limit of {limit_mb} MB")]
+    UncompressedTooLarge { limit_mb: u64 },
+    #[error("The archive has more than {limit} entries")]
+    TooManyEntries { limit: u64 },
+    #[error("Entry {entry} exceeds the compression ratio limit of {limit}")]
+    CompressionRatio { entry: String, limit: u64 },
+    #[error("The download is not an archive, it is {0}")]
+    NotAnArchive(&'static str),
+    #[error("Unsafe path {entry}: {reason}")]
+    UnsafePath { entry: String, reason: &'static str },
+    #[error("More than one entry is named {file_name}")]
+    NameCollision { file_name: String },
+    #[error("Google Drive: {0}")]
+    GoogleDrive(GoogleDriveError),
+    #[error("Expected {:?} checksum {}, got {}", expected.algorithm, expected.value, checksum.value)]
+    ChecksumMismatch {
+        expected: Checksum,
+        checksum: Checksum,
+    },
+}
+
+impl DownloadError {
+    pub fn failure_code(&self) -> FailureCode {
+        match self {
+            Self::Reqwest(_) | Self::Bytes(_) | Self::UnexpectedPartialContent => {
+                FailureCode::Download
+            }
+            Self::Zip(_) | Self::Tar(_) | Self::Io(_) => FailureCode::Extract,
+            // Reported as the timeout or cancellation that caused it
+            Self::BlockingTask | Self::Canceled => FailureCode::Internal,
+            Self::DownloadTooLarge { .. } => FailureCode::DownloadTooLarge,
+            Self::UncompressedTooLarge { .. } => FailureCode::UncompressedTooLarge,
+            Self::TooManyEntries { .. } => FailureCode::TooManyEntries,
+            Self::CompressionRatio { .. } => FailureCode::CompressionRatioExceeded,
+            Self::NotAnArchive(_) => FailureCode::NotAnArchive,
+            Self::UnsafePath { .. } => FailureCode::UnsafePath,
+            Self::NameCollision { .. } => FailureCode::NameCollision,
+            Self::GoogleDrive(err) => err.failure_code(),
+            Self::ChecksumMismatch { .. } => FailureCode::ChecksumMismatch,
+        }
+    }
+
+    /// Connection errors, server errors and rate limiting are worth another attempt
+    pub fn retryable_failure(&self) -> Option<RetryableFailure> {
+        match self {
+            Self::Reqwest(err) | Self::Bytes(err) => {
+                let transient = err.status().is_none_or(|status| {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                });
+
+                transient.then_some(RetryableFailure::Network)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zip_of(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+        for (name, content) in entries {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_of(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, content.as_slice())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Extracts `bytes` as if they were downloaded with `app.log.gz` in the `Content-Disposition`
+    fn extract_into(
+        dir: &Path,
+        bytes: Vec<u8>,
+        limits: &ArchiveLimits,
+        options: &ExtractOptions,
+    ) -> Result<(), DownloadError> {
+        let downloaded = Downloaded {
+            format: detect_format(&bytes[..bytes.len().min(SNIFF_LEN)], None)?,
+            size: bytes.len() as u64,
+            file_name: Some(String::from("app.log.gz")),
+        };
+        let mut extractor = Extractor::new(dir, limits, options, watch::channel(false).1);
+
+        extract(
+            Cursor::new(bytes),
+            &downloaded,
+            &mut extractor,
+            &ProgressReporter::default(),
+        )
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("job_hub_download_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn detects_what_was_downloaded() {
+        assert_eq!(detect_format(b"PK\x03\x04rest", None).unwrap(), Format::Zip);
+        assert_eq!(
+            detect_format(&tar_of(&[("a.txt", vec![b'a'])]), None).unwrap(),
+            Format::Tar
+        );
+        assert_eq!(
+            detect_format(b"old tar", Some("application/x-tar; charset=binary")).unwrap(),
+            Format::Tar
+        );
+        assert!(matches!(
+            detect_format(b"\n  <!DOCTYPE html>", Some("application/zip")),
+            Err(DownloadError::NotAnArchive("an HTML page"))
+        ));
+        assert!(matches!(
+            detect_format(b"", None),
+            Err(DownloadError::NotAnArchive("empty"))
+        ));
+        assert_eq!(
+            content_disposition_file_name(r#"attachment; filename="logs.tar.gz""#).as_deref(),
+            Some("logs.tar.gz")
+        );
+    }
+
+    #[test]
+    fn enforces_archive_limits_while_extracting() {
+        let dir = temp_dir();
+        let flatten = ExtractOptions::default();
+
+        let limits = ArchiveLimits {
+            entries: 1,
+            ..Default::default()
+        };
+        let zip = zip_of(&[("a.txt", vec![b'a']), ("b.txt", vec![b'b'])]);
+        let err = extract_into(&dir, zip, &limits, &flatten).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::TooManyEntries);
+
+        let tar = tar_of(&[("a.txt", vec![b'a']), ("b.txt", vec![b'b'])]);
+        let err = extract_into(&dir, tar, &limits, &flatten).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::TooManyEntries);
+
+        let limits = ArchiveLimits {
+            uncompressed_mb: 1,
+            compression_ratio: u64::MAX,
+            ..Default::default()
+        };
+        let zip = zip_of(&[("big.txt", vec![0; 2 * MB as usize])]);
+        let err = extract_into(&dir, zip, &limits, &flatten).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::UncompressedTooLarge);
+
+        let zip = zip_of(&[("bomb.txt", vec![0; 2 * MB as usize])]);
+        let err = extract_into(&dir, zip, &ArchiveLimits::default(), &flatten).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::CompressionRatioExceeded);
+
+        let tar_gz = gzip(&tar_of(&[("bomb.txt", vec![0; 2 * MB as usize])]));
+        let err = extract_into(&dir, tar_gz, &ArchiveLimits::default(), &flatten).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::CompressionRatioExceeded);
+
+        let zip = zip_of(&[("dir/ok.txt", b"ok".to_vec())]);
+        extract_into(&dir, zip, &ArchiveLimits::default(), &flatten).unwrap();
+        assert_eq!(std::fs::read(dir.join("ok.txt")).unwrap(), b"ok");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_entries_escaping_the_project() {
+        assert_eq!(
+            safe_relative_path("./day1/app.log").unwrap(),
+            Path::new("day1/app.log")
+        );
+        assert!(safe_relative_path("day1/../../etc/passwd").is_err());
+        assert!(safe_relative_path("/etc/passwd").is_err());
+        assert!(safe_relative_path("./").is_err());
+    }
+
+    #[test]
+    fn resolves_collisions_of_flattened_files() {
+        let resolve = |policy| {
+            let mut taken = HashSet::new();
+            ["app.log", "app.log", "app.log"]
+                .into_iter()
+                .map(|name| resolve_collision(PathBuf::from(name), policy, &mut taken))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let renamed = resolve(CollisionPolicy::Rename).unwrap();
+        let renamed: Vec<_> = renamed
+            .iter()
+            .flatten()
+            .map(|p| p.to_str().unwrap())
+            .collect();
+        assert_eq!(renamed, ["app.log", "app_1.log", "app_2.log"]);
+
+        let skipped = resolve(CollisionPolicy::Skip).unwrap();
+        assert_eq!(skipped.iter().flatten().count(), 1);
+
+        let err = resolve(CollisionPolicy::Fail).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::NameCollision);
+    }
+
+    #[test]
+    fn preserves_directories_of_every_format() {
+        let limits = ArchiveLimits::default();
+        let preserve = ExtractOptions {
+            extract_mode: ExtractMode::Preserve,
+            ..Default::default()
+        };
+
+        let entries = [
+            ("day1/app.log", b"1".to_vec()),
+            ("day2/app.log", b"2".to_vec()),
+        ];
+        let tar = tar_of(&entries);
+
+        let archives = [
+            zip_of(&entries),
+            tar.clone(),
+            gzip(&tar),
+            zstd::encode_all(tar.as_slice(), 0).unwrap(),
+            {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&tar).unwrap();
+                encoder.finish().unwrap()
+            },
+        ];
+
+        for archive in archives {
+            let dir = temp_dir();
+
+            extract_into(&dir, archive, &limits, &preserve).unwrap();
+            assert_eq!(std::fs::read(dir.join("day1/app.log")).unwrap(), b"1");
+            assert_eq!(std::fs::read(dir.join("day2/app.log")).unwrap(), b"2");
+
+            let _ = std::fs::remove_dir_all(dir);
+        }
+
+        let dir = temp_dir();
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_symlink("link", "/etc/passwd", zip::write::FileOptions::default())
+            .unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+        let err = extract_into(&dir, zip, &limits, &preserve).unwrap_err();
+        assert_eq!(err.failure_code(), FailureCode::UnsafePath);
+        assert!(!dir.join("link").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stops_extracting_once_canceled() {
+        let dir = temp_dir();
+        let zip = zip_of(&[("a.txt", b"a".to_vec())]);
+        let downloaded = Downloaded {
+            format: Format::Zip,
+            size: zip.len() as u64,
+            file_name: None,
+        };
+        let (cancel, canceled) = watch::channel(false);
+        let limits = ArchiveLimits::default();
+        let options = ExtractOptions::default();
+        let mut extractor = Extractor::new(&dir, &limits, &options, canceled);
+
+        cancel.send_replace(true);
+
+        assert!(matches!(
+            extract(
+                Cursor::new(zip),
+                &downloaded,
+                &mut extractor,
+                &ProgressReporter::default()
+            ),
+            Err(DownloadError::Canceled)
+        ));
+        assert!(!dir.join("a.txt").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn extracts_single_compressed_files() {
+        let dir = temp_dir();
+
+        extract_into(
+            &dir,
+            gzip(b"line"),
+            &ArchiveLimits::default(),
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(dir.join("app.log")).unwrap(), b"line");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn verifies_checksums() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        assert!(Checksum::expected(None, None).unwrap().is_none());
+        assert!(Checksum::expected(Some("abc"), None).is_err());
+        assert!(
+            Checksum::expected(Some(sha256), Some("5d41402abc4b2a76b9719d911017c592")).is_err()
+        );
+
+        let expected = Checksum::expected(Some(&sha256.to_uppercase()), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected.value, sha256);
+
+        let dir = temp_dir();
+        let path = dir.join("hello");
+        std::fs::write(&path, b"hello").unwrap();
+        let progress = ProgressReporter::default();
+
+        let computed = Checksum::compute(HashAlgorithm::Sha256, &path, &progress).unwrap();
+        assert_eq!(computed, expected);
+
+        let computed = Checksum::compute(HashAlgorithm::Md5, &path, &progress).unwrap();
+        assert_eq!(computed.value, "5d41402abc4b2a76b9719d911017c592");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn never_writes_a_range_that_does_not_continue_the_download() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let download_url = format!("http://{}/file.zip", listener.local_addr().unwrap());
+
+        // Answers every request with some other range
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+
+                let response = "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 2-4/10\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc";
+                stream.write_all(response.as_bytes()).await.unwrap();
+
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            }
+
+            requests
+        });
+
+        let link = ResolvedLink {
+            source: DownloadSource::Https,
+            download_url: url::Url::parse(&download_url).unwrap(),
+        };
+        let mut partial = PartialDownload::new();
+        partial.validator = Some(HeaderValue::from_static("\"etag\""));
+        tokio::fs::write(partial.file.path(), b"01234")
+            .await
+            .unwrap();
+
+        let result = download(
+            &link,
+            &mut partial,
+            &ArchiveLimits::default(),
+            &ProgressReporter::default(),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(DownloadError::UnexpectedPartialContent)
+        ));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].contains("range: bytes=5-"));
+        assert!(!requests[1].contains("range:"));
+
+        let written = tokio::fs::read(partial.file.path()).await.unwrap();
+        assert_eq!(written, b"01234");
+    }
+}
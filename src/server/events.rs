@@ -0,0 +1,91 @@
+//! Live events of running tasks, broadcast to every subscriber.
+//!
+//! Events are not stored. Subscribers that fall behind miss events,
+//! see [`tokio::sync::broadcast::error::RecvError::Lagged`].
+use super::{db::TransitionRow, output::OutputStream, task::Status};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Events a subscriber may miss before it lags behind
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct TaskEvent {
+    pub task_id: String,
+    pub kind: TaskEventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TaskEventKind {
+    /// The status was persisted. See [`crate::server::db::TransitionRow`]
+    Status {
+        status: Status,
+        chat_id: String,
+        seq: u64,
+        version: u64,
+        at: DateTime<Utc>,
+    },
+    /// A line of the output of the OS process
+    Output {
+        stream: OutputStream,
+        /// 1-based number of the line in its stream
+        line_number: u64,
+        line: String,
+    },
+}
+
+impl TaskEvent {
+    /// The chat and the transition of a status change. [`None`] for other events.
+    pub fn transition(&self) -> Option<(&str, TransitionRow)> {
+        let TaskEventKind::Status {
+            status,
+            chat_id,
+            seq,
+            version,
+            at,
+        } = &self.kind
+        else {
+            return None;
+        };
+
+        let transition = TransitionRow {
+            task_id: self.task_id.clone(),
+            seq: *seq,
+            version: *version,
+            status: status.clone(),
+            at: *at,
+        };
+
+        Some((chat_id, transition))
+    }
+}
+
+/// Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct TaskEventBus {
+    tx: broadcast::Sender<Arc<TaskEvent>>,
+}
+
+impl Default for TaskEventBus {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CAPACITY);
+
+        Self { tx }
+    }
+}
+
+impl TaskEventBus {
+    pub fn publish(&self, task_id: &str, kind: TaskEventKind) {
+        // Fails if nobody is subscribed
+        let _ = self.tx.send(Arc::new(TaskEvent {
+            task_id: task_id.to_string(),
+            kind,
+        }));
+    }
+
+    /// Receives every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<TaskEvent>> {
+        self.tx.subscribe()
+    }
+}
@@ -0,0 +1,26 @@
+use crate::server::response::ApiError;
+use axum::{
+    extract::{FromRequest, Request},
+    Json as AxumJson,
+};
+use serde::de::DeserializeOwned;
+
+/// A Wrapper around [`axum::Json`] that rejects with an [`ApiError`]
+pub struct Json<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let json = AxumJson::<T>::from_request(req, state)
+            .await
+            .map_err(|_| ApiError::BodyInvalid)?;
+
+        Ok(Self(json.0))
+    }
+}
@@ -1,2 +1,3 @@
 pub mod chat_id;
+pub mod json;
 pub mod query;
@@ -0,0 +1,245 @@
+//! Downloads from Google Drive.
+//!
+//! Drive serves files it can not scan for viruses, usually over about 100 MB, behind an HTML page
+//! that asks to download anyway. The page holds a form, or in older versions a link, with a confirm token.
+//! Older versions also set a `download_warning` cookie with the token.
+//! The file is requested again with the token until Drive serves the file itself.
+use super::{download::DownloadError, task::FailureCode};
+use reqwest::header::{HeaderMap, CONTENT_TYPE, COOKIE, SET_COOKIE};
+
+/// Requests made for one file, including the first one
+const MAX_REQUESTS: usize = 3;
+
+/// HTML pages of Drive are small. Anything larger is not read to the end
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Requests a file from Google Drive and confirms the virus scan warning if Drive asks for it.
+/// Returns the response with the content of the file. `headers` are sent with every request.
+pub async fn get(
+    client: &reqwest::Client,
+    download_url: url::Url,
+    headers: HeaderMap,
+) -> Result<reqwest::Response, DownloadError> {
+    let mut url = download_url;
+    let mut cookie: Option<String> = None;
+
+    for _ in 0..MAX_REQUESTS {
+        let mut request = client.get(url.clone()).headers(headers.clone());
+
+        if let Some(cookie) = &cookie {
+            request = request.header(COOKIE, cookie);
+        }
+
+        let response = request.send().await.map_err(DownloadError::Reqwest)?;
+
+        // Private files redirect to the sign in page
+        if response.url().host_str() == Some("accounts.google.com") {
+            return Err(DownloadError::GoogleDrive(GoogleDriveError::PrivateFile));
+        }
+
+        if !is_html(&response) {
+            return response.error_for_status().map_err(DownloadError::Reqwest);
+        }
+
+        let status = response.status();
+        let warning_cookie = download_warning_cookie(&response);
+        let page = read_page(response).await?;
+
+        url = if let Some(form_url) = confirm_form_url(&page, &url) {
+            form_url
+        } else if let Some((name, token)) = warning_cookie.or_else(|| confirm_link_token(&page)) {
+            cookie = Some(format!("{name}={token}"));
+
+            let mut confirm_url = url.clone();
+            confirm_url.query_pairs_mut().append_pair("confirm", &token);
+            confirm_url
+        } else {
+            let err = page_error(&page, status).unwrap_or(GoogleDriveError::UnexpectedPage);
+
+            return Err(DownloadError::GoogleDrive(err));
+        };
+
+        tracing::debug!("Confirming virus scan warning");
+    }
+
+    Err(DownloadError::GoogleDrive(GoogleDriveError::Unconfirmed))
+}
+
+fn is_html(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/html"))
+}
+
+/// Name and value of the `download_warning` cookie, which holds the confirm token
+fn download_warning_cookie(response: &reqwest::Response) -> Option<(String, String)> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|cookie| cookie.split(';').next()?.split_once('='))
+        .find(|(name, _)| name.starts_with("download_warning"))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+}
+
+async fn read_page(mut response: reqwest::Response) -> Result<String, DownloadError> {
+    let mut page = Vec::new();
+
+    while let Some(chunk) = response.chunk().await.map_err(DownloadError::Bytes)? {
+        page.extend_from_slice(&chunk);
+
+        if page.len() >= MAX_PAGE_SIZE {
+            break;
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&page).into_owned())
+}
+
+/// The reason Drive refuses to serve the file, if a page without a confirm token says so
+fn page_error(page: &str, status: reqwest::StatusCode) -> Option<GoogleDriveError> {
+    if page.contains("Too many users have viewed or downloaded this file recently")
+        || page.contains("Quota exceeded")
+    {
+        return Some(GoogleDriveError::QuotaExceeded);
+    }
+
+    if status == reqwest::StatusCode::NOT_FOUND || page.contains("does not exist") {
+        return Some(GoogleDriveError::NotFound);
+    }
+
+    if matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    ) || page.contains("You need access")
+        || page.contains("You need permission")
+    {
+        return Some(GoogleDriveError::PrivateFile);
+    }
+
+    None
+}
+
+/// The URL the `download-form` of the page submits to, with its hidden inputs as query.
+fn confirm_form_url(page: &str, base: &url::Url) -> Option<url::Url> {
+    let id = page.find(r#"id="download-form""#)?;
+    let start = page[..id].rfind("<form")?;
+    let form = &page[start..];
+    let form = &form[..form.find("</form>").unwrap_or(form.len())];
+
+    let form_tag = &form[..form.find('>')?];
+    let mut url = base.join(&attribute(form_tag, "action")?).ok()?;
+
+    let inputs: Vec<_> = form
+        .split("<input")
+        .skip(1)
+        .map(|input| &input[..input.find('>').unwrap_or(input.len())])
+        .filter(|input| attribute(input, "type").as_deref() == Some("hidden"))
+        .filter_map(|input| Some((attribute(input, "name")?, attribute(input, "value")?)))
+        .collect();
+
+    url.query_pairs_mut().clear().extend_pairs(inputs);
+
+    Some(url)
+}
+
+/// The confirm token of the download link of older pages, e.g. `/uc?export=download&confirm=AbC1&id=...`
+fn confirm_link_token(page: &str) -> Option<(String, String)> {
+    let start = page.find("confirm=")? + "confirm=".len();
+
+    let token: String = page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+
+    if token.is_empty() {
+        return None;
+    }
+
+    Some((String::from("download_warning"), token))
+}
+
+/// Value of a double quoted attribute of an HTML tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!(r#" {name}=""#);
+    let start = tag.find(&pattern)? + pattern.len();
+    let value = &tag[start..start + tag[start..].find('"')?];
+
+    Some(value.replace("&amp;", "&"))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GoogleDriveError {
+    #[error("The file is private. Share it with anyone who has the link")]
+    PrivateFile,
+    #[error("The download quota of the file is exceeded. Try again in 24 hours")]
+    QuotaExceeded,
+    #[error("The file does not exist")]
+    NotFound,
+    #[error("Google Drive returned a page instead of the file")]
+    UnexpectedPage,
+    #[error("Google Drive kept asking to confirm the download")]
+    Unconfirmed,
+}
+
+impl GoogleDriveError {
+    pub fn failure_code(&self) -> FailureCode {
+        match self {
+            Self::PrivateFile => FailureCode::AccessDenied,
+            Self::QuotaExceeded => FailureCode::QuotaExceeded,
+            Self::NotFound | Self::UnexpectedPage | Self::Unconfirmed => FailureCode::Download,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_confirm_token() {
+        let base = url::Url::parse("https://drive.google.com/uc?export=download&id=abc").unwrap();
+
+        let page = r#"<html><body>
+            <form id="download-form" action="https://drive.usercontent.google.com/download" method="get">
+            <input type="submit" id="uc-download-link" value="Download anyway"/>
+            <input type="hidden" name="id" value="abc">
+            <input type="hidden" name="export" value="download">
+            <input type="hidden" name="confirm" value="t">
+            </form></body></html>"#;
+        assert_eq!(
+            confirm_form_url(page, &base).unwrap().as_str(),
+            "https://drive.usercontent.google.com/download?id=abc&export=download&confirm=t"
+        );
+
+        let page =
+            r#"<a id="uc-download-link" href="/uc?export=download&amp;confirm=Ab-1_c&amp;id=abc">"#;
+        assert!(confirm_form_url(page, &base).is_none());
+        assert_eq!(confirm_link_token(page).unwrap().1, "Ab-1_c");
+    }
+
+    #[test]
+    fn tells_why_drive_refuses() {
+        let ok = reqwest::StatusCode::OK;
+
+        assert!(matches!(
+            page_error(
+                "Too many users have viewed or downloaded this file recently",
+                reqwest::StatusCode::FORBIDDEN
+            ),
+            Some(GoogleDriveError::QuotaExceeded)
+        ));
+        assert!(matches!(
+            page_error("<html>You need access</html>", ok),
+            Some(GoogleDriveError::PrivateFile)
+        ));
+        assert!(matches!(
+            page_error("", reqwest::StatusCode::NOT_FOUND),
+            Some(GoogleDriveError::NotFound)
+        ));
+        assert!(page_error("<html>Virus scan warning</html>", ok).is_none());
+    }
+}
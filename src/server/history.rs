@@ -0,0 +1,41 @@
+//! Append-only history of everything that happened to a task.
+//!
+//! Unlike the status of a task, entries are never overwritten. They are deleted together with their task.
+use super::task::{FailureReason, Status, TerminationSignal};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Who or what caused an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    /// A request of a user, e.g. cancel
+    User,
+    /// The timeout of the task
+    Timeout,
+    /// The server itself, e.g. the scheduler, the retry policy or a restart
+    System,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "event", content = "content")]
+pub enum HistoryEvent {
+    StatusChanged {
+        status: Status,
+    },
+    /// Only recorded for tasks that did not finish yet
+    CancelRequested,
+    /// Attempts are counted from 1
+    AttemptStarted {
+        attempt: u32,
+    },
+    /// The attempt failed. Followed by a retry or the final status
+    AttemptFailed {
+        attempt: u32,
+        reason: FailureReason,
+    },
+    /// A signal is about to be sent to the process group of the OS process to stop it
+    SignalSent {
+        signal: TerminationSignal,
+    },
+}
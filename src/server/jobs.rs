@@ -0,0 +1,619 @@
+//! Job types that run scripts as OS processes, defined in a TOML config file.
+//!
+//! Relative paths in the config file are relative to the directory of the config file.
+//! OS processes start with an empty environment, except for the variables listed in `inherit_env`
+//! and the ones set in `env`.
+//!
+//! ```toml
+//! [interpreters]
+//! python = "python3"
+//!
+//! [jobs.gs_log_to_locust_converter]
+//! description = "Converts GS log files to the Locust log format"
+//! interpreter = "python"
+//! script = "ML_ETL/GS/Logfiles/GSLogToLocustConverter.py"
+//! args = ["--directory", "{project_dir}", "--force"]
+//! working_dir = "{project_dir}"
+//! inherit_env = ["PATH", "LANG"]
+//! env = { LOG_LEVEL = "{level}" }
+//! timeout_secs = 600
+//! termination_grace_secs = 10
+//! priority = "normal"
+//! interactive = false
+//!
+//! [jobs.gs_log_to_locust_converter.params.level]
+//! default = "info"
+//! allowed_values = ["debug", "info"]
+//!
+//! [jobs.gs_log_to_locust_converter.retry]
+//! max_attempts = 2
+//!
+//! [jobs.gs_log_to_locust_converter.limits]
+//! memory_mb = 2048
+//!
+//! [download.retry]
+//! max_attempts = 3
+//!
+//! [download.limits]
+//! download_mb = 2048
+//! ```
+use super::{
+    download::ArchiveLimits, limits::ResourceLimits, retry::RetryPolicy, scheduler::Priority,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+/// Placeholder replaced with the directory of the project the job runs on
+pub const PROJECT_DIR_PLACEHOLDER: &str = "project_dir";
+/// Placeholder replaced with the name of the project the job runs on
+pub const PROJECT_NAME_PLACEHOLDER: &str = "project_name";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobsConfig {
+    /// Programs by name. Merged into the default interpreters
+    #[serde(default)]
+    interpreters: BTreeMap<String, String>,
+    #[serde(default)]
+    jobs: HashMap<String, JobDefinition>,
+    #[serde(default)]
+    download: DownloadConfig,
+}
+
+/// Applies to every download task
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownloadConfig {
+    #[serde(default = "default_download_retry")]
+    pub retry: RetryPolicy,
+    /// Defaults to [`ArchiveLimits::default`]
+    #[serde(default)]
+    pub limits: ArchiveLimits,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            retry: default_download_retry(),
+            limits: ArchiveLimits::default(),
+        }
+    }
+}
+
+/// Google Drive downloads fail transiently, so they are retried unless configured otherwise
+fn default_download_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobDefinition {
+    /// Shown to clients listing the available jobs
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Name of an entry in `[interpreters]` or a program that runs the script.
+    /// Resolved to the program when the config is loaded
+    #[serde(default = "default_interpreter")]
+    pub interpreter: String,
+    /// Script passed as first argument to the interpreter
+    pub script: PathBuf,
+    /// Arguments passed after the script. May contain `{placeholders}`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory of the OS process. May contain `{placeholders}`.
+    /// Defaults to the directory of the config file
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// Variables of the server's environment passed to the OS process
+    #[serde(default)]
+    pub inherit_env: Vec<String>,
+    /// Variables set for the OS process. Values may contain `{placeholders}`
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Time between SIGTERM and SIGKILL when the OS process is stopped after a timeout or cancel
+    #[serde(default = "default_termination_grace_secs")]
+    pub termination_grace_secs: u64,
+    /// Priority in the scheduler's queue unless the client submits one
+    #[serde(default)]
+    pub priority: Priority,
+    /// Parameters a client may submit. Each one can be used as a placeholder in [`JobDefinition::args`]
+    #[serde(default)]
+    pub params: BTreeMap<String, ParamDefinition>,
+    /// Defaults to a single attempt
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Defaults to no limits
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Whether clients may send input to stdin while the job runs. Otherwise stdin is empty
+    #[serde(default)]
+    pub interactive: bool,
+    /// Directory of the config file. Relative paths are resolved against it
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ParamDefinition {
+    /// Shown to clients listing the available jobs
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Used if the client does not submit the parameter. Parameters without a default are required
+    #[serde(default)]
+    pub default: Option<String>,
+    /// If set, the submitted value must be one of these
+    #[serde(default)]
+    pub allowed_values: Option<Vec<String>>,
+}
+
+fn default_timeout_secs() -> u64 {
+    600
+}
+
+fn default_termination_grace_secs() -> u64 {
+    10
+}
+
+fn default_interpreter() -> String {
+    String::from("python")
+}
+
+/// Overridden by `[interpreters]`, e.g. with `python = "python"` on Windows
+fn default_interpreters() -> BTreeMap<String, String> {
+    BTreeMap::from([(String::from("python"), String::from("python3"))])
+}
+
+impl JobDefinition {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn termination_grace(&self) -> Duration {
+        Duration::from_secs(self.termination_grace_secs)
+    }
+
+    /// Checks the submitted params and fills in the defaults.
+    pub fn resolve_params(
+        &self,
+        mut params: HashMap<String, String>,
+    ) -> Result<BTreeMap<String, String>, JobParamsError> {
+        if let Some(unknown) = params.keys().find(|name| !self.params.contains_key(*name)) {
+            return Err(JobParamsError::Unknown(unknown.clone()));
+        }
+
+        let mut resolved = BTreeMap::new();
+
+        for (name, definition) in self.params.iter() {
+            let value = params
+                .remove(name)
+                .or_else(|| definition.default.clone())
+                .ok_or_else(|| JobParamsError::Missing(name.clone()))?;
+
+            if let Some(allowed_values) = &definition.allowed_values {
+                if !allowed_values.contains(&value) {
+                    return Err(JobParamsError::NotAllowed {
+                        param: name.clone(),
+                        value,
+                    });
+                }
+            }
+
+            resolved.insert(name.clone(), value);
+        }
+
+        Ok(resolved)
+    }
+
+    /// Builds the arguments of the OS process. `params` must come from [`JobDefinition::resolve_params`].
+    pub fn render_args(
+        &self,
+        project_dir: &Path,
+        project_name: &str,
+        params: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let script = self.base_dir.join(&self.script);
+
+        let mut args = vec![script.to_string_lossy().to_string()];
+
+        args.extend(
+            self.args
+                .iter()
+                .map(|arg| render_placeholders(arg, project_dir, project_name, params)),
+        );
+
+        args
+    }
+
+    /// `params` must come from [`JobDefinition::resolve_params`].
+    pub fn render_working_dir(
+        &self,
+        project_dir: &Path,
+        project_name: &str,
+        params: &BTreeMap<String, String>,
+    ) -> PathBuf {
+        match &self.working_dir {
+            Some(working_dir) => self.base_dir.join(render_placeholders(
+                working_dir,
+                project_dir,
+                project_name,
+                params,
+            )),
+            None => self.base_dir.clone(),
+        }
+    }
+
+    /// The complete environment of the OS process. `params` must come from [`JobDefinition::resolve_params`].
+    pub fn render_env(
+        &self,
+        project_dir: &Path,
+        project_name: &str,
+        params: &BTreeMap<String, String>,
+    ) -> BTreeMap<String, String> {
+        let mut env: BTreeMap<String, String> = self
+            .inherit_env
+            .iter()
+            .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+            .collect();
+
+        env.extend(self.env.iter().map(|(name, value)| {
+            (
+                name.clone(),
+                render_placeholders(value, project_dir, project_name, params),
+            )
+        }));
+
+        env
+    }
+
+    /// Every placeholder must be known and params must not shadow the built-in placeholders.
+    fn validate(&self, job_type: &str) -> Result<(), JobsConfigError> {
+        self.retry
+            .validate()
+            .map_err(|reason| JobsConfigError::InvalidRetryPolicy {
+                job_type: job_type.to_string(),
+                reason,
+            })?;
+
+        self.limits
+            .validate()
+            .map_err(|reason| JobsConfigError::InvalidResourceLimits {
+                job_type: job_type.to_string(),
+                reason,
+            })?;
+
+        for name in self.params.keys() {
+            if name == PROJECT_DIR_PLACEHOLDER || name == PROJECT_NAME_PLACEHOLDER {
+                return Err(JobsConfigError::ReservedParam {
+                    job_type: job_type.to_string(),
+                    param: name.clone(),
+                });
+            }
+        }
+
+        let templates = self
+            .args
+            .iter()
+            .chain(self.working_dir.iter())
+            .chain(self.env.values());
+
+        for template in templates {
+            for placeholder in placeholders(template) {
+                let known = placeholder == PROJECT_DIR_PLACEHOLDER
+                    || placeholder == PROJECT_NAME_PLACEHOLDER
+                    || self.params.contains_key(placeholder);
+
+                if !known {
+                    return Err(JobsConfigError::UnknownPlaceholder {
+                        job_type: job_type.to_string(),
+                        placeholder: placeholder.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Names between `{` and `}`
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+}
+
+/// Replaces the built-in placeholders and the params in `template`.
+fn render_placeholders(
+    template: &str,
+    project_dir: &Path,
+    project_name: &str,
+    params: &BTreeMap<String, String>,
+) -> String {
+    let project_dir = project_dir.to_string_lossy();
+
+    render(template, |placeholder: &str| match placeholder {
+        PROJECT_DIR_PLACEHOLDER => Some(project_dir.to_string()),
+        PROJECT_NAME_PLACEHOLDER => Some(project_name.to_string()),
+        _ => params.get(placeholder).cloned(),
+    })
+}
+
+/// Replaces every `{placeholder}` known by `lookup`. Unknown placeholders are kept as is.
+fn render<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+
+        let after = &rest[start + 1..];
+        match after.find('}').and_then(|end| {
+            let value = lookup(&after[..end])?;
+            Some((end, value))
+        }) {
+            Some((end, value)) => {
+                rendered.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+
+    rendered
+}
+
+/// All the jobs that can be run. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<BTreeMap<String, Arc<JobDefinition>>>,
+    download: Arc<DownloadConfig>,
+}
+
+impl JobRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, JobsConfigError> {
+        let path = std::path::absolute(path).map_err(JobsConfigError::Io)?;
+        let content = std::fs::read_to_string(&path).map_err(JobsConfigError::Io)?;
+
+        let base_dir = path.parent().unwrap_or(Path::new("/"));
+
+        Self::parse_in(&content, base_dir)
+    }
+
+    /// Relative paths are relative to the current directory.
+    pub fn parse(content: &str) -> Result<Self, JobsConfigError> {
+        Self::parse_in(content, Path::new(""))
+    }
+
+    fn parse_in(content: &str, base_dir: &Path) -> Result<Self, JobsConfigError> {
+        let mut config: JobsConfig = toml::from_str(content).map_err(JobsConfigError::Toml)?;
+
+        let mut interpreters = default_interpreters();
+        interpreters.append(&mut config.interpreters);
+
+        for (job_type, definition) in config.jobs.iter_mut() {
+            definition.validate(job_type)?;
+
+            if let Some(program) = interpreters.get(&definition.interpreter) {
+                definition.interpreter = program.clone();
+            }
+
+            definition.base_dir = base_dir.to_path_buf();
+        }
+
+        config
+            .download
+            .retry
+            .validate()
+            .map_err(|reason| JobsConfigError::InvalidRetryPolicy {
+                job_type: String::from("download"),
+                reason,
+            })?;
+
+        config.download.limits.validate().map_err(|reason| {
+            JobsConfigError::InvalidResourceLimits {
+                job_type: String::from("download"),
+                reason,
+            }
+        })?;
+
+        let jobs = config
+            .jobs
+            .into_iter()
+            .map(|(job_type, definition)| (job_type, Arc::new(definition)))
+            .collect();
+
+        Ok(Self {
+            jobs: Arc::new(jobs),
+            download: Arc::new(config.download),
+        })
+    }
+
+    pub fn download(&self) -> &DownloadConfig {
+        &self.download
+    }
+
+    pub fn get(&self, job_type: &str) -> Option<Arc<JobDefinition>> {
+        self.jobs.get(job_type).cloned()
+    }
+
+    /// Sorted by job type
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<JobDefinition>)> {
+        self.jobs.iter()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobsConfigError {
+    #[error("Io error: {0}")]
+    Io(std::io::Error),
+    #[error("Toml error: {0}")]
+    Toml(toml::de::Error),
+    #[error("Job {job_type}: unknown placeholder {{{placeholder}}}")]
+    UnknownPlaceholder {
+        job_type: String,
+        placeholder: String,
+    },
+    #[error("Job {job_type}: param {param} shadows a built-in placeholder")]
+    ReservedParam { job_type: String, param: String },
+    #[error("Job {job_type}: invalid retry policy: {reason}")]
+    InvalidRetryPolicy {
+        job_type: String,
+        reason: &'static str,
+    },
+    #[error("Job {job_type}: invalid resource limits: {reason}")]
+    InvalidResourceLimits {
+        job_type: String,
+        reason: &'static str,
+    },
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+#[serde(tag = "error", content = "param")]
+pub enum JobParamsError {
+    #[error("Unknown param {0}")]
+    Unknown(String),
+    #[error("Missing param {0}")]
+    Missing(String),
+    #[error("Value {value} is not allowed for param {param}")]
+    NotAllowed { param: String, value: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [jobs.convert]
+        script = "convert.py"
+        args = ["--directory", "{project_dir}", "--level={level}", "{project_name}"]
+
+        [jobs.convert.params.level]
+        default = "info"
+        allowed_values = ["debug", "info"]
+    "#;
+
+    #[test]
+    fn renders_args_with_defaults_and_submitted_params() {
+        let registry = JobRegistry::parse(CONFIG).expect("Failed to parse config");
+        let job = registry.get("convert").expect("Job not found");
+
+        let params = job.resolve_params(HashMap::new()).unwrap();
+        let args = job.render_args(Path::new("projects/p"), "p", &params);
+        assert_eq!(
+            args,
+            [
+                "convert.py",
+                "--directory",
+                "projects/p",
+                "--level=info",
+                "p"
+            ]
+        );
+
+        let params = job
+            .resolve_params(HashMap::from([("level".into(), "debug".into())]))
+            .unwrap();
+        let args = job.render_args(Path::new("projects/p"), "p", &params);
+        assert_eq!(args[3], "--level=debug");
+    }
+
+    #[test]
+    fn resolves_interpreters_and_env() {
+        let config = r#"
+            [interpreters]
+            node = "/usr/bin/node"
+
+            [jobs.convert]
+            script = "convert.py"
+            working_dir = "{project_dir}/logs"
+            env = { PROJECT = "{project_name}" }
+
+            [jobs.render]
+            interpreter = "node"
+            script = "render.js"
+        "#;
+
+        let registry = JobRegistry::parse(config).expect("Failed to parse config");
+        let convert = registry.get("convert").expect("Job not found");
+        let render = registry.get("render").expect("Job not found");
+
+        assert_eq!(convert.interpreter, "python3");
+        assert_eq!(render.interpreter, "/usr/bin/node");
+
+        let params = BTreeMap::new();
+        assert_eq!(
+            convert.render_working_dir(Path::new("/projects/p"), "p", &params),
+            Path::new("/projects/p/logs")
+        );
+        assert_eq!(
+            convert.render_env(Path::new("/projects/p"), "p", &params),
+            BTreeMap::from([(String::from("PROJECT"), String::from("p"))])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let registry = JobRegistry::parse(CONFIG).expect("Failed to parse config");
+        let job = registry.get("convert").expect("Job not found");
+
+        assert!(matches!(
+            job.resolve_params(HashMap::from([("other".into(), "x".into())])),
+            Err(JobParamsError::Unknown(_))
+        ));
+        assert!(matches!(
+            job.resolve_params(HashMap::from([("level".into(), "trace".into())])),
+            Err(JobParamsError::NotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_zero_limits() {
+        let config = r#"
+            [jobs.convert]
+            script = "convert.py"
+
+            [jobs.convert.limits]
+            open_files = 0
+        "#;
+
+        assert!(matches!(
+            JobRegistry::parse(config),
+            Err(JobsConfigError::InvalidResourceLimits { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let config = r#"
+            [jobs.convert]
+            script = "convert.py"
+            args = ["{nope}"]
+        "#;
+
+        assert!(matches!(
+            JobRegistry::parse(config),
+            Err(JobsConfigError::UnknownPlaceholder { .. })
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! Resource limits for the OS processes of jobs, applied with `setrlimit` before the script starts.
+//!
+//! ```toml
+//! [jobs.gs_log_to_locust_converter.limits]
+//! memory_mb = 2048
+//! cpu_secs = 600
+//! file_size_mb = 1024
+//! open_files = 256
+//! ```
+//!
+//! Exceeding the CPU time or file size limit kills the OS process with `SIGXCPU` or `SIGXFSZ`,
+//! which is reported as [`ResourceLimit`]. An OS process that ignores `SIGXCPU` is killed with `SIGKILL`
+//! at the hard limit a second later, which is reported as exceeding the CPU time as well.
+//!
+//! The memory and open files limits are enforced but never reported. Exceeding them makes allocations
+//! or opening files fail inside the OS process, which usually exits with a non-zero exit code.
+//! So does exceeding the file size limit in python, which ignores `SIGXFSZ`.
+//!
+//! Limits are only supported on unix.
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+use utoipa::ToSchema;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// Virtual address space of the OS process
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// CPU time of the OS process, not wall time. See `timeout_secs` for wall time
+    #[serde(default)]
+    pub cpu_secs: Option<u64>,
+    /// Size of every file the OS process writes
+    #[serde(default)]
+    pub file_size_mb: Option<u64>,
+    /// Number of file descriptors the OS process may open
+    #[serde(default)]
+    pub open_files: Option<u64>,
+}
+
+/// A limit the OS process was killed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceLimit {
+    CpuTime,
+    FileSize,
+}
+
+impl ResourceLimit {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::CpuTime => "CPU time",
+            Self::FileSize => "file size",
+        }
+    }
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.memory_mb.is_none()
+            && self.cpu_secs.is_none()
+            && self.file_size_mb.is_none()
+            && self.open_files.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let limits = [
+            self.memory_mb,
+            self.cpu_secs,
+            self.file_size_mb,
+            self.open_files,
+        ];
+
+        if limits.contains(&Some(0)) {
+            return Err("limits must be at least 1");
+        }
+
+        Ok(())
+    }
+
+    /// Applies the limits to the OS process in the forked child before it executes the command.
+    /// The spawn fails if a limit can not be set, e.g. if it exceeds the hard limit of the server.
+    #[cfg(unix)]
+    pub fn apply(&self, command: &mut std::process::Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return;
+        }
+
+        let memory = self.memory_mb.map(|memory_mb| memory_mb.saturating_mul(MB));
+        let cpu_secs = self.cpu_secs;
+        let file_size = self
+            .file_size_mb
+            .map(|file_size_mb| file_size_mb.saturating_mul(MB));
+        let open_files = self.open_files;
+
+        let setrlimit = |resource, soft: u64, hard: u64| {
+            let limit = libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            };
+
+            // SAFETY: setrlimit is async-signal-safe and `limit` outlives the call
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        };
+
+        // SAFETY: The closure only calls setrlimit and does not allocate
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(memory) = memory {
+                    setrlimit(libc::RLIMIT_AS, memory, memory)?;
+                }
+
+                if let Some(cpu_secs) = cpu_secs {
+                    // SIGXCPU is sent at the soft limit. SIGKILL follows a second later if it is ignored
+                    setrlimit(libc::RLIMIT_CPU, cpu_secs, cpu_secs.saturating_add(1))?;
+                }
+
+                if let Some(file_size) = file_size {
+                    setrlimit(libc::RLIMIT_FSIZE, file_size, file_size)?;
+                }
+
+                if let Some(open_files) = open_files {
+                    setrlimit(libc::RLIMIT_NOFILE, open_files, open_files)?;
+                }
+
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _command: &mut std::process::Command) {
+        if !self.is_empty() {
+            tracing::warn!("Resource limits are not supported on this platform");
+        }
+    }
+
+    /// The limit the OS process was killed for, if any.
+    ///
+    /// With a CPU time limit, any `SIGKILL` is taken for the hard limit,
+    /// although it may have been sent by someone else, e.g. the OOM killer.
+    #[cfg(unix)]
+    pub fn exceeded(&self, exit_status: &ExitStatus) -> Option<ResourceLimit> {
+        use std::os::unix::process::ExitStatusExt;
+
+        match exit_status.signal()? {
+            libc::SIGXCPU | libc::SIGKILL if self.cpu_secs.is_some() => {
+                Some(ResourceLimit::CpuTime)
+            }
+            libc::SIGXFSZ if self.file_size_mb.is_some() => Some(ResourceLimit::FileSize),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn exceeded(&self, _exit_status: &ExitStatus) -> Option<ResourceLimit> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn killed_by(signal: i32) -> ExitStatus {
+        ExitStatus::from_raw(signal)
+    }
+
+    #[test]
+    fn reports_the_cpu_time_hard_limit() {
+        let limits = ResourceLimits {
+            cpu_secs: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            limits.exceeded(&killed_by(libc::SIGXCPU)),
+            Some(ResourceLimit::CpuTime)
+        );
+        assert_eq!(
+            limits.exceeded(&killed_by(libc::SIGKILL)),
+            Some(ResourceLimit::CpuTime)
+        );
+        assert_eq!(limits.exceeded(&killed_by(libc::SIGXFSZ)), None);
+        assert_eq!(
+            ResourceLimits::default().exceeded(&killed_by(libc::SIGKILL)),
+            None
+        );
+    }
+}
@@ -1,6 +1,19 @@
+pub mod db;
+pub mod download;
+pub mod events;
 pub mod extractors;
+pub mod google_drive;
+pub mod history;
+pub mod jobs;
+pub mod limits;
+pub mod output;
+pub mod pipeline;
+pub mod progress;
 pub mod response;
+pub mod retry;
+pub mod schedule;
+pub mod scheduler;
+pub mod source;
 pub mod state;
 pub mod task;
-pub mod utils;
 pub mod ws;
@@ -0,0 +1,223 @@
+//! Output of OS processes, stored per task so that it outlives the task.
+//!
+//! Every task gets a directory `<output_dir>/<task_id>` with a `stdout.log` and a `stderr.log`.
+//! Every line is prefixed with the time it was read: `2024-01-01T12:00:00.000000Z <line>`.
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    fn file_name(&self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout.log",
+            Self::Stderr => "stderr.log",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OutputLine {
+    /// When the line was read
+    #[schema(value_type = String, format = DateTime)]
+    pub timestamp: DateTime<Utc>,
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// Where the output of all tasks is stored. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct OutputStore {
+    dir: PathBuf,
+}
+
+impl OutputStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn task_dir(&self, task_id: &str) -> PathBuf {
+        self.dir.join(task_id)
+    }
+
+    /// Opens the file of the stream for appending. Attempts of the same task share the file.
+    pub async fn writer(
+        &self,
+        task_id: &str,
+        stream: OutputStream,
+    ) -> std::io::Result<OutputWriter> {
+        let task_dir = self.task_dir(task_id);
+
+        tokio::fs::create_dir_all(&task_dir).await?;
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(task_dir.join(stream.file_name()))
+            .await?;
+
+        Ok(OutputWriter {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Lines of the given stream, or of both streams ordered by time.
+    /// Only the last `tail` lines if given.
+    ///
+    /// Tasks without output, e.g. downloads, have no lines.
+    pub async fn read(
+        &self,
+        task_id: &str,
+        stream: Option<OutputStream>,
+        tail: Option<usize>,
+    ) -> std::io::Result<Vec<OutputLine>> {
+        let streams = match stream {
+            Some(stream) => vec![stream],
+            None => vec![OutputStream::Stdout, OutputStream::Stderr],
+        };
+
+        let mut lines = Vec::new();
+
+        for stream in streams {
+            lines.extend(self.read_stream(task_id, stream, tail).await?);
+        }
+
+        // Stable, so lines with the same timestamp keep their order within a stream
+        lines.sort_by_key(|line| line.timestamp);
+
+        if let Some(tail) = tail {
+            lines.drain(..lines.len().saturating_sub(tail));
+        }
+
+        Ok(lines)
+    }
+
+    async fn read_stream(
+        &self,
+        task_id: &str,
+        stream: OutputStream,
+        tail: Option<usize>,
+    ) -> std::io::Result<VecDeque<OutputLine>> {
+        let path = self.task_dir(task_id).join(stream.file_name());
+
+        let file = match File::open(&path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut buf = Vec::new();
+        let mut lines = VecDeque::new();
+
+        loop {
+            buf.clear();
+
+            if reader.read_until(b'\n', &mut buf).await? == 0 {
+                break;
+            }
+
+            let Some(line) = parse_line(&buf, stream) else {
+                continue;
+            };
+
+            if tail.is_some_and(|tail| lines.len() == tail) {
+                lines.pop_front();
+            }
+
+            lines.push_back(line);
+        }
+
+        Ok(lines)
+    }
+
+    /// Deletes the output of a task. Missing output is not an error.
+    pub async fn delete(&self, task_id: &str) -> std::io::Result<()> {
+        match tokio::fs::remove_dir_all(self.task_dir(task_id)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Appends timestamped lines to the file of a stream.
+pub struct OutputWriter {
+    file: BufWriter<File>,
+}
+
+impl OutputWriter {
+    /// Flushes every line, so that readers see the output while the task is running.
+    pub async fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+        self.file
+            .write_all(format!("{timestamp} {line}\n").as_bytes())
+            .await?;
+
+        self.file.flush().await
+    }
+}
+
+fn parse_line(raw: &[u8], stream: OutputStream) -> Option<OutputLine> {
+    let raw = String::from_utf8_lossy(raw);
+    let (timestamp, line) = raw.trim_end_matches('\n').split_once(' ')?;
+
+    Some(OutputLine {
+        timestamp: DateTime::parse_from_rfc3339(timestamp).ok()?.to_utc(),
+        stream,
+        line: line.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_interleaved_streams_and_tails() {
+        let dir = std::env::temp_dir().join(format!("job_hub_output_{}", uuid::Uuid::new_v4()));
+        let store = OutputStore::new(&dir);
+
+        let mut stdout = store.writer("1", OutputStream::Stdout).await.unwrap();
+        let mut stderr = store.writer("1", OutputStream::Stderr).await.unwrap();
+
+        stdout.write_line("first").await.unwrap();
+        stderr.write_line("second").await.unwrap();
+        stdout.write_line("third").await.unwrap();
+
+        let lines = store.read("1", None, None).await.unwrap();
+        let texts: Vec<_> = lines.iter().map(|line| line.line.as_str()).collect();
+        assert_eq!(texts, ["first", "second", "third"]);
+        assert_eq!(lines[1].stream, OutputStream::Stderr);
+
+        let tail = store
+            .read("1", Some(OutputStream::Stdout), Some(1))
+            .await
+            .unwrap();
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail[0].line, "third");
+
+        assert!(store.read("2", None, None).await.unwrap().is_empty());
+
+        store.delete("1").await.unwrap();
+        assert!(store.read("1", None, None).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
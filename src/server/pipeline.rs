@@ -0,0 +1,187 @@
+//! Pipelines are tasks chained with `depends_on` edges.
+//!
+//! A step runs once all of its upstream steps succeeded.
+//! If an upstream step fails or is canceled, the step is skipped and never runs.
+use super::download::{CollisionPolicy, ExtractMode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct PipelineStepSpec {
+    /// Unique name of the step in the pipeline
+    #[schema(example = "download")]
+    pub name: String,
+    /// Names of the steps that must succeed before this step runs
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// What the step does
+    pub action: StepAction,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepAction {
+    /// Download a zip file from a link into a project
+    Download {
+        project_name: String,
+        /// `google_drive_share_link` is the former name
+        #[serde(alias = "google_drive_share_link")]
+        url: String,
+        #[serde(default)]
+        extract_mode: ExtractMode,
+        /// Only applies to [`ExtractMode::Flatten`]
+        #[serde(default)]
+        on_collision: CollisionPolicy,
+        /// Expected hex sha256 of the downloaded file
+        #[serde(default)]
+        sha256: Option<String>,
+        /// Expected hex md5 of the downloaded file. Only one of `sha256` and `md5` may be given
+        #[serde(default)]
+        md5: Option<String>,
+    },
+    /// Run a job type from the jobs config on a project
+    Job {
+        job_type: String,
+        project_name: String,
+        #[serde(default)]
+        params: HashMap<String, String>,
+    },
+}
+
+impl StepAction {
+    pub fn project_name(&self) -> &str {
+        match self {
+            Self::Download { project_name, .. } | Self::Job { project_name, .. } => project_name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum PipelineStatus {
+    Running,
+    /// Every step succeeded
+    Succeeded,
+    /// At least one step failed. Its downstream steps were skipped
+    Failed,
+    Canceled,
+    /// The server stopped while the pipeline was running
+    Interrupted,
+}
+
+impl PipelineStatus {
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Running)
+    }
+}
+
+/// Checks the graph of steps and returns the indices of the steps in an order
+/// where every step comes after its upstream steps.
+pub fn execution_order(steps: &[PipelineStepSpec]) -> Result<Vec<usize>, PipelineGraphError> {
+    if steps.is_empty() {
+        return Err(PipelineGraphError::Empty);
+    }
+
+    let mut indices = HashMap::new();
+
+    for (i, step) in steps.iter().enumerate() {
+        if indices.insert(step.name.as_str(), i).is_some() {
+            return Err(PipelineGraphError::DuplicateStep(step.name.clone()));
+        }
+    }
+
+    let mut in_degree = vec![0; steps.len()];
+    let mut downstream = vec![Vec::new(); steps.len()];
+
+    for (i, step) in steps.iter().enumerate() {
+        for upstream in step.depends_on.iter() {
+            let upstream = *indices.get(upstream.as_str()).ok_or_else(|| {
+                PipelineGraphError::UnknownDependency {
+                    step: step.name.clone(),
+                    depends_on: upstream.clone(),
+                }
+            })?;
+
+            in_degree[i] += 1;
+            downstream[upstream].push(i);
+        }
+    }
+
+    let mut ready: VecDeque<usize> = (0..steps.len()).filter(|&i| in_degree[i] == 0).collect();
+    let mut order = Vec::with_capacity(steps.len());
+
+    while let Some(i) = ready.pop_front() {
+        order.push(i);
+
+        for &next in downstream[i].iter() {
+            in_degree[next] -= 1;
+
+            if in_degree[next] == 0 {
+                ready.push_back(next);
+            }
+        }
+    }
+
+    if order.len() != steps.len() {
+        return Err(PipelineGraphError::Cycle);
+    }
+
+    Ok(order)
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum PipelineGraphError {
+    #[error("Pipeline has no steps")]
+    Empty,
+    #[error("Step {0} is defined more than once")]
+    DuplicateStep(String),
+    #[error("Step {step} depends on unknown step {depends_on}")]
+    UnknownDependency { step: String, depends_on: String },
+    #[error("Steps depend on each other in a cycle")]
+    Cycle,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str, depends_on: &[&str]) -> PipelineStepSpec {
+        PipelineStepSpec {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(ToString::to_string).collect(),
+            action: StepAction::Job {
+                job_type: String::from("job"),
+                project_name: String::from("project"),
+                params: HashMap::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn upstream_steps_come_first() {
+        let steps = [
+            step("analyze", &["convert"]),
+            step("convert", &["download"]),
+            step("download", &[]),
+        ];
+
+        assert_eq!(execution_order(&steps).unwrap(), [2, 1, 0]);
+    }
+
+    #[test]
+    fn rejects_invalid_graphs() {
+        assert!(matches!(
+            execution_order(&[step("a", &["b"]), step("b", &["a"])]),
+            Err(PipelineGraphError::Cycle)
+        ));
+        assert!(matches!(
+            execution_order(&[step("a", &["c"])]),
+            Err(PipelineGraphError::UnknownDependency { .. })
+        ));
+        assert!(matches!(
+            execution_order(&[step("a", &[]), step("a", &[])]),
+            Err(PipelineGraphError::DuplicateStep(_))
+        ));
+    }
+}
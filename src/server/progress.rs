@@ -0,0 +1,174 @@
+//! Progress of running tasks.
+//!
+//! Downloads report the bytes received and the files extracted. Jobs report progress by printing
+//! lines to stdout:
+//!
+//! ```text
+//! ::progress:: 42% converting
+//! ::progress:: 3/10 files
+//! ```
+//!
+//! The label after the numbers is optional. Progress lines are not part of the output of the task.
+//! Progress is only kept in memory while the task runs.
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use utoipa::ToSchema;
+
+const PROGRESS_PREFIX: &str = "::progress::";
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Progress {
+    /// What the task is doing, e.g. `downloading` or `extracting`
+    #[schema(example = "downloading")]
+    pub phase: String,
+    #[schema(example = 52_428_800)]
+    pub current: u64,
+    /// Missing if unknown, e.g. without a `Content-Length`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 104_857_600)]
+    pub total: Option<u64>,
+    /// Missing if the total is unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 50.0)]
+    pub percent: Option<f64>,
+    /// Estimated seconds until the phase is done, from the rate since the phase started.
+    /// Missing if the total is unknown or nothing happened yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 30)]
+    pub eta_secs: Option<u64>,
+}
+
+struct Phase {
+    label: String,
+    started_at: Instant,
+    current: u64,
+    total: Option<u64>,
+}
+
+/// Shared between a task and its handle. Cheap to clone.
+///
+/// Synchronous, so that blocking code like extracting archives can report progress.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    phase: Arc<Mutex<Option<Phase>>>,
+}
+
+impl ProgressReporter {
+    /// Starts a new phase at 0, or updates the current phase if the label did not change.
+    pub fn report(&self, label: &str, current: u64, total: Option<u64>) {
+        let mut phase = self.phase.lock().expect("Progress lock poisoned");
+
+        match phase.as_mut() {
+            Some(phase) if phase.label == label => {
+                phase.current = current;
+                phase.total = total;
+            }
+            _ => {
+                *phase = Some(Phase {
+                    label: label.to_string(),
+                    started_at: Instant::now(),
+                    current,
+                    total,
+                })
+            }
+        }
+    }
+
+    /// Advances the current phase by `delta`.
+    pub fn advance(&self, delta: u64) {
+        let mut phase = self.phase.lock().expect("Progress lock poisoned");
+
+        if let Some(phase) = phase.as_mut() {
+            phase.current += delta;
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.phase.lock().expect("Progress lock poisoned") = None;
+    }
+
+    pub fn get(&self) -> Option<Progress> {
+        let phase = self.phase.lock().expect("Progress lock poisoned");
+        let phase = phase.as_ref()?;
+
+        let total = phase.total.filter(|total| *total > 0);
+        let current = total.map_or(phase.current, |total| phase.current.min(total));
+
+        let percent = total.map(|total| current as f64 * 100.0 / total as f64);
+
+        let eta_secs = total.filter(|_| current > 0).map(|total| {
+            let elapsed = phase.started_at.elapsed().as_secs_f64();
+
+            (elapsed * (total - current) as f64 / current as f64).round() as u64
+        });
+
+        Some(Progress {
+            phase: phase.label.clone(),
+            current: phase.current,
+            total: phase.total,
+            percent,
+            eta_secs,
+        })
+    }
+
+    /// Reports a progress line of a job. Returns `false` if the line is regular output.
+    pub fn report_line(&self, line: &str) -> bool {
+        let Some((current, total, label)) = parse_line(line) else {
+            return false;
+        };
+
+        self.report(label.unwrap_or("running"), current, Some(total));
+
+        true
+    }
+}
+
+/// `current`, `total` and the optional label of a progress line. Percentages are rounded to whole percents of 100.
+fn parse_line(line: &str) -> Option<(u64, u64, Option<&str>)> {
+    let rest = line.strip_prefix(PROGRESS_PREFIX)?.trim();
+
+    let (numbers, label) = match rest.split_once(char::is_whitespace) {
+        Some((numbers, label)) => (numbers, Some(label.trim()).filter(|l| !l.is_empty())),
+        None => (rest, None),
+    };
+
+    if let Some(percent) = numbers.strip_suffix('%') {
+        let percent: f64 = percent.parse().ok()?;
+
+        if !(0.0..=100.0).contains(&percent) {
+            return None;
+        }
+
+        return Some((percent.round() as u64, 100, label));
+    }
+
+    let (current, total) = numbers.split_once('/')?;
+
+    Some((current.parse().ok()?, total.parse().ok()?, label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress_lines() {
+        assert_eq!(
+            parse_line("::progress:: 42.5% converting logs"),
+            Some((43, 100, Some("converting logs")))
+        );
+        assert_eq!(parse_line("::progress:: 3/10"), Some((3, 10, None)));
+        assert_eq!(parse_line("::progress:: 120%"), None);
+        assert_eq!(parse_line("progress 3/10"), None);
+
+        let reporter = ProgressReporter::default();
+        assert!(reporter.report_line("::progress:: 1/4 files"));
+
+        let progress = reporter.get().unwrap();
+        assert_eq!(progress.phase, "files");
+        assert_eq!(progress.percent, Some(25.0));
+    }
+}
@@ -20,6 +20,7 @@ impl From<ApiError> for ApiErrorResponse {
             ApiError::ApiKeyMissing => (StatusCode::BAD_REQUEST, "Api key missing"),
             ApiError::ApiKeyInvalid => (StatusCode::UNAUTHORIZED, "Api key invalid"),
             ApiError::QueryInvalid => (StatusCode::BAD_REQUEST, "Query invalid"),
+            ApiError::BodyInvalid => (StatusCode::BAD_REQUEST, "Body invalid"),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "Not found"),
             ApiError::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -53,6 +54,7 @@ pub enum ApiError {
     ApiKeyMissing,
     ApiKeyInvalid,
     QueryInvalid,
+    BodyInvalid,
     NotFound,
     InternalServerError,
 }
@@ -0,0 +1,169 @@
+//! Retry policies for tasks that fail transiently.
+//!
+//! ```toml
+//! [jobs.gs_log_to_locust_converter.retry]
+//! max_attempts = 3
+//! initial_backoff_ms = 1000
+//! max_backoff_ms = 60000
+//! multiplier = 2.0
+//! jitter = 0.5
+//! retry_on = ["non_zero_exit"]
+//! ```
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Failures a task may be retried on. Cancellations are never retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryableFailure {
+    /// The download failed to connect, or the server answered with a 5xx or 429
+    Network,
+    /// The OS process exited with a non-zero exit code
+    NonZeroExit,
+    /// The attempt ran longer than the timeout of the task
+    Timeout,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Including the first attempt. `1` disables retries
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Backoff before the second attempt
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the backoff before jitter is applied
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// The backoff is multiplied by this after every attempt
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    /// Fraction of the backoff that is randomized, between `0.0` and `1.0`
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryableFailure>,
+}
+
+fn default_max_attempts() -> u32 {
+    1
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_multiplier() -> f64 {
+    2.0
+}
+
+fn default_jitter() -> f64 {
+    0.5
+}
+
+fn default_retry_on() -> Vec<RetryableFailure> {
+    vec![RetryableFailure::Network, RetryableFailure::NonZeroExit]
+}
+
+impl Default for RetryPolicy {
+    /// A single attempt
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            multiplier: default_multiplier(),
+            jitter: default_jitter(),
+            retry_on: default_retry_on(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether another attempt follows the given failed attempt.
+    /// `failure` is [`None`] for attempts that succeeded or failed in a way that is never retried.
+    pub fn should_retry(&self, attempt: u32, failure: Option<RetryableFailure>) -> bool {
+        attempt < self.max_attempts
+            && failure.is_some_and(|failure| self.retry_on.contains(&failure))
+    }
+
+    /// Exponential backoff with jitter before the attempt after `attempt`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+
+        let backoff = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_backoff_ms as f64);
+
+        let jitter = backoff * self.jitter * rand::thread_rng().gen_range(0.0..=1.0);
+
+        Duration::from_millis((backoff - jitter) as u64)
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be at least 1");
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err("jitter must be between 0.0 and 1.0");
+        }
+
+        if self.multiplier < 1.0 {
+            return Err("multiplier must be at least 1.0");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_until_capped_and_jitter_only_shortens_it() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 300,
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+
+        for _ in 0..100 {
+            let backoff = policy.backoff(2);
+            assert!(backoff >= Duration::from_millis(100) && backoff <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn retries_only_listed_failures_until_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            retry_on: vec![RetryableFailure::Network],
+            ..Default::default()
+        };
+
+        assert!(policy.should_retry(1, Some(RetryableFailure::Network)));
+        assert!(policy.should_retry(2, Some(RetryableFailure::Network)));
+        assert!(!policy.should_retry(3, Some(RetryableFailure::Network)));
+        assert!(!policy.should_retry(1, Some(RetryableFailure::NonZeroExit)));
+        assert!(!policy.should_retry(1, None));
+    }
+}
@@ -0,0 +1,85 @@
+//! Cron schedules that run a job type on a project.
+//!
+//! Expressions have 5 fields (`min hour day month weekday`) or 6 to 7 fields with leading seconds
+//! and trailing years. Times are UTC.
+//!
+//! A schedule fires once its next fire time after its last run has passed.
+//! Runs missed while the server was down are caught up with a single run.
+use super::db::ScheduleRow;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+/// A parsed cron expression.
+#[derive(Debug, Clone)]
+pub struct CronExpression {
+    schedule: cron::Schedule,
+}
+
+impl FromStr for CronExpression {
+    type Err = InvalidCronExpression;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = expression.trim();
+
+        // The cron crate expects seconds as the first field
+        let expression = if expression.split_whitespace().count() == 5 {
+            format!("0 {expression}")
+        } else {
+            expression.to_string()
+        };
+
+        let schedule = cron::Schedule::from_str(&expression)
+            .map_err(|err| InvalidCronExpression(err.to_string()))?;
+
+        Ok(Self { schedule })
+    }
+}
+
+impl CronExpression {
+    /// The first fire time strictly after `after`. [`None`] if the expression never fires again.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+}
+
+/// The next time the schedule fires. [`None`] if it is paused or never fires again.
+///
+/// May be in the past if the server was down when the schedule should have fired.
+pub fn next_fire_at(schedule: &ScheduleRow) -> Option<DateTime<Utc>> {
+    if schedule.paused {
+        return None;
+    }
+
+    let cron: CronExpression = schedule.cron.parse().ok()?;
+
+    let anchor = schedule
+        .last_run_at
+        .map_or(schedule.active_since, |last_run_at| {
+            last_run_at.max(schedule.active_since)
+        });
+
+    cron.next_after(anchor)
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid cron expression: {0}")]
+pub struct InvalidCronExpression(pub String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn five_field_expressions_fire_on_the_minute() {
+        let nightly: CronExpression = "30 2 * * *".parse().expect("Failed to parse");
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            nightly.next_after(after),
+            Some(Utc.with_ymd_and_hms(2024, 1, 2, 2, 30, 0).unwrap())
+        );
+
+        assert!("not a cron".parse::<CronExpression>().is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+/// Tasks with a higher priority leave the queue first.
+/// Tasks with the same priority leave the queue in the order they entered it.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Limits how many tasks run at the same time. Tasks over the limit wait in a priority queue.
+///
+/// Cheap to clone.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Mutex<SchedulerInner>>,
+}
+
+struct SchedulerInner {
+    max_concurrent: usize,
+    running: usize,
+    /// Incremented for every queued task. Keeps the queue FIFO for equal priorities.
+    next_seq: u64,
+    queue: BinaryHeap<Waiting>,
+}
+
+struct Waiting {
+    priority: Priority,
+    seq: u64,
+    task_id: String,
+    tx: oneshot::Sender<Permit>,
+}
+
+impl Waiting {
+    /// Dropping the [`Ticket`] is how a task leaves the queue
+    fn abandoned(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl Ord for Waiting {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiting {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiting {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiting {}
+
+/// A place in the queue. Resolves to a [`Permit`] once the task may run.
+pub type Ticket = oneshot::Receiver<Permit>;
+
+/// Allows a task to run. The slot is given to the next queued task on drop.
+pub struct Permit {
+    /// [`None`] if the permit never reached its task
+    scheduler: Option<Scheduler>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SchedulerInner {
+                max_concurrent: max_concurrent.max(1),
+                running: 0,
+                next_seq: 0,
+                queue: BinaryHeap::new(),
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerInner> {
+        // The lock is never held across a panic point that leaves the state inconsistent
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Puts the task at the end of its priority in the queue.
+    pub fn enqueue(&self, task_id: String, priority: Priority) -> Ticket {
+        let (tx, rx) = oneshot::channel();
+
+        let mut inner = self.lock();
+
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+
+        inner.queue.push(Waiting {
+            priority,
+            seq,
+            task_id,
+            tx,
+        });
+
+        self.dispatch(&mut inner);
+
+        rx
+    }
+
+    /// 1-based position of the task in the queue. [`None`] if the task is not queued.
+    pub fn queue_position(&self, task_id: &str) -> Option<usize> {
+        let inner = self.lock();
+
+        let waiting = inner
+            .queue
+            .iter()
+            .find(|waiting| waiting.task_id == task_id && !waiting.abandoned())?;
+
+        let ahead = inner
+            .queue
+            .iter()
+            .filter(|other| !other.abandoned() && *other > waiting)
+            .count();
+
+        Some(ahead + 1)
+    }
+
+    fn release(&self) {
+        let mut inner = self.lock();
+
+        inner.running -= 1;
+
+        self.dispatch(&mut inner);
+    }
+
+    /// Hands out permits while there are free slots
+    fn dispatch(&self, inner: &mut SchedulerInner) {
+        while inner.running < inner.max_concurrent {
+            let Some(waiting) = inner.queue.pop() else {
+                break;
+            };
+
+            let permit = Permit {
+                scheduler: Some(self.clone()),
+            };
+
+            match waiting.tx.send(permit) {
+                Ok(_) => {
+                    inner.running += 1;
+                }
+                Err(mut permit) => {
+                    tracing::debug!(id=%waiting.task_id, "Task left the queue before it could run");
+
+                    // Releasing here would lock the scheduler again
+                    permit.scheduler = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn higher_priority_runs_first_and_abandoned_tickets_are_skipped() {
+        let scheduler = Scheduler::new(1);
+
+        let running = scheduler.enqueue("0".into(), Priority::Normal);
+        let low = scheduler.enqueue("1".into(), Priority::Low);
+        let canceled = scheduler.enqueue("2".into(), Priority::High);
+        let mut high = scheduler.enqueue("3".into(), Priority::High);
+
+        let permit = running.await.expect("First task should run immediately");
+
+        assert_eq!(scheduler.queue_position("1"), Some(3));
+        assert_eq!(scheduler.queue_position("2"), Some(1));
+        assert_eq!(scheduler.queue_position("3"), Some(2));
+
+        drop(canceled);
+
+        assert_eq!(scheduler.queue_position("2"), None);
+        assert_eq!(scheduler.queue_position("3"), Some(1));
+        assert_eq!(scheduler.queue_position("1"), Some(2));
+        assert!(high.try_recv().is_err());
+
+        drop(permit);
+
+        let permit = high.await.expect("High priority task should run next");
+        assert_eq!(scheduler.queue_position("1"), Some(1));
+
+        drop(permit);
+
+        low.await.expect("Low priority task should run last");
+    }
+}
@@ -0,0 +1,307 @@
+//! Providers that files are downloaded from.
+//!
+//! Share links are recognised by their host and normalised to a URL that serves the file itself
+//! instead of a preview page. Links of unknown hosts are downloaded as they are.
+use base64::Engine;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadSource {
+    GoogleDrive,
+    Dropbox,
+    OneDrive,
+    /// An asset of a GitHub release
+    GithubRelease,
+    /// Any other https URL
+    Https,
+}
+
+/// A link resolved to the URL its file is downloaded from
+#[derive(Debug, Clone)]
+pub struct ResolvedLink {
+    pub source: DownloadSource,
+    pub download_url: url::Url,
+}
+
+impl DownloadSource {
+    /// The provider of a link, by its host
+    pub fn of(link: &url::Url) -> Self {
+        match link.host_str().unwrap_or_default() {
+            "drive.google.com" | "docs.google.com" => Self::GoogleDrive,
+            "dropbox.com" | "www.dropbox.com" => Self::Dropbox,
+            "1drv.ms" | "onedrive.live.com" => Self::OneDrive,
+            host if host.ends_with(".sharepoint.com") => Self::OneDrive,
+            "github.com" => Self::GithubRelease,
+            _ => Self::Https,
+        }
+    }
+
+    /// Recognises the provider of a link and normalises it to a direct download.
+    pub fn resolve(link: url::Url) -> Result<ResolvedLink, DownloadSourceError> {
+        if link.scheme() != "https" {
+            return Err(DownloadSourceError::InvalidScheme);
+        }
+
+        if link.host_str().is_none() {
+            return Err(DownloadSourceError::NoHost);
+        }
+
+        let source = Self::of(&link);
+
+        let download_url = match source {
+            Self::GoogleDrive => google_drive(&link).map_err(DownloadSourceError::GoogleDrive)?,
+            Self::Dropbox => dropbox(link).map_err(DownloadSourceError::Dropbox)?,
+            Self::OneDrive => one_drive(link).map_err(DownloadSourceError::OneDrive)?,
+            Self::GithubRelease => {
+                github_release(link).map_err(DownloadSourceError::GithubRelease)?
+            }
+            Self::Https => link,
+        };
+
+        Ok(ResolvedLink {
+            source,
+            download_url,
+        })
+    }
+}
+
+/// Supports `/file/d/<id>/...`, `/open?id=<id>` and `/uc?id=<id>` links.
+/// Google Docs and Sheets are exported as zipped HTML.
+fn google_drive(link: &url::Url) -> Result<url::Url, GoogleDriveLinkError> {
+    let segments: Vec<_> = link
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    let id = match segments.as_slice() {
+        ["file", "d", id, ..] => id.to_string(),
+        [kind @ ("document" | "spreadsheets"), "d", id, ..] => {
+            let mut export_url = link.clone();
+            export_url.set_path(&format!("/{kind}/d/{id}/export"));
+            export_url.set_query(Some("format=zip"));
+
+            return Ok(export_url);
+        }
+        ["presentation" | "forms" | "drawings", ..] => {
+            return Err(GoogleDriveLinkError::UnsupportedDocument)
+        }
+        ["open" | "uc"] => link
+            .query_pairs()
+            .find(|(key, _)| key == "id")
+            .map(|(_, id)| id.into_owned())
+            .ok_or(GoogleDriveLinkError::NoId)?,
+        ["drive", "folders", ..] | ["drive", "u", _, "folders", ..] => {
+            return Err(GoogleDriveLinkError::Folder)
+        }
+        _ => return Err(GoogleDriveLinkError::NoId),
+    };
+
+    if id.is_empty() {
+        return Err(GoogleDriveLinkError::NoId);
+    }
+
+    let mut download_url =
+        url::Url::parse("https://drive.google.com/uc?export=download").expect("hardcoded url");
+    download_url.query_pairs_mut().append_pair("id", &id);
+
+    Ok(download_url)
+}
+
+/// Share links of files and folders are served directly with `dl=1`. Folders are zipped by Dropbox.
+fn dropbox(mut link: url::Url) -> Result<url::Url, DropboxLinkError> {
+    let is_share_link = link
+        .path_segments()
+        .and_then(|mut segments| segments.next())
+        .is_some_and(|first| matches!(first, "s" | "scl" | "sh"));
+
+    if !is_share_link {
+        return Err(DropboxLinkError::NotAShareLink);
+    }
+
+    let query: Vec<(String, String)> = link
+        .query_pairs()
+        .filter(|(key, _)| key != "dl" && key != "raw")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    link.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("dl", "1");
+
+    Ok(link)
+}
+
+/// Personal share links go through the shares API, which redirects to the file.
+/// SharePoint links are served directly with `download=1`.
+fn one_drive(mut link: url::Url) -> Result<url::Url, OneDriveLinkError> {
+    let host = link.host_str().unwrap_or_default();
+    let path = link.path();
+
+    if host.ends_with(".sharepoint.com") {
+        // Share links look like /:u:/g/personal/...
+        if !path.starts_with("/:") {
+            return Err(OneDriveLinkError::NotAShareLink);
+        }
+
+        link.query_pairs_mut().append_pair("download", "1");
+
+        return Ok(link);
+    }
+
+    if path.trim_matches('/').is_empty() && link.query().is_none() {
+        return Err(OneDriveLinkError::NotAShareLink);
+    }
+
+    // https://learn.microsoft.com/en-us/onedrive/developer/rest-api/api/shares_get#encoding-sharing-urls
+    let share_id = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(link.as_str());
+
+    url::Url::parse(&format!(
+        "https://api.onedrive.com/v1.0/shares/u!{share_id}/root/content"
+    ))
+    .map_err(|_| OneDriveLinkError::NotAShareLink)
+}
+
+/// `/<owner>/<repo>/releases/download/<tag>/<asset>` and `/<owner>/<repo>/releases/latest/download/<asset>`
+fn github_release(link: url::Url) -> Result<url::Url, GithubReleaseLinkError> {
+    let segments: Vec<_> = link
+        .path_segments()
+        .map(|segments| segments.collect())
+        .unwrap_or_default();
+
+    match segments.as_slice() {
+        [_, _, "releases", "download", tag, asset] if !tag.is_empty() && !asset.is_empty() => {
+            Ok(link)
+        }
+        [_, _, "releases", "latest", "download", asset] if !asset.is_empty() => Ok(link),
+        [_, _, "releases", ..] => Err(GithubReleaseLinkError::NoAsset),
+        _ => Err(GithubReleaseLinkError::NotARelease),
+    }
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum DownloadSourceError {
+    #[error("Invalid scheme, only https is supported")]
+    InvalidScheme,
+    #[error("No host")]
+    NoHost,
+    #[error("Google Drive: {0}")]
+    GoogleDrive(GoogleDriveLinkError),
+    #[error("Dropbox: {0}")]
+    Dropbox(DropboxLinkError),
+    #[error("OneDrive: {0}")]
+    OneDrive(OneDriveLinkError),
+    #[error("GitHub: {0}")]
+    GithubRelease(GithubReleaseLinkError),
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+pub enum GoogleDriveLinkError {
+    #[error("No file id in the link")]
+    NoId,
+    #[error("Folders can not be downloaded")]
+    Folder,
+    #[error("Only Google Docs and Sheets can be exported")]
+    UnsupportedDocument,
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+pub enum DropboxLinkError {
+    #[error("Not a share link")]
+    NotAShareLink,
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+pub enum OneDriveLinkError {
+    #[error("Not a share link")]
+    NotAShareLink,
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+pub enum GithubReleaseLinkError {
+    #[error("Not a link to a release")]
+    NotARelease,
+    #[error("No asset in the link")]
+    NoAsset,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(link: &str) -> Result<ResolvedLink, DownloadSourceError> {
+        DownloadSource::resolve(url::Url::parse(link).unwrap())
+    }
+
+    fn download_url(link: &str) -> String {
+        resolve(link).unwrap().download_url.to_string()
+    }
+
+    #[test]
+    fn normalises_google_drive_links() {
+        let expected = "https://drive.google.com/uc?export=download&id=abc";
+
+        assert_eq!(
+            download_url("https://drive.google.com/file/d/abc/view?usp=sharing"),
+            expected
+        );
+        assert_eq!(
+            download_url("https://drive.google.com/open?id=abc"),
+            expected
+        );
+        assert_eq!(download_url("https://docs.google.com/uc?id=abc"), expected);
+        assert_eq!(
+            download_url("https://docs.google.com/document/d/abc/edit"),
+            "https://docs.google.com/document/d/abc/export?format=zip"
+        );
+        assert!(matches!(
+            resolve("https://drive.google.com/drive/folders/abc"),
+            Err(DownloadSourceError::GoogleDrive(
+                GoogleDriveLinkError::Folder
+            ))
+        ));
+        assert!(matches!(
+            resolve("http://drive.google.com/file/d/abc/view"),
+            Err(DownloadSourceError::InvalidScheme)
+        ));
+    }
+
+    #[test]
+    fn normalises_other_providers() {
+        assert_eq!(
+            download_url("https://www.dropbox.com/scl/fi/x/logs.zip?rlkey=k&dl=0"),
+            "https://www.dropbox.com/scl/fi/x/logs.zip?rlkey=k&dl=1"
+        );
+        assert!(matches!(
+            resolve("https://www.dropbox.com/home"),
+            Err(DownloadSourceError::Dropbox(_))
+        ));
+
+        let one_drive = resolve("https://1drv.ms/u/s!abc").unwrap();
+        assert_eq!(one_drive.source, DownloadSource::OneDrive);
+        assert_eq!(
+            one_drive.download_url.as_str(),
+            "https://api.onedrive.com/v1.0/shares/u!aHR0cHM6Ly8xZHJ2Lm1zL3UvcyFhYmM/root/content"
+        );
+        assert_eq!(
+            download_url("https://contoso.sharepoint.com/:u:/g/personal/x/abc"),
+            "https://contoso.sharepoint.com/:u:/g/personal/x/abc?download=1"
+        );
+
+        let asset = "https://github.com/owner/repo/releases/download/v1.0/logs.tar.gz";
+        assert_eq!(download_url(asset), asset);
+        assert!(matches!(
+            resolve("https://github.com/owner/repo/blob/main/logs.tar.gz"),
+            Err(DownloadSourceError::GithubRelease(
+                GithubReleaseLinkError::NotARelease
+            ))
+        ));
+
+        let plain = resolve("https://example.com/logs.zip").unwrap();
+        assert_eq!(plain.source, DownloadSource::Https);
+        assert_eq!(plain.download_url.as_str(), "https://example.com/logs.zip");
+    }
+}
@@ -1,17 +1,39 @@
-use super::task::{Handle, Status, Task};
+use super::{
+    db::{
+        AttemptRow, Database, DbError, HistoryRow, NewPipelineStep, NewSchedule, NewTask,
+        PipelineRow, RetentionPolicy, ScheduleRow, TaskFilter, TaskRow, TransitionRow,
+    },
+    download::{Checksum, DownloadRequest, ExtractOptions},
+    events::{TaskEvent, TaskEventBus, TaskEventKind},
+    history::{EventSource, HistoryEvent},
+    jobs::{JobParamsError, JobRegistry},
+    output::{OutputLine, OutputStore, OutputStream, OutputWriter},
+    pipeline::{self, PipelineGraphError, PipelineStatus, PipelineStepSpec, StepAction},
+    progress::{Progress, ProgressReporter},
+    retry::RetryPolicy,
+    schedule::{self, CronExpression, InvalidCronExpression},
+    scheduler::{Priority, Scheduler},
+    source::{DownloadSource, DownloadSourceError, ResolvedLink},
+    task::{Handle, OsProcess, Status, StdinError, StdinInput, Task, TaskKind},
+};
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde::Serialize;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     ops::Deref,
     path::PathBuf,
-    sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
-    },
+    sync::Arc,
+    time::Duration,
 };
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, BufReader},
-    sync::RwLock,
+    sync::{mpsc, Notify, RwLock},
+    task::JoinHandle,
 };
+use utoipa::ToSchema;
+
+/// Job type behind the `/api/gs_log_to_locust_converter` endpoint
+pub const GS_LOG_TO_LOCUST_CONVERTER_JOB: &str = "gs_log_to_locust_converter";
 
 /// I want my [`ApiState`] to be [`Clone`] and [`Send`] and [`Sync`] as is.
 /// So I'm wrapping [`ApiState::inner`] in an [`Arc`].
@@ -21,15 +43,255 @@ pub struct ApiState {
 }
 
 impl ApiState {
-    pub fn new(api_token: String, projects_dir: String) -> Self {
+    pub fn new(
+        api_token: String,
+        projects_dir: String,
+        db: Database,
+        jobs: JobRegistry,
+        scheduler: Scheduler,
+        output: OutputStore,
+    ) -> Self {
         Self {
-            inner: Arc::new(ApiStateInner::new(api_token, projects_dir)),
+            inner: Arc::new(ApiStateInner::new(
+                api_token,
+                projects_dir,
+                db,
+                jobs,
+                scheduler,
+                output,
+            )),
         }
     }
 
     pub fn api_token_valid(&self, api_token: &str) -> bool {
         api_token == self.api_token
     }
+
+    /// Creates a task for every step and runs them in dependency order.
+    ///
+    /// Every step is validated before any task is created.
+    pub async fn run_pipeline(
+        &self,
+        chat_id: String,
+        steps: Vec<PipelineStepSpec>,
+        priority: Option<Priority>,
+    ) -> Result<String, RunPipelineError> {
+        let order = pipeline::execution_order(&steps)?;
+
+        let runs = steps
+            .iter()
+            .map(|step| self.prepare_pipeline_step(&steps, step, priority))
+            .collect::<Result<Vec<_>, RunPipelineError>>()?;
+
+        let mut prepared = Vec::with_capacity(steps.len());
+
+        for (step, (kind, job_type, params, run, priority)) in steps.iter().zip(runs) {
+            let project_name = step.action.project_name();
+
+            let created = async {
+                // Let's create a directory for the project
+                if let TaskRun::Download { request, .. } = &run {
+                    tokio::fs::create_dir_all(&request.project_dir).await?;
+                }
+
+                let task = self
+                    .create_task(
+                        chat_id.clone(),
+                        kind,
+                        job_type.as_deref(),
+                        project_name,
+                        params,
+                        &run,
+                    )
+                    .await?;
+
+                Ok::<_, RunPipelineError>(task)
+            };
+
+            let task = match created.await {
+                Ok(task) => task,
+                Err(err) => {
+                    ApiStateInner::cancel_unstarted(&self.tasks, prepared).await;
+                    return Err(err);
+                }
+            };
+
+            prepared.push(PipelineStepRun {
+                name: step.name.clone(),
+                task_id: task.id().to_string(),
+                upstream: Vec::new(),
+                task: Some(task),
+                run: Some(run),
+                priority,
+                outcome: StepOutcome::Pending,
+            });
+        }
+
+        for (i, step) in steps.iter().enumerate() {
+            prepared[i].upstream = step
+                .depends_on
+                .iter()
+                .filter_map(|name| steps.iter().position(|other| &other.name == name))
+                .collect();
+        }
+
+        let new_steps: Vec<NewPipelineStep> = steps
+            .iter()
+            .zip(prepared.iter())
+            .map(|(step, run)| NewPipelineStep {
+                name: &step.name,
+                task_id: &run.task_id,
+                depends_on: &step.depends_on,
+            })
+            .collect();
+
+        let id = match self.db.insert_pipeline(&chat_id, &new_steps).await {
+            Ok(id) => id,
+            Err(err) => {
+                ApiStateInner::cancel_unstarted(&self.tasks, prepared).await;
+                return Err(err.into());
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(1);
+
+        self.pipelines
+            .write()
+            .await
+            .insert(id.clone(), PipelineData { chat_id, tx });
+
+        let state = self.clone();
+        let pipeline_id = id.clone();
+        tokio::spawn(async move {
+            state.drive_pipeline(pipeline_id, prepared, order, rx).await;
+        });
+
+        Ok(id)
+    }
+
+    /// Starts steps once their upstream steps succeeded, skips them once an upstream step did not.
+    #[tracing::instrument(skip_all, fields(pipeline_id=id))]
+    async fn drive_pipeline(
+        self,
+        id: String,
+        mut steps: Vec<PipelineStepRun>,
+        order: Vec<usize>,
+        mut cancel_rx: mpsc::Receiver<()>,
+    ) {
+        let mut running = FuturesUnordered::new();
+        let mut canceled = false;
+
+        loop {
+            // Upstream steps come first in `order`, so skips propagate in a single pass
+            for &i in order.iter() {
+                if steps[i].outcome != StepOutcome::Pending {
+                    continue;
+                }
+
+                let upstream: Vec<StepOutcome> = steps[i]
+                    .upstream
+                    .iter()
+                    .map(|&u| steps[u].outcome)
+                    .collect();
+
+                let skip = canceled
+                    || upstream.iter().any(|outcome| {
+                        matches!(outcome, StepOutcome::Failed | StepOutcome::Skipped)
+                    });
+
+                if skip {
+                    tracing::debug!(step = %steps[i].name, "Skipping step");
+
+                    if let Some(task) = steps[i].task.take() {
+                        let task_id = task.id().to_string();
+                        let source = if canceled {
+                            EventSource::User
+                        } else {
+                            EventSource::System
+                        };
+
+                        task.cancel(source).await;
+                        ApiStateInner::remove_task(&self.tasks, &task_id).await;
+                    }
+
+                    if let Err(err) = self.db.mark_pipeline_step_skipped(&id, i).await {
+                        tracing::error!(%err, "Failed to persist skipped step");
+                    }
+
+                    steps[i].outcome = StepOutcome::Skipped;
+                    continue;
+                }
+
+                if upstream
+                    .iter()
+                    .all(|outcome| *outcome == StepOutcome::Succeeded)
+                {
+                    let (Some(task), Some(run)) = (steps[i].task.take(), steps[i].run.take())
+                    else {
+                        continue;
+                    };
+
+                    tracing::debug!(step = %steps[i].name, "Starting step");
+
+                    let handle = self.spawn_task(task, steps[i].priority, run);
+                    running.push(async move { (i, handle.await) });
+
+                    steps[i].outcome = StepOutcome::Running;
+                }
+            }
+
+            if running.is_empty() {
+                break;
+            }
+
+            tokio::select! {
+                Some((i, status)) = running.next() => {
+                    let succeeded = matches!(&status, Ok(status) if status.is_success());
+
+                    tracing::debug!(step = %steps[i].name, %succeeded, "Step finished");
+
+                    steps[i].outcome = if succeeded {
+                        StepOutcome::Succeeded
+                    } else {
+                        StepOutcome::Failed
+                    };
+                },
+                Some(_) = cancel_rx.recv(), if !canceled => {
+                    tracing::info!("Received cancel signal");
+
+                    canceled = true;
+
+                    for step in steps.iter().filter(|step| step.outcome == StepOutcome::Running) {
+                        ApiStateInner::record_cancel_request(&self.db, &step.task_id).await;
+
+                        if let Some(task_data) = self.tasks.read().await.get(&step.task_id) {
+                            task_data.handle.send_cancel_signal();
+                        }
+                    }
+                },
+            }
+        }
+
+        let status = if canceled {
+            PipelineStatus::Canceled
+        } else if steps
+            .iter()
+            .all(|step| step.outcome == StepOutcome::Succeeded)
+        {
+            PipelineStatus::Succeeded
+        } else {
+            PipelineStatus::Failed
+        };
+
+        tracing::debug!(?status, "Pipeline finished");
+
+        if let Err(err) = self.db.update_pipeline_status(&id, status).await {
+            tracing::error!(%err, "Failed to persist pipeline status");
+        }
+
+        let mut pipelines = self.pipelines.write().await;
+        pipelines.remove(&id);
+    }
 }
 
 /// Collecting relevant data for a task.
@@ -38,24 +300,103 @@ struct TaskData {
     handle: Handle,
 }
 
+/// Collecting relevant data for a running pipeline.
+struct PipelineData {
+    chat_id: String,
+    /// Used to send cancel signal to the pipeline
+    tx: mpsc::Sender<()>,
+}
+
+/// What a task runs once the scheduler lets it.
+enum TaskRun {
+    Download {
+        request: DownloadRequest,
+        timeout: Duration,
+        retry: RetryPolicy,
+    },
+    Process(OsProcess),
+}
+
+impl TaskRun {
+    fn max_attempts(&self) -> u32 {
+        match self {
+            Self::Download { retry, .. } => retry.max_attempts,
+            Self::Process(process) => process.retry.max_attempts,
+        }
+    }
+
+    fn interactive(&self) -> bool {
+        matches!(self, Self::Process(process) if process.interactive)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepOutcome {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// A pipeline step while the pipeline runs.
+struct PipelineStepRun {
+    name: String,
+    task_id: String,
+    /// Indices of the upstream steps
+    upstream: Vec<usize>,
+    /// Taken when the step starts or is skipped
+    task: Option<Task>,
+    /// Taken when the step starts
+    run: Option<TaskRun>,
+    priority: Priority,
+    outcome: StepOutcome,
+}
+
 pub struct ApiStateInner {
     api_token: String,
     /// Contains all the tasks that are currently running.
     /// The key is the task id.
+    /// Finished tasks are removed and only live in [`ApiStateInner::db`].
     tasks: Arc<RwLock<HashMap<String, TaskData>>>,
-    /// I'm not wrapping [`ApiStateInner`] in a lock.
-    /// So it's a good old [`AtomicU32`].
-    current_id: AtomicU32,
+    /// Persists tasks and their status transitions. Also generates the task ids.
+    db: Database,
+    /// Job types that can be run with [`ApiStateInner::run_job_task`].
+    jobs: JobRegistry,
+    /// Every task waits here for a free slot before it runs.
+    scheduler: Scheduler,
+    /// Contains all the pipelines that are currently running.
+    /// The key is the pipeline id.
+    pipelines: RwLock<HashMap<String, PipelineData>>,
+    /// Wakes up [`ApiStateInner::run_schedules`] when schedules are created, paused, resumed or deleted.
+    schedules_changed: Notify,
     projects_dir: String,
+    /// Output of the OS processes of tasks
+    output: OutputStore,
+    /// Status changes and output of running tasks
+    events: TaskEventBus,
 }
 
 impl ApiStateInner {
-    pub fn new(api_token: String, projects_dir: String) -> Self {
+    pub fn new(
+        api_token: String,
+        projects_dir: String,
+        db: Database,
+        jobs: JobRegistry,
+        scheduler: Scheduler,
+        output: OutputStore,
+    ) -> Self {
         Self {
             api_token,
             tasks: Arc::new(RwLock::new(HashMap::new())),
-            current_id: AtomicU32::new(0),
+            db,
+            jobs,
+            scheduler,
+            pipelines: RwLock::new(HashMap::new()),
+            schedules_changed: Notify::new(),
             projects_dir,
+            output,
+            events: TaskEventBus::default(),
         }
     }
 
@@ -63,201 +404,854 @@ impl ApiStateInner {
         uuid::Uuid::new_v4().to_string()
     }
 
-    fn increment_current_task_id(&self) -> u32 {
-        let id = self.current_id.load(Ordering::Relaxed);
+    /// Persists a new task and registers its handle as running.
+    async fn create_task(
+        &self,
+        chat_id: String,
+        kind: TaskKind,
+        job_type: Option<&str>,
+        project_name: &str,
+        params: serde_json::Value,
+        run: &TaskRun,
+    ) -> Result<Task, DbError> {
+        let status = Status::created(kind);
+
+        let id = self
+            .db
+            .insert_task(NewTask {
+                chat_id: &chat_id,
+                kind,
+                job_type,
+                project_name,
+                params,
+                status: &status,
+                max_attempts: run.max_attempts(),
+            })
+            .await?;
+
+        let (task, task_handle) = Task::new(
+            id.clone(),
+            status,
+            self.db.clone(),
+            self.events.clone(),
+            run.interactive(),
+        );
+        let task_data = TaskData {
+            chat_id,
+            handle: task_handle,
+        };
 
-        self.current_id.store(id + 1, Ordering::Relaxed);
+        let mut tasks = self.tasks.write().await;
+        tasks.insert(id, task_data);
 
-        id
+        Ok(task)
     }
 
-    fn project_dir(&self, project_name: &str) -> PathBuf {
-        PathBuf::from(&self.projects_dir).join(project_name)
+    async fn record_cancel_request(db: &Database, id: &str) {
+        let event = HistoryEvent::CancelRequested;
+
+        if let Err(err) = db.append_task_event(id, EventSource::User, &event).await {
+            tracing::error!(%err, %id, "Failed to persist cancel request");
+        }
     }
 
-    pub async fn run_download_task(
-        &self,
-        chat_id: String,
-        download_url: url::Url,
-        project_name: String,
-    ) -> Result<String, std::io::Error> {
-        // Let's create a directory for the project
-        let project_dir = self.project_dir(&project_name);
-        tokio::fs::create_dir_all(&project_dir).await?;
+    /// Cancels the tasks of a pipeline that failed to start.
+    async fn cancel_unstarted(
+        tasks: &RwLock<HashMap<String, TaskData>>,
+        steps: Vec<PipelineStepRun>,
+    ) {
+        for task in steps.into_iter().filter_map(|step| step.task) {
+            let task_id = task.id().to_string();
+            task.cancel(EventSource::System).await;
+            Self::remove_task(tasks, &task_id).await;
+        }
+    }
+
+    /// Waits for the scheduler, then runs the task. Resolves to the final status.
+    fn spawn_task(&self, mut task: Task, priority: Priority, run: TaskRun) -> JoinHandle<Status> {
+        let task_id = task.id().to_string();
+        let ticket = self.scheduler.enqueue(task_id.clone(), priority);
+        let tasks = self.tasks.clone();
+        let output = self.output.clone();
+        let events = self.events.clone();
 
-        let id = self.increment_current_task_id().to_string();
-        let task_id = id.clone();
+        tokio::spawn(async move {
+            let status = match task.wait_for_permit(ticket).await {
+                Ok(_permit) => Self::run_task(task, run, output, events).await,
+                Err(status) => {
+                    // Closes the cancel channel before the tasks are locked for writing
+                    drop(task);
 
-        let timeout = std::time::Duration::from_secs(600);
+                    status
+                }
+            };
 
-        let (task, task_handle) = Task::new(id.clone());
-        let task_data = TaskData {
-            chat_id,
-            handle: task_handle,
+            Self::remove_task(&tasks, &task_id).await;
+
+            status
+        })
+    }
+
+    async fn run_task(
+        task: Task,
+        run: TaskRun,
+        output: OutputStore,
+        events: TaskEventBus,
+    ) -> Status {
+        match run {
+            TaskRun::Download {
+                request,
+                timeout,
+                retry,
+            } => {
+                task.run_download_and_unzip_from_download_url(timeout, retry, request)
+                    .await
+            }
+            TaskRun::Process(process) => {
+                let (stdout_tx, stdout_rx) = tokio::io::duplex(100);
+                let (stderr_tx, stderr_rx) = tokio::io::duplex(100);
+
+                for (stream, rx) in [
+                    (OutputStream::Stdout, stdout_rx),
+                    (OutputStream::Stderr, stderr_rx),
+                ] {
+                    let task_id = task.id().to_string();
+
+                    let writer = match output.writer(&task_id, stream).await {
+                        Ok(writer) => Some(writer),
+                        Err(err) => {
+                            tracing::error!(%err, id=%task_id, ?stream, "Failed to create output file");
+                            None
+                        }
+                    };
+
+                    let events = events.clone();
+                    let progress = (stream == OutputStream::Stdout).then(|| task.progress());
+
+                    tokio::spawn(async move {
+                        Self::record_output(task_id, stream, rx, writer, events, progress).await;
+                    });
+                }
+
+                task.run_os_process(process, Some(stdout_tx), Some(stderr_tx))
+                    .await
+            }
+        }
+    }
+
+    /// Removes a finished task from memory. Its status stays in the database.
+    async fn remove_task(tasks: &RwLock<HashMap<String, TaskData>>, id: &str) {
+        tracing::debug!(%id, "Task finished. Removing it from memory");
+
+        let mut tasks = tasks.write().await;
+        tasks.remove(id);
+    }
+
+    /// Periodically deletes finished tasks according to the given policy. Runs forever.
+    pub async fn run_retention_policy(&self, policy: RetentionPolicy) {
+        let Some(max_age) = policy.max_age else {
+            tracing::info!("No retention policy. Keeping finished tasks forever");
+            return;
         };
 
-        let mut tasks = self.tasks.write().await;
-        tasks.insert(id.clone(), task_data);
+        let mut interval = tokio::time::interval(policy.interval);
 
-        let tasks = self.tasks.clone();
+        loop {
+            interval.tick().await;
 
-        tokio::spawn(async move {
-            task.run_download_and_unzip_from_download_url(timeout, download_url, project_dir)
-                .await;
-
-            // TODO: remove after adding a database.
-            // Keeping task in memory for 15 minutes after it's done.
-            // simulating an in-memory database.
-
-            tracing::debug!(id=%task_id, "Task finished. Waiting 15 minutes before removing it from memory");
-            tokio::time::sleep(std::time::Duration::from_secs(900)).await;
-            tracing::debug!(id=%task_id, "Removing task from memory");
-            let mut tasks = tasks.write().await;
-            tasks.remove(&task_id);
-        });
+            let Ok(max_age) = chrono::Duration::from_std(max_age) else {
+                tracing::error!(?max_age, "Retention max age out of range");
+                return;
+            };
 
-        Ok(id)
+            let before = chrono::Utc::now() - max_age;
+
+            match self.db.delete_pipelines_finished_before(before).await {
+                Ok(deleted) => tracing::debug!(%deleted, "Applied retention policy to pipelines"),
+                Err(err) => tracing::error!(%err, "Failed to apply retention policy to pipelines"),
+            }
+
+            match self.db.delete_tasks_finished_before(before).await {
+                Ok(deleted) => {
+                    for id in deleted.iter() {
+                        if let Err(err) = self.output.delete(id).await {
+                            tracing::error!(%err, %id, "Failed to delete task output");
+                        }
+                    }
+
+                    tracing::debug!(deleted = deleted.len(), "Applied retention policy")
+                }
+                Err(err) => tracing::error!(%err, "Failed to apply retention policy"),
+            }
+        }
     }
 
-    #[tracing::instrument(skip_all, fields(id=task_id))]
-    async fn trace_stdout<R: AsyncRead + Unpin>(task_id: String, stdout_rx: R) {
-        let buf_reader = BufReader::new(stdout_rx);
-        let mut lines = buf_reader.lines();
+    /// Fires schedules once they are due. Never returns.
+    pub async fn run_schedules(&self) {
+        // Upper bound for sleeping. Keeps the loop robust against clock changes
+        let max_sleep = Duration::from_secs(60 * 60);
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            tracing::trace!("{line}");
+        loop {
+            let now = chrono::Utc::now();
+
+            let sleep = match self.fire_due_schedules(now).await {
+                Ok(Some(next_fire_at)) => (next_fire_at - now).to_std().unwrap_or_default(),
+                Ok(None) => max_sleep,
+                Err(err) => {
+                    tracing::error!(%err, "Failed to fire schedules");
+
+                    Duration::from_secs(60)
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep.min(max_sleep)) => {},
+                _ = self.schedules_changed.notified() => {
+                    tracing::debug!("Schedules changed");
+                },
+            }
+        }
+    }
+
+    /// Fires every schedule that is due at `now`. Returns the next time a schedule is due.
+    /// Fails only if the schedules can not be loaded.
+    async fn fire_due_schedules(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>, DbError> {
+        let mut next_fire_at: Option<chrono::DateTime<chrono::Utc>> = None;
+
+        for mut schedule in self.db.schedules(None).await? {
+            let Some(mut fire_at) = schedule::next_fire_at(&schedule) else {
+                continue;
+            };
+
+            if fire_at <= now {
+                // One failing schedule must not hold back the others
+                if let Err(err) = self.fire_schedule(&schedule, now).await {
+                    tracing::error!(%err, schedule_id = schedule.id, "Failed to fire schedule");
+                }
+
+                schedule.last_run_at = Some(now);
+
+                let Some(next) = schedule::next_fire_at(&schedule) else {
+                    continue;
+                };
+
+                fire_at = next;
+            }
+
+            next_fire_at = Some(next_fire_at.map_or(fire_at, |next| next.min(fire_at)));
+        }
+
+        Ok(next_fire_at)
+    }
+
+    #[tracing::instrument(skip_all, fields(schedule_id=schedule.id))]
+    async fn fire_schedule(
+        &self,
+        schedule: &ScheduleRow,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), DbError> {
+        let result = self
+            .run_job_task(
+                schedule.chat_id.clone(),
+                &schedule.job_type,
+                schedule.project_name.clone(),
+                schedule.params.clone(),
+                schedule.priority,
+            )
+            .await;
+
+        match result {
+            Ok(task_id) => {
+                tracing::info!(%task_id, "Schedule fired");
+
+                self.db
+                    .record_schedule_run(&schedule.id, now, Ok(&task_id))
+                    .await
+            }
+            Err(err) => {
+                tracing::warn!(%err, "Schedule fired but failed to create a task");
+
+                self.db
+                    .record_schedule_run(&schedule.id, now, Err(&err.to_string()))
+                    .await
+            }
         }
+    }
 
-        tracing::debug!("Finished reading stdout");
+    /// Validates the job and its params before the schedule is stored.
+    pub async fn create_schedule(
+        &self,
+        chat_id: &str,
+        cron: &str,
+        job_type: &str,
+        project_name: &str,
+        params: HashMap<String, String>,
+        priority: Option<Priority>,
+    ) -> Result<ScheduleRow, CreateScheduleError> {
+        let _: CronExpression = cron.parse()?;
+
+        let job = self
+            .jobs
+            .get(job_type)
+            .ok_or(CreateScheduleError::JobNotFound)?;
+
+        job.resolve_params(params.clone())?;
+
+        if !self.project_dir(project_name).exists() {
+            return Err(CreateScheduleError::ProjectNotFound);
+        }
+
+        let id = self
+            .db
+            .insert_schedule(NewSchedule {
+                chat_id,
+                cron,
+                job_type,
+                project_name,
+                params: &params,
+                priority,
+            })
+            .await?;
+
+        self.schedules_changed.notify_one();
+
+        let schedule = self
+            .db
+            .schedule(&id, chat_id)
+            .await?
+            .ok_or(CreateScheduleError::DbError(DbError::InvalidId))?;
+
+        Ok(schedule)
     }
 
-    #[tracing::instrument(skip_all, fields(id=task_id))]
-    async fn trace_stderr<R: AsyncRead + Unpin>(task_id: String, stderr_rx: R) {
-        let buf_reader = BufReader::new(stderr_rx);
-        let mut lines = buf_reader.lines();
+    pub async fn schedules(&self, chat_id: &str) -> Result<Vec<ScheduleRow>, DbError> {
+        self.db.schedules(Some(chat_id)).await
+    }
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            tracing::error!("{line}");
+    /// Returns the updated schedule. [`None`] if the schedule does not exist for the given chat.
+    pub async fn set_schedule_paused(
+        &self,
+        id: &str,
+        chat_id: &str,
+        paused: bool,
+    ) -> Result<Option<ScheduleRow>, DbError> {
+        if !self.db.set_schedule_paused(id, chat_id, paused).await? {
+            return Ok(None);
         }
 
-        tracing::debug!("Finished reading stderr");
+        self.schedules_changed.notify_one();
+
+        self.db.schedule(id, chat_id).await
     }
 
-    pub async fn run_gs_log_to_locust_converter_task(
+    /// Returns `false` if the schedule does not exist for the given chat.
+    pub async fn delete_schedule(&self, id: &str, chat_id: &str) -> Result<bool, DbError> {
+        let deleted = self.db.delete_schedule(id, chat_id).await?;
+
+        if deleted {
+            self.schedules_changed.notify_one();
+        }
+
+        Ok(deleted)
+    }
+
+    fn project_dir(&self, project_name: &str) -> PathBuf {
+        PathBuf::from(&self.projects_dir).join(project_name)
+    }
+
+    pub async fn run_download_task(
         &self,
         chat_id: String,
+        link: ResolvedLink,
         project_name: String,
-    ) -> Result<String, GsLogToLocustConverterError> {
+        options: ExtractOptions,
+        expected_checksum: Option<Checksum>,
+        priority: Option<Priority>,
+    ) -> Result<String, RunDownloadTaskError> {
+        // Let's create a directory for the project
         let project_dir = self.project_dir(&project_name);
+        tokio::fs::create_dir_all(&project_dir).await?;
 
-        if !project_dir.exists() {
-            return Err(GsLogToLocustConverterError::NotFound);
-        }
+        let params = Self::download_params(&link, &options, expected_checksum.as_ref());
+        let run = self.prepare_download(link, project_dir, options, expected_checksum);
 
-        let id = self.increment_current_task_id().to_string();
-        let task_id = id.clone();
+        let task = self
+            .create_task(
+                chat_id,
+                TaskKind::Download,
+                None,
+                &project_name,
+                params,
+                &run,
+            )
+            .await?;
 
-        let timeout = std::time::Duration::from_secs(600);
+        let id = task.id().to_string();
 
-        let (task, task_handle) = Task::new(id.clone());
+        self.spawn_task(task, priority.unwrap_or_default(), run);
 
-        // TODO: Move to tests
-        // {
-        // Test canceling the task before running it. and expect it to be canceled immediately after running.
-        // task_handle.cancel().await;
+        Ok(id)
+    }
 
-        // Test dropping the handle before running the task. and expect it to be canceled immediately after running.
-        // drop(task_handle);
-        // }
+    /// Stored with the task so clients can see how it downloads
+    fn download_params(
+        link: &ResolvedLink,
+        options: &ExtractOptions,
+        expected_checksum: Option<&Checksum>,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "source": link.source,
+            "download_url": link.download_url.as_str(),
+            "extract_mode": options.extract_mode,
+            "on_collision": options.on_collision,
+            "expected_checksum": expected_checksum,
+        })
+    }
 
-        let task_data = TaskData {
-            chat_id,
-            handle: task_handle,
+    fn prepare_download(
+        &self,
+        link: ResolvedLink,
+        project_dir: PathBuf,
+        options: ExtractOptions,
+        expected_checksum: Option<Checksum>,
+    ) -> TaskRun {
+        TaskRun::Download {
+            request: DownloadRequest {
+                link,
+                project_dir,
+                limits: self.jobs.download().limits,
+                options,
+                expected_checksum,
+            },
+            timeout: Duration::from_secs(600),
+            retry: self.jobs.download().retry.clone(),
+        }
+    }
+
+    /// Resolves the params and builds the OS process of a job. Does not check the project.
+    fn prepare_job(
+        &self,
+        job_type: &str,
+        project_name: &str,
+        params: HashMap<String, String>,
+        priority: Option<Priority>,
+    ) -> Result<(BTreeMap<String, String>, TaskRun, Priority), RunJobError> {
+        let job = self.jobs.get(job_type).ok_or(RunJobError::JobNotFound)?;
+
+        let params = job.resolve_params(params)?;
+
+        let project_dir = self.project_dir(project_name);
+
+        let run = TaskRun::Process(OsProcess {
+            command: job.interpreter.clone(),
+            args: job.render_args(&project_dir, project_name, &params),
+            working_dir: job.render_working_dir(&project_dir, project_name, &params),
+            env: job.render_env(&project_dir, project_name, &params),
+            timeout: job.timeout(),
+            termination_grace: job.termination_grace(),
+            limits: job.limits.clone(),
+            retry: job.retry.clone(),
+            interactive: job.interactive,
+        });
+
+        Ok((params, run, priority.unwrap_or(job.priority)))
+    }
+
+    /// Validates a pipeline step and builds what its task will run.
+    #[allow(clippy::type_complexity)]
+    fn prepare_pipeline_step(
+        &self,
+        steps: &[PipelineStepSpec],
+        step: &PipelineStepSpec,
+        priority: Option<Priority>,
+    ) -> Result<
+        (
+            TaskKind,
+            Option<String>,
+            serde_json::Value,
+            TaskRun,
+            Priority,
+        ),
+        RunPipelineError,
+    > {
+        let step_error = |err| RunPipelineError::Step {
+            step: step.name.clone(),
+            err,
         };
 
-        let mut tasks = self.tasks.write().await;
-        tasks.insert(id.clone(), task_data);
+        match &step.action {
+            StepAction::Download {
+                project_name,
+                url,
+                extract_mode,
+                on_collision,
+                sha256,
+                md5,
+            } => {
+                let link =
+                    url::Url::parse(url).map_err(|_| step_error(PipelineStepError::InvalidUrl))?;
+
+                let link = DownloadSource::resolve(link)
+                    .map_err(|err| step_error(PipelineStepError::Source(err)))?;
+
+                let options = ExtractOptions {
+                    extract_mode: *extract_mode,
+                    on_collision: *on_collision,
+                };
+
+                let expected_checksum = Checksum::expected(sha256.as_deref(), md5.as_deref())
+                    .map_err(|_| step_error(PipelineStepError::InvalidChecksum))?;
+
+                let params = Self::download_params(&link, &options, expected_checksum.as_ref());
+                let run = self.prepare_download(
+                    link,
+                    self.project_dir(project_name),
+                    options,
+                    expected_checksum,
+                );
+
+                Ok((
+                    TaskKind::Download,
+                    None,
+                    params,
+                    run,
+                    priority.unwrap_or_default(),
+                ))
+            }
+            StepAction::Job {
+                job_type,
+                project_name,
+                params,
+            } => {
+                let (params, run, priority) = self
+                    .prepare_job(job_type, project_name, params.clone(), priority)
+                    .map_err(|err| match err {
+                        RunJobError::JobNotFound => step_error(PipelineStepError::JobNotFound),
+                        RunJobError::Params(err) => step_error(PipelineStepError::Params(err)),
+                        err => RunPipelineError::Job(err),
+                    })?;
+
+                // The project may be created by a download step of this pipeline
+                let downloaded_by_pipeline = steps.iter().any(|other| {
+                    matches!(&other.action, StepAction::Download { project_name: p, .. } if p == project_name)
+                });
+
+                if !downloaded_by_pipeline && !self.project_dir(project_name).exists() {
+                    return Err(step_error(PipelineStepError::ProjectNotFound));
+                }
 
-        let tasks = self.tasks.clone();
-        tokio::spawn(async move {
-            let (stdout_tx, stdout_rx) = tokio::io::duplex(100);
-            let (stderr_tx, stderr_rx) = tokio::io::duplex(100);
+                Ok((
+                    TaskKind::Process,
+                    Some(job_type.clone()),
+                    serde_json::json!(params),
+                    run,
+                    priority,
+                ))
+            }
+        }
+    }
 
-            let stdout_task_id = task_id.clone();
-            let stderr_task_id = task_id.clone();
+    /// Traces the output of an OS process, appends it to its output file and publishes it.
+    /// Progress lines are reported to `progress` instead, see [`crate::server::progress`].
+    #[tracing::instrument(skip_all, fields(id=task_id, ?stream))]
+    async fn record_output<R: AsyncRead + Unpin>(
+        task_id: String,
+        stream: OutputStream,
+        rx: R,
+        mut writer: Option<OutputWriter>,
+        events: TaskEventBus,
+        progress: Option<ProgressReporter>,
+    ) {
+        let mut reader = BufReader::new(rx);
+        let mut buf = Vec::new();
+        let mut line_number = 0;
 
-            tokio::spawn(async move {
-                Self::trace_stdout(stdout_task_id, stdout_rx).await;
-            });
+        loop {
+            buf.clear();
 
-            tokio::spawn(async move {
-                Self::trace_stderr(stderr_task_id, stderr_rx).await;
-            });
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
 
-            let command = cfg!(target_os = "windows")
-                .then(|| "python")
-                .unwrap_or("python3")
-                .to_string();
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
 
-            let path_to_gs_log_to_locust_converter_script = PathBuf::from("ML_ETL")
-                .join("GS")
-                .join("Logfiles")
-                .join("GSLogToLocustConverter.py")
-                .to_string_lossy()
-                .to_string();
+            if progress
+                .as_ref()
+                .is_some_and(|progress| progress.report_line(line))
+            {
+                continue;
+            }
 
-            let project_dir = project_dir.to_string_lossy().to_string();
+            match stream {
+                OutputStream::Stdout => tracing::trace!("{line}"),
+                OutputStream::Stderr => tracing::error!("{line}"),
+            }
 
-            let args = vec![
-                path_to_gs_log_to_locust_converter_script,
-                String::from("--directory"),
-                project_dir,
-                String::from("--force"),
-            ];
+            if let Some(write) = writer.as_mut() {
+                if let Err(err) = write.write_line(line).await {
+                    tracing::error!(%err, "Failed to write output file. Output is only traced from now on");
+                    writer = None;
+                }
+            }
 
-            task.run_os_process(command, args, timeout, Some(stdout_tx), Some(stderr_tx))
-                .await;
+            line_number += 1;
 
-            // TODO: remove after adding a database.
-            // Keeping task in memory for 15 minutes after it's done.
-            // simulating an in-memory database.
+            events.publish(
+                &task_id,
+                TaskEventKind::Output {
+                    stream,
+                    line_number,
+                    line: line.to_string(),
+                },
+            );
+        }
 
-            tracing::debug!(id=%task_id, "Task finished. Waiting 15 minutes before removing it from memory");
-            tokio::time::sleep(std::time::Duration::from_secs(900)).await;
-            tracing::debug!(id=%task_id, "Removing task from memory");
-            let mut tasks = tasks.write().await;
-            tasks.remove(&task_id);
-        });
+        tracing::debug!("Finished reading output");
+    }
+
+    /// Runs the job type registered in [`ApiStateInner::jobs`] on the given project.
+    pub async fn run_job_task(
+        &self,
+        chat_id: String,
+        job_type: &str,
+        project_name: String,
+        params: HashMap<String, String>,
+        priority: Option<Priority>,
+    ) -> Result<String, RunJobError> {
+        let (params, run, priority) =
+            self.prepare_job(job_type, &project_name, params, priority)?;
+
+        if !self.project_dir(&project_name).exists() {
+            return Err(RunJobError::ProjectNotFound);
+        }
+
+        let task = self
+            .create_task(
+                chat_id,
+                TaskKind::Process,
+                Some(job_type),
+                &project_name,
+                serde_json::json!(params),
+                &run,
+            )
+            .await?;
+
+        let id = task.id().to_string();
+
+        self.spawn_task(task, priority, run);
 
         Ok(id)
     }
 
+    /// Backs the `/api/gs_log_to_locust_converter` endpoint.
+    /// New scripts should be added to the jobs config and run with [`ApiStateInner::run_job_task`].
+    pub async fn run_gs_log_to_locust_converter_task(
+        &self,
+        chat_id: String,
+        project_name: String,
+        priority: Option<Priority>,
+    ) -> Result<String, RunJobError> {
+        self.run_job_task(
+            chat_id,
+            GS_LOG_TO_LOCUST_CONVERTER_JOB,
+            project_name,
+            HashMap::new(),
+            priority,
+        )
+        .await
+    }
+
+    pub fn jobs(&self) -> &JobRegistry {
+        &self.jobs
+    }
+
     /// Send a cancel signal to the task with the given id and return immediately.
-    /// The Terminated task will be removed fom memory in a different tokio task which is spawned by [`ApiStateInner::run_task`].
+    /// The Terminated task will be removed fom memory in a different tokio task which is spawned by [`ApiStateInner::run_download_task`] or [`ApiStateInner::run_job_task`].
+    /// Finished tasks can not be canceled.
     pub async fn cancel_task<'a>(&self, id: &'a str, chat_id: &str) -> Option<&'a str> {
+        let owned = self
+            .tasks
+            .read()
+            .await
+            .get(id)
+            .is_some_and(|task_data| task_data.chat_id == chat_id);
+
+        if !owned {
+            return None;
+        }
+
+        // Not while the tasks are locked
+        ApiStateInner::record_cancel_request(&self.db, id).await;
+
+        if let Some(task_data) = self.tasks.read().await.get(id) {
+            task_data.handle.send_cancel_signal();
+        }
+
+        Some(id)
+    }
+
+    /// Queues input for the stdin of a running or queued task. [`None`] if there is no such task.
+    pub async fn send_stdin(
+        &self,
+        id: &str,
+        chat_id: &str,
+        input: StdinInput,
+    ) -> Option<Result<(), StdinError>> {
         let tasks = self.tasks.read().await;
         match tasks.get(id) {
             Some(task_data) if task_data.chat_id == chat_id => {
-                task_data.handle.send_cancel_signal().await;
-
-                Some(id)
+                Some(task_data.handle.send_stdin(input))
             }
             _ => None,
         }
     }
 
-    pub async fn task_status(&self, id: &str, chat_id: &str) -> Option<Status> {
-        let tasks = self.tasks.read().await;
-        match tasks.get(id) {
-            Some(task_data) if task_data.chat_id == chat_id => {
-                let status = task_data.handle.status().await;
+    /// Send a cancel signal to the pipeline with the given id and return immediately.
+    /// Running steps are canceled, steps that did not start yet are skipped.
+    pub async fn cancel_pipeline<'a>(&self, id: &'a str, chat_id: &str) -> Option<&'a str> {
+        let pipelines = self.pipelines.read().await;
+        match pipelines.get(id) {
+            Some(pipeline_data) if pipeline_data.chat_id == chat_id => {
+                // Never waits. The pipeline reads only the first signal, so waiting for room would hold the lock forever
+                match pipeline_data.tx.try_send(()) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(())) => {
+                        tracing::debug!(%id, "Pipeline is already canceling");
+                    }
+                    Err(mpsc::error::TrySendError::Closed(())) => {
+                        tracing::warn!(%id, "Failed to send cancel signal. Pipeline probably finished");
+                    }
+                }
 
-                Some(status)
+                Some(id)
             }
             _ => None,
         }
     }
 
+    pub async fn pipeline(&self, id: &str, chat_id: &str) -> Result<Option<PipelineRow>, DbError> {
+        self.db.pipeline(id, chat_id).await
+    }
+
+    pub async fn task_status(&self, id: &str, chat_id: &str) -> Result<Option<Status>, DbError> {
+        let task = self.db.task(id, chat_id).await?;
+
+        Ok(task.map(|task| task.status))
+    }
+
+    /// The task with its attempts, oldest first.
+    pub async fn task_with_attempts(
+        &self,
+        id: &str,
+        chat_id: &str,
+    ) -> Result<Option<(TaskRow, Vec<AttemptRow>)>, DbError> {
+        let Some(task) = self.db.task(id, chat_id).await? else {
+            return Ok(None);
+        };
+
+        let attempts = self.db.task_attempts(id).await?;
+
+        Ok(Some((task, attempts)))
+    }
+
+    pub async fn task(&self, id: &str, chat_id: &str) -> Result<Option<TaskRow>, DbError> {
+        self.db.task(id, chat_id).await
+    }
+
+    /// Tasks of a chat matching the filter, newest first.
+    pub async fn tasks(
+        &self,
+        chat_id: &str,
+        filter: TaskFilter,
+        limit: usize,
+    ) -> Result<Vec<TaskRow>, DbError> {
+        self.db.tasks(chat_id, filter, limit).await
+    }
+
+    /// The tasks with the given ids that belong to the given chat. Unknown ids are ignored.
+    pub async fn tasks_by_ids(
+        &self,
+        ids: &[String],
+        chat_id: &str,
+    ) -> Result<Vec<TaskRow>, DbError> {
+        self.db.tasks_by_ids(ids, chat_id).await
+    }
+
+    /// History of a task, oldest first. [`None`] if the task does not belong to the chat.
+    pub async fn task_history(
+        &self,
+        id: &str,
+        chat_id: &str,
+    ) -> Result<Option<Vec<HistoryRow>>, DbError> {
+        if self.db.task(id, chat_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.db.task_history(id).await?))
+    }
+
+    /// Status changes of a task after the given version, oldest first.
+    pub async fn task_transitions_after(
+        &self,
+        id: &str,
+        version: u64,
+    ) -> Result<Vec<TransitionRow>, DbError> {
+        self.db.task_transitions_after(id, version).await
+    }
+
+    /// Status changes of all tasks of a chat after the given [`TransitionRow::seq`], oldest first.
+    pub async fn chat_transitions_after(
+        &self,
+        chat_id: &str,
+        seq: u64,
+    ) -> Result<Vec<TransitionRow>, DbError> {
+        self.db.chat_transitions_after(chat_id, seq).await
+    }
+
+    /// The [`TransitionRow::seq`] of the latest status change of a chat. 0 if there is none.
+    pub async fn latest_chat_seq(&self, chat_id: &str) -> Result<u64, DbError> {
+        self.db.latest_chat_seq(chat_id).await
+    }
+
+    /// Receives the events of all tasks published from now on.
+    pub fn subscribe_task_events(&self) -> tokio::sync::broadcast::Receiver<Arc<TaskEvent>> {
+        self.events.subscribe()
+    }
+
+    /// Output of the OS process of a task, see [`OutputStore::read`]. [`None`] if there is no such task.
+    pub async fn task_output(
+        &self,
+        id: &str,
+        chat_id: &str,
+        stream: Option<OutputStream>,
+        tail: Option<usize>,
+    ) -> Result<Option<Vec<OutputLine>>, TaskOutputError> {
+        if self.db.task(id, chat_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let lines = self.output.read(id, stream, tail).await?;
+
+        Ok(Some(lines))
+    }
+
+    /// Progress of the running attempt of a task. [`None`] if the task does not report progress.
+    pub async fn task_progress(&self, id: &str) -> Option<Progress> {
+        let tasks = self.tasks.read().await;
+
+        tasks.get(id)?.handle.progress()
+    }
+
+    /// 1-based position of a queued task. [`None`] if the task is not queued.
+    pub fn queue_position(&self, id: &str) -> Option<usize> {
+        self.scheduler.queue_position(id)
+    }
+
     pub async fn list_files(&self, project_name: String) -> Result<Vec<String>, ListFilesError> {
         let project_dir = PathBuf::from(&self.projects_dir).join(project_name);
 
@@ -303,9 +1297,79 @@ impl ApiStateInner {
 }
 
 #[derive(Debug, thiserror::Error)]
-pub enum GsLogToLocustConverterError {
+pub enum RunDownloadTaskError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunJobError {
+    #[error("Job type not found")]
+    JobNotFound,
     #[error("Project not found")]
-    NotFound,
+    ProjectNotFound,
+    #[error("Invalid params: {0}")]
+    Params(#[from] JobParamsError),
+    #[error("Database error: {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunPipelineError {
+    #[error("Invalid pipeline: {0}")]
+    Graph(#[from] PipelineGraphError),
+    #[error("Invalid step {step}: {err}")]
+    Step {
+        step: String,
+        err: PipelineStepError,
+    },
+    #[error("Failed to prepare job: {0}")]
+    Job(RunJobError),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Debug, thiserror::Error, Serialize, ToSchema)]
+#[serde(tag = "error", content = "content")]
+pub enum PipelineStepError {
+    #[error("Invalid url")]
+    InvalidUrl,
+    #[error("Unsupported link: {0}")]
+    Source(DownloadSourceError),
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+    #[error("Job type not found")]
+    JobNotFound,
+    #[error("Project not found")]
+    ProjectNotFound,
+    #[error("Invalid params: {0}")]
+    Params(JobParamsError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CreateScheduleError {
+    #[error("{0}")]
+    InvalidCron(#[from] InvalidCronExpression),
+    #[error("Job type not found")]
+    JobNotFound,
+    #[error("Project not found")]
+    ProjectNotFound,
+    #[error("Invalid params: {0}")]
+    Params(#[from] JobParamsError),
+    #[error("Database error: {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskOutputError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    DbError(#[from] DbError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -338,6 +1402,52 @@ impl Drop for ApiStateInner {
     }
 }
 
+#[cfg(test)]
+impl ApiState {
+    /// No jobs, an in-memory database and a single slot
+    pub fn in_memory() -> Self {
+        Self::new(
+            String::new(),
+            String::from("projects"),
+            Database::open_in_memory().expect("Failed to open database"),
+            JobRegistry::parse("").expect("Failed to parse jobs config"),
+            Scheduler::new(1),
+            OutputStore::new(
+                std::env::temp_dir().join(format!("job_hub_test_output_{}", uuid::Uuid::new_v4())),
+            ),
+        )
+    }
+
+    pub fn output(&self) -> &OutputStore {
+        &self.output
+    }
+
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    /// Persists the status like a running task does. Returns the event to publish.
+    pub async fn persist_status(&self, id: &str, status: Status) -> TaskEventKind {
+        let update = self
+            .db
+            .update_task_status(id, &status, EventSource::System)
+            .await
+            .expect("Failed to update task status");
+
+        TaskEventKind::Status {
+            status,
+            chat_id: update.chat_id,
+            seq: update.seq,
+            version: update.version,
+            at: update.at,
+        }
+    }
+
+    pub fn events(&self) -> &TaskEventBus {
+        &self.events
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -353,6 +1463,61 @@ mod tests {
             .init();
     }
 
+    #[tokio::test]
+    async fn repeated_pipeline_cancels_do_not_block() {
+        let state = ApiState::in_memory();
+        // Like a pipeline that already read its first signal
+        let (tx, _rx) = mpsc::channel(1);
+
+        state.pipelines.write().await.insert(
+            String::from("1"),
+            PipelineData {
+                chat_id: String::from("chat"),
+                tx,
+            },
+        );
+
+        for _ in 0..3 {
+            let canceled =
+                tokio::time::timeout(Duration::from_secs(1), state.cancel_pipeline("1", "chat"))
+                    .await
+                    .expect("Cancel blocked");
+            assert_eq!(canceled, Some("1"));
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_task_cancels_do_not_block() {
+        let state = ApiState::in_memory();
+        let id = state.db().insert_test_task("chat").await;
+        // Like a queued task that did not read its first signal yet
+        let (_task, handle) = Task::new(
+            id.clone(),
+            Status::created(TaskKind::Process),
+            state.db().clone(),
+            state.events().clone(),
+            false,
+        );
+
+        state.tasks.write().await.insert(
+            id.clone(),
+            TaskData {
+                chat_id: String::from("chat"),
+                handle,
+            },
+        );
+
+        for _ in 0..3 {
+            let canceled =
+                tokio::time::timeout(Duration::from_secs(1), state.cancel_task(&id, "chat"))
+                    .await
+                    .expect("Cancel blocked");
+            assert_eq!(canceled, Some(id.as_str()));
+        }
+
+        assert_eq!(state.cancel_task(&id, "other").await, None);
+    }
+
     // cargo test --package job_hub --lib -- server::state::tests::run_gs_log_to_locust_converter_task --exact --nocapture --ignored
     // python .\ML_ETL\GS\Logfiles\GSLogToLocustConverter.py --directory .\projects\project\ --force
     // python3 ML_ETL/GS/Logfiles/GSLogToLocustConverter.py --directory projects/project --force
@@ -361,19 +1526,32 @@ mod tests {
     async fn run_gs_log_to_locust_converter_task() {
         init_tracing();
 
-        let api_state = ApiState::new("".to_string(), "projects".to_string());
+        let db = Database::open_in_memory().expect("Failed to open database");
+        let jobs = JobRegistry::load("jobs.toml").expect("Failed to load jobs config");
+        let api_state = ApiState::new(
+            "".to_string(),
+            "projects".to_string(),
+            db,
+            jobs,
+            Scheduler::new(1),
+            OutputStore::new("task_output"),
+        );
 
         let chat_id = "chat_id".to_string();
         let project_name = "project".to_string();
 
         let task_id = api_state
-            .run_gs_log_to_locust_converter_task(chat_id.clone(), project_name)
+            .run_gs_log_to_locust_converter_task(chat_id.clone(), project_name, None)
             .await
             .expect("Failed to start task");
 
         loop {
-            match api_state.task_status(&task_id, &chat_id).await {
-                Some(Process(ProcessStatus::Created)) => {
+            match api_state
+                .task_status(&task_id, &chat_id)
+                .await
+                .expect("Failed to get task status")
+            {
+                Some(Process(ProcessStatus::Created | ProcessStatus::Queued)) => {
                     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
                 }
                 Some(status) => {
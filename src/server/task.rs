@@ -1,43 +1,377 @@
-use serde::Serialize;
-use std::{ffi::OsStr, process::ExitStatus, sync::Arc, time::Duration};
+use super::{
+    db::Database,
+    download::{self, Checksum, DownloadRequest, PartialDownload},
+    events::{TaskEventBus, TaskEventKind},
+    history::{EventSource, HistoryEvent},
+    limits::{ResourceLimit, ResourceLimits},
+    progress::{Progress, ProgressReporter},
+    retry::{RetryPolicy, RetryableFailure},
+    scheduler::{Permit, Ticket},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf, process::ExitStatus, sync::Arc, time::Duration};
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
-    process::Command,
-    sync::{mpsc, RwLock},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    process::{ChildStdin, Command},
+    sync::{mpsc, oneshot, watch, RwLock},
 };
 use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+/// What a task does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    Download,
+    Process,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "type", content = "content")]
 pub enum Status {
     Download(DownloadZipFileStatus),
     Process(ProcessStatus),
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+impl Status {
+    /// The status a task of the given kind starts with
+    pub fn created(kind: TaskKind) -> Self {
+        match kind {
+            TaskKind::Download => Self::Download(DownloadZipFileStatus::Created),
+            TaskKind::Process => Self::Process(ProcessStatus::Created),
+        }
+    }
+
+    pub fn queued(kind: TaskKind) -> Self {
+        match kind {
+            TaskKind::Download => Self::Download(DownloadZipFileStatus::Queued),
+            TaskKind::Process => Self::Process(ProcessStatus::Queued),
+        }
+    }
+
+    pub fn canceled(kind: TaskKind) -> Self {
+        match kind {
+            TaskKind::Download => Self::Download(DownloadZipFileStatus::Canceled),
+            TaskKind::Process => Self::Process(ProcessStatus::Canceled { signal: None }),
+        }
+    }
+
+    pub fn retrying(kind: TaskKind, next_attempt: u32, max_attempts: u32, delay: Duration) -> Self {
+        let delay_ms = delay.as_millis() as u64;
+
+        match kind {
+            TaskKind::Download => Self::Download(DownloadZipFileStatus::Retrying {
+                next_attempt,
+                max_attempts,
+                delay_ms,
+            }),
+            TaskKind::Process => Self::Process(ProcessStatus::Retrying {
+                next_attempt,
+                max_attempts,
+                delay_ms,
+            }),
+        }
+    }
+
+    /// The status of a task that was still running when the server stopped
+    pub fn interrupted(kind: TaskKind) -> Self {
+        match kind {
+            TaskKind::Download => Self::Download(DownloadZipFileStatus::Failed {
+                reason: FailureReason::new(
+                    FailureCode::Interrupted,
+                    "Interrupted by a server restart",
+                ),
+            }),
+            TaskKind::Process => Self::Process(ProcessStatus::Failed {
+                operation: FailOperation::Interrupted,
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> TaskKind {
+        match self {
+            Self::Download(_) => TaskKind::Download,
+            Self::Process(_) => TaskKind::Process,
+        }
+    }
+
+    pub fn is_queued(&self) -> bool {
+        matches!(
+            self,
+            Self::Download(DownloadZipFileStatus::Queued) | Self::Process(ProcessStatus::Queued)
+        )
+    }
+
+    /// Downloads that exited and processes that exited with success
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            Self::Download(DownloadZipFileStatus::Exited)
+                | Self::Process(ProcessStatus::Exited {
+                    exit_status: ExitedStatus::Success
+                })
+        )
+    }
+
+    /// Where the task is in its lifecycle, independent of its kind
+    pub fn phase(&self) -> TaskPhase {
+        match self {
+            Self::Download(status) => match status {
+                DownloadZipFileStatus::Created => TaskPhase::Created,
+                DownloadZipFileStatus::Queued => TaskPhase::Queued,
+                DownloadZipFileStatus::Running => TaskPhase::Running,
+                DownloadZipFileStatus::Retrying { .. } => TaskPhase::Retrying,
+                DownloadZipFileStatus::Canceled => TaskPhase::Canceled,
+                DownloadZipFileStatus::Exited => TaskPhase::Succeeded,
+                DownloadZipFileStatus::Failed { .. } | DownloadZipFileStatus::Timeout => {
+                    TaskPhase::Failed
+                }
+            },
+            Self::Process(status) => match status {
+                ProcessStatus::Created => TaskPhase::Created,
+                ProcessStatus::Queued => TaskPhase::Queued,
+                ProcessStatus::Running => TaskPhase::Running,
+                ProcessStatus::Retrying { .. } => TaskPhase::Retrying,
+                ProcessStatus::Canceled { .. } => TaskPhase::Canceled,
+                ProcessStatus::Exited {
+                    exit_status: ExitedStatus::Success,
+                } => TaskPhase::Succeeded,
+                ProcessStatus::Failed { .. }
+                | ProcessStatus::Exited { .. }
+                | ProcessStatus::Timeout { .. }
+                | ProcessStatus::LimitExceeded { .. } => TaskPhase::Failed,
+            },
+        }
+    }
+
+    /// Why the task failed. [`None`] unless the phase is [`TaskPhase::Failed`]
+    pub fn failure(&self) -> Option<FailureReason> {
+        match self {
+            Self::Download(DownloadZipFileStatus::Failed { reason }) => Some(reason.clone()),
+            Self::Download(DownloadZipFileStatus::Timeout) => Some(FailureReason::new(
+                FailureCode::Timeout,
+                "The download took longer than its timeout",
+            )),
+            Self::Process(ProcessStatus::Failed { operation }) => Some(operation.failure()),
+            Self::Process(ProcessStatus::Exited {
+                exit_status: ExitedStatus::Failure { code, signal },
+            }) => {
+                let message = match (code, signal) {
+                    (_, Some(signal)) => format!("Killed by signal {signal}"),
+                    (Some(code), None) => format!("Exited with code {code}"),
+                    (None, None) => String::from("Exited with failure"),
+                };
+
+                Some(FailureReason::new(FailureCode::NonZeroExit, message))
+            }
+            Self::Process(ProcessStatus::Timeout { .. }) => Some(FailureReason::new(
+                FailureCode::Timeout,
+                "The OS process took longer than its timeout",
+            )),
+            Self::Process(ProcessStatus::LimitExceeded { limit }) => Some(FailureReason::new(
+                FailureCode::LimitExceeded,
+                format!("The OS process exceeded its {} limit", limit.description()),
+            )),
+            _ => None,
+        }
+    }
+
+    /// A terminal status will never change again
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Self::Download(status) => !matches!(
+                status,
+                DownloadZipFileStatus::Created
+                    | DownloadZipFileStatus::Queued
+                    | DownloadZipFileStatus::Running
+                    | DownloadZipFileStatus::Retrying { .. }
+            ),
+            Self::Process(status) => !matches!(
+                status,
+                ProcessStatus::Created
+                    | ProcessStatus::Queued
+                    | ProcessStatus::Running
+                    | ProcessStatus::Retrying { .. }
+            ),
+        }
+    }
+}
+
+/// Where a task is in its lifecycle. The same for every kind of task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskPhase {
+    Created,
+    /// Waiting for a free slot in the scheduler
+    Queued,
+    Running,
+    /// A previous attempt failed. Waiting before the next attempt
+    Retrying,
+    Succeeded,
+    /// See [`FailureReason`]
+    Failed,
+    Canceled,
+}
+
+/// Why a task failed. The same shape for every kind of task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FailureReason {
+    pub code: FailureCode,
+    /// Details for humans. Not meant to be parsed
+    pub message: String,
+}
+
+impl FailureReason {
+    pub fn new(code: FailureCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCode {
+    /// The server stopped while the task was running
+    Interrupted,
+    /// The task took longer than its timeout
+    Timeout,
+    /// The file could not be downloaded
+    Download,
+    /// The provider refused to serve the file, e.g. because it is private
+    AccessDenied,
+    /// The provider's download quota of the file is exceeded
+    QuotaExceeded,
+    /// The downloaded archive could not be extracted
+    Extract,
+    /// The download exceeds the size limit
+    DownloadTooLarge,
+    /// The extracted files exceed the size limit
+    UncompressedTooLarge,
+    /// The archive has more entries than allowed
+    TooManyEntries,
+    /// An entry of the archive is compressed suspiciously well, e.g. in a zip bomb
+    CompressionRatioExceeded,
+    /// The download is not an archive, e.g. an HTML page
+    NotAnArchive,
+    /// The checksum of the download differs from the expected one
+    ChecksumMismatch,
+    /// An entry of the archive would be written outside of the project, or is a symlink
+    UnsafePath,
+    /// Two entries of the archive are flattened to the same file name
+    NameCollision,
+    /// The OS process could not be started
+    Spawn,
+    /// The OS process exited with a non-zero exit code or was killed by a signal
+    NonZeroExit,
+    /// The OS process exceeded one of the resource limits of the job
+    LimitExceeded,
+    /// The OS process could not be stopped or waited for
+    ProcessControl,
+    /// An error on the server
+    Internal,
+}
+
+/// Name of a [`Status`] without its content, e.g. `Running` or `Exited`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum StatusName {
+    Created,
+    Queued,
+    Failed,
+    Running,
+    Retrying,
+    Canceled,
+    Exited,
+    Timeout,
+    LimitExceeded,
+}
+
+impl StatusName {
+    /// The `status` tag of the serialized [`Status`]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "Created",
+            Self::Queued => "Queued",
+            Self::Failed => "Failed",
+            Self::Running => "Running",
+            Self::Retrying => "Retrying",
+            Self::Canceled => "Canceled",
+            Self::Exited => "Exited",
+            Self::Timeout => "Timeout",
+            Self::LimitExceeded => "LimitExceeded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "status", content = "content")]
 pub enum DownloadZipFileStatus {
     Created,
-    Failed { reason: String },
+    /// Waiting for a free slot in the scheduler
+    Queued,
+    Failed {
+        reason: FailureReason,
+    },
     Running,
+    /// A previous attempt failed. Waiting before the next attempt
+    Retrying {
+        next_attempt: u32,
+        max_attempts: u32,
+        delay_ms: u64,
+    },
     Canceled,
     Exited,
     Timeout,
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "status", content = "content")]
 pub enum ProcessStatus {
     Created,
-    Failed { operation: FailOperation },
+    /// Waiting for a free slot in the scheduler
+    Queued,
+    Failed {
+        operation: FailOperation,
+    },
     Running,
-    Canceled,
-    Exited { exit_status: ExitedStatus },
-    Timeout,
+    /// A previous attempt failed. Waiting before the next attempt
+    Retrying {
+        next_attempt: u32,
+        max_attempts: u32,
+        delay_ms: u64,
+    },
+    Canceled {
+        /// Signal that ended the OS process. Missing if the OS process never started
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signal: Option<TerminationSignal>,
+    },
+    Exited {
+        exit_status: ExitedStatus,
+    },
+    Timeout {
+        /// Signal that ended the OS process
+        signal: TerminationSignal,
+    },
+    /// Killed for exceeding one of the resource limits of the job
+    LimitExceeded {
+        limit: ResourceLimit,
+    },
+}
+
+impl ProcessStatus {
+    fn retryable_failure(&self) -> Option<RetryableFailure> {
+        match self {
+            Self::Exited {
+                exit_status: ExitedStatus::Failure { .. },
+            } => Some(RetryableFailure::NonZeroExit),
+            Self::Timeout { .. } => Some(RetryableFailure::Timeout),
+            _ => None,
+        }
+    }
 }
 
 /// Where did the task fail
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum FailOperation {
     /// Failed to spawn OS process
     OnSpawn,
@@ -51,15 +385,53 @@ pub enum FailOperation {
     AfterCancelOnWait,
     /// Failed during wait
     OnWait,
+    /// The server stopped while the OS process was running
+    Interrupted,
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+impl FailOperation {
+    fn failure(&self) -> FailureReason {
+        match self {
+            Self::OnSpawn => {
+                FailureReason::new(FailureCode::Spawn, "Failed to spawn the OS process")
+            }
+            Self::AfterTimeoutOnKill | Self::AfterCancelOnKill => {
+                FailureReason::new(FailureCode::ProcessControl, "Failed to kill the OS process")
+            }
+            Self::AfterTimeoutOnWait | Self::AfterCancelOnWait | Self::OnWait => {
+                FailureReason::new(
+                    FailureCode::ProcessControl,
+                    "Failed to wait for the OS process",
+                )
+            }
+            Self::Interrupted => {
+                FailureReason::new(FailureCode::Interrupted, "Interrupted by a server restart")
+            }
+        }
+    }
+}
+
+/// Signal that ended an OS process after a timeout or cancel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum TerminationSignal {
+    /// The OS process exited within the grace period after SIGTERM
+    Sigterm,
+    /// The OS process was still running after the grace period and was killed
+    Sigkill,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "exit_status", content = "content")]
 pub enum ExitedStatus {
     /// Exited with success
     Success,
     /// Exited with failure
-    Failure { code: Option<i32> },
+    Failure {
+        code: Option<i32>,
+        /// Signal that killed the OS process. Missing if it exited on its own
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signal: Option<i32>,
+    },
 }
 
 impl From<ExitStatus> for ExitedStatus {
@@ -69,7 +441,13 @@ impl From<ExitStatus> for ExitedStatus {
         }
 
         let code = exit_status.code();
-        Self::Failure { code }
+
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&exit_status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Self::Failure { code, signal }
     }
 }
 
@@ -81,9 +459,50 @@ impl From<ExitStatus> for ProcessStatus {
     }
 }
 
+/// An OS process run by [`Task::run_os_process`]
+#[derive(Debug, Clone)]
+pub struct OsProcess {
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: PathBuf,
+    /// The complete environment. Nothing is inherited from the server
+    pub env: BTreeMap<String, String>,
+    /// Per attempt
+    pub timeout: Duration,
+    /// Time between SIGTERM and SIGKILL when the OS process is stopped
+    pub termination_grace: Duration,
+    pub limits: ResourceLimits,
+    pub retry: RetryPolicy,
+    /// Whether clients may write to stdin. Otherwise stdin is empty
+    pub interactive: bool,
+}
+
+/// Input for the stdin of an interactive OS process
+#[derive(Debug)]
+pub enum StdinInput {
+    Data(Vec<u8>),
+    /// Closes stdin for the rest of the task
+    Eof,
+}
+
+/// Inputs that may wait for the OS process to read them
+const STDIN_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, thiserror::Error, Serialize, Deserialize, ToSchema)]
+pub enum StdinError {
+    #[error("Task is not interactive")]
+    NotInteractive,
+    #[error("Too much input is waiting to be read")]
+    Full,
+    #[error("Stdin was closed or the task finished")]
+    Closed,
+}
+
 pub struct Data {
     pub id: String,
     pub status: RwLock<Status>,
+    /// Only set while an attempt runs
+    pub progress: ProgressReporter,
 }
 
 pub struct Handle {
@@ -91,14 +510,31 @@ pub struct Handle {
     ///
     /// This is not a CancellationToken because dropping the handle should cancel the task
     tx: mpsc::Sender<()>,
+    /// Only set for interactive tasks
+    stdin: Option<mpsc::Sender<StdinInput>>,
     data: Arc<Data>,
 }
 
 impl Handle {
+    /// Queues input for the stdin of the OS process. Input sent before the OS process started is
+    /// written once it starts.
+    pub fn send_stdin(&self, input: StdinInput) -> Result<(), StdinError> {
+        let stdin = self.stdin.as_ref().ok_or(StdinError::NotInteractive)?;
+
+        stdin.try_send(input).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => StdinError::Full,
+            mpsc::error::TrySendError::Closed(_) => StdinError::Closed,
+        })
+    }
+
     pub async fn status(&self) -> Status {
         self.data.status.read().await.clone()
     }
 
+    pub fn progress(&self) -> Option<Progress> {
+        self.data.progress.get()
+    }
+
     pub fn id(&self) -> &str {
         &self.data.id
     }
@@ -108,13 +544,20 @@ impl Handle {
     /// This will not wait for the task to finish. Waiting for the task to finish may cause a bad response times for the api.
     /// Running tasks will be locked until the task is finished, which may take a long time.
     /// Locking the tasks will prevent other tasks from running.
+    /// Never waits, so that it may be called while the tasks are locked.
+    /// A task that did not read the previous signal yet is already canceling.
     #[tracing::instrument(name = "cancel_signal", skip(self), fields(id=self.id()))]
-    pub async fn send_cancel_signal(&self) {
-        match self.tx.send(()).await {
+    pub fn send_cancel_signal(&self) {
+        match self.tx.try_send(()) {
             Ok(_) => {
                 tracing::info!("Sent cancel signal");
             }
-            Err(_) => tracing::warn!("Failed to send cancel signal. Task was probably dropped"),
+            Err(mpsc::error::TrySendError::Full(())) => {
+                tracing::debug!("Task is already canceling")
+            }
+            Err(mpsc::error::TrySendError::Closed(())) => {
+                tracing::warn!("Failed to send cancel signal. Task was probably dropped")
+            }
         }
     }
 }
@@ -122,40 +565,90 @@ impl Handle {
 pub struct Task {
     rx: mpsc::Receiver<()>,
     data: Arc<Data>,
+    /// Every status change is persisted
+    db: Database,
+    /// Every persisted status change is published
+    events: TaskEventBus,
+    /// Only set for interactive tasks
+    stdin: Option<mpsc::Receiver<StdinInput>>,
 }
 
 impl Task {
-    pub fn new(id: String) -> (Self, Handle) {
+    /// `id` must be the id of an existing task in `db` with the given `status`.
+    /// The handle of an `interactive` task can send input to the stdin of the OS process.
+    pub fn new(
+        id: String,
+        status: Status,
+        db: Database,
+        events: TaskEventBus,
+        interactive: bool,
+    ) -> (Self, Handle) {
         let (tx, rx) = mpsc::channel(1);
 
+        let (stdin_tx, stdin_rx) = interactive.then(|| mpsc::channel(STDIN_CAPACITY)).unzip();
+
         let data = Arc::new(Data {
             id,
-            status: RwLock::new(Status::Process(ProcessStatus::Created)),
+            status: RwLock::new(status),
+            progress: ProgressReporter::default(),
         });
 
         let handle = Handle {
             tx,
+            stdin: stdin_tx,
             data: data.clone(),
         };
 
-        let task = Self { rx, data };
+        let task = Self {
+            rx,
+            data,
+            db,
+            events,
+            stdin: stdin_rx,
+        };
 
         (task, handle)
     }
 
-    fn id(&self) -> &str {
+    pub fn id(&self) -> &str {
         &self.data.id
     }
 
-    async fn set_status(&self, status: Status) {
+    /// Where the running attempt reports its progress
+    pub fn progress(&self) -> ProgressReporter {
+        self.data.progress.clone()
+    }
+
+    async fn set_status(&self, status: Status, source: EventSource) {
+        match self.db.update_task_status(self.id(), &status, source).await {
+            Ok(update) => self.events.publish(
+                self.id(),
+                TaskEventKind::Status {
+                    status: status.clone(),
+                    chat_id: update.chat_id,
+                    seq: update.seq,
+                    version: update.version,
+                    at: update.at,
+                },
+            ),
+            Err(err) => tracing::error!(%err, "Failed to persist status"),
+        }
+
         *self.data.status.write().await = status
     }
 
     #[tracing::instrument(name = "status", skip_all)]
-    async fn set_status_and_log(&self, status: Status) {
-        tracing::debug!(?status, "Setting status");
+    async fn set_status_and_log(&self, status: Status, source: EventSource) {
+        tracing::debug!(?status, ?source, "Setting status");
+
+        self.set_status(status, source).await;
+    }
 
-        self.set_status(status).await;
+    /// Appends to the history of the task. Status changes are recorded by [`Task::set_status`].
+    async fn record(&self, source: EventSource, event: HistoryEvent) {
+        if let Err(err) = self.db.append_task_event(self.id(), source, &event).await {
+            tracing::error!(%err, "Failed to persist history");
+        }
     }
 
     #[tracing::instrument(name = "cancel_signal", skip_all)]
@@ -169,6 +662,51 @@ impl Task {
         tracing::warn!("No more signals. Handle was probably dropped");
     }
 
+    /// Waits in the scheduler's queue until the task may run.
+    /// The task must keep the returned [`Permit`] while running.
+    ///
+    /// Returns the final status if the task was canceled while waiting.
+    #[tracing::instrument(skip_all, fields(id=self.id()))]
+    pub async fn wait_for_permit(&mut self, ticket: Ticket) -> Result<Permit, Status> {
+        let kind = self.data.status.read().await.kind();
+
+        self.set_status_and_log(Status::queued(kind), EventSource::System)
+            .await;
+
+        let source = tokio::select! {
+            permit = ticket => {
+                match permit {
+                    Ok(permit) => return Ok(permit),
+                    Err(_) => {
+                        tracing::error!("Scheduler dropped the task");
+
+                        EventSource::System
+                    }
+                }
+            },
+            _ = self.wait_for_cancel_signal() => {
+                EventSource::User
+            }
+        };
+
+        let canceled = Status::canceled(kind);
+
+        self.set_status_and_log(canceled.clone(), source).await;
+
+        Err(canceled)
+    }
+
+    /// Cancels a task that will never run. Returns the final status.
+    #[tracing::instrument(skip_all, fields(id=self.id()))]
+    pub async fn cancel(self, source: EventSource) -> Status {
+        let kind = self.data.status.read().await.kind();
+        let canceled = Status::canceled(kind);
+
+        self.set_status_and_log(canceled.clone(), source).await;
+
+        canceled
+    }
+
     async fn copy_io<R, W>(reader: &mut R, writer: &mut W)
     where
         R: AsyncRead + Unpin + ?Sized,
@@ -199,17 +737,142 @@ impl Task {
         Self::copy_io(reader, writer).await;
     }
 
-    #[tracing::instrument(skip_all, fields(id=self.id(), timeout))]
-    pub async fn run_os_process<S, I, O, E>(
+    /// Stores the checksum of a downloaded file with the task
+    async fn set_checksum(&self, checksum: &Checksum) {
+        if let Err(err) = self.db.set_task_checksum(self.id(), checksum).await {
+            tracing::error!(%err, "Failed to persist checksum");
+        }
+    }
+
+    /// Records the start of an attempt. Attempts are counted from 1.
+    async fn start_attempt(&self, attempt: u32) {
+        tracing::debug!(%attempt, "Starting attempt");
+
+        if let Err(err) = self.db.start_task_attempt(self.id(), attempt).await {
+            tracing::error!(%err, "Failed to persist attempt");
+        }
+
+        self.record(
+            EventSource::System,
+            HistoryEvent::AttemptStarted { attempt },
+        )
+        .await;
+    }
+
+    async fn finish_attempt(&self, attempt: u32, status: &Status) {
+        self.data.progress.clear();
+
+        if let Err(err) = self
+            .db
+            .finish_task_attempt(self.id(), attempt, status)
+            .await
+        {
+            tracing::error!(%err, "Failed to persist attempt");
+        }
+
+        if let Some(reason) = status.failure() {
+            let event = HistoryEvent::AttemptFailed { attempt, reason };
+
+            self.record(final_source(status), event).await;
+        }
+    }
+
+    /// Waits for the backoff of the retry policy before `next_attempt`.
+    /// The scheduler's slot is kept while waiting.
+    ///
+    /// Returns the final status if the task was canceled while waiting.
+    async fn wait_for_retry(
+        &mut self,
+        kind: TaskKind,
+        next_attempt: u32,
+        retry: &RetryPolicy,
+    ) -> Result<(), Status> {
+        let delay = retry.backoff(next_attempt - 1);
+
+        self.set_status_and_log(
+            Status::retrying(kind, next_attempt, retry.max_attempts, delay),
+            EventSource::System,
+        )
+        .await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => Ok(()),
+            _ = self.wait_for_cancel_signal() => Err(Status::canceled(kind)),
+        }
+    }
+
+    /// Runs the OS process until an attempt succeeds or the retry policy gives up.
+    #[tracing::instrument(skip_all, fields(id=self.id(), timeout=?process.timeout))]
+    pub async fn run_os_process<O, E>(
         mut self,
-        command: S,
-        args: I,
-        timeout: Duration,
+        process: OsProcess,
+        mut stdout_writer: Option<O>,
+        mut stderr_writer: Option<E>,
+    ) -> Status
+    where
+        O: 'static + AsyncWrite + Unpin + Send,
+        E: 'static + AsyncWrite + Unpin + Send,
+    {
+        let mut attempt = 1;
+        let mut stdin_reader = self.stdin.take();
+
+        let status = loop {
+            self.start_attempt(attempt).await;
+
+            let (status, stdout, stderr, stdin) = self
+                .run_os_process_attempt(
+                    &process,
+                    stdout_writer.take(),
+                    stderr_writer.take(),
+                    stdin_reader.take(),
+                )
+                .await;
+
+            stdout_writer = stdout;
+            stderr_writer = stderr;
+            stdin_reader = stdin;
+
+            let failure = status.retryable_failure();
+            let status = Status::Process(status);
+
+            self.finish_attempt(attempt, &status).await;
+
+            if !process.retry.should_retry(attempt, failure) {
+                break status;
+            }
+
+            attempt += 1;
+
+            if let Err(canceled) = self
+                .wait_for_retry(TaskKind::Process, attempt, &process.retry)
+                .await
+            {
+                break canceled;
+            }
+        };
+
+        self.set_status_and_log(status.clone(), final_source(&status))
+            .await;
+
+        tracing::debug!("Terminated");
+
+        status
+    }
+
+    /// Runs the OS process once. The writers and the stdin reader are handed back for the next attempt.
+    async fn run_os_process_attempt<O, E>(
+        &mut self,
+        process: &OsProcess,
         stdout_writer: Option<O>,
         stderr_writer: Option<E>,
-    ) where
-        S: AsRef<OsStr>,
-        I: IntoIterator<Item = S>,
+        stdin_reader: Option<mpsc::Receiver<StdinInput>>,
+    ) -> (
+        ProcessStatus,
+        Option<O>,
+        Option<E>,
+        Option<mpsc::Receiver<StdinInput>>,
+    )
+    where
         O: 'static + AsyncWrite + Unpin + Send,
         E: 'static + AsyncWrite + Unpin + Send,
     {
@@ -225,95 +888,112 @@ impl Task {
             std::process::Stdio::null()
         };
 
-        let child = Command::new(command)
-            .args(args)
+        let stdin = if stdin_reader.is_some() {
+            std::process::Stdio::piped()
+        } else {
+            std::process::Stdio::null()
+        };
+
+        let mut command = std::process::Command::new(&process.command);
+
+        command
+            .args(&process.args)
+            .current_dir(&process.working_dir)
+            .env_clear()
+            .envs(&process.env)
+            .stdin(stdin)
             .stdout(stdout)
-            .stderr(stderr)
-            .spawn();
+            .stderr(stderr);
+
+        // Own process group, so that grandchildren are stopped together with the child
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+        process.limits.apply(&mut command);
 
-        let mut child = match child {
+        let mut command = Command::from(command);
+
+        let mut child = match command.spawn() {
             Ok(child) => child,
             Err(err) => {
                 tracing::error!(?err, "Failed to spawn OS process");
 
-                self.set_status_and_log(Status::Process(ProcessStatus::Failed {
+                let status = ProcessStatus::Failed {
                     operation: FailOperation::OnSpawn,
-                }))
-                .await;
+                };
 
-                return;
+                return (status, stdout_writer, stderr_writer, stdin_reader);
             }
         };
 
-        if let Some(mut write) = stdout_writer {
+        let stdin_copy = stdin_reader.map(|reader| {
+            let stdin = child.stdin.take();
+            let (done_tx, done_rx) = oneshot::channel();
+
+            let copy = tokio::spawn(async move {
+                match stdin {
+                    Some(stdin) => Self::copy_stdin(reader, stdin, done_rx).await,
+                    None => Some(reader),
+                }
+            });
+
+            (done_tx, copy)
+        });
+
+        let stdout_copy = stdout_writer.map(|mut write| {
             let id = self.id().to_string();
             let stdout = child.stdout.take();
             tokio::spawn(async move {
                 if let Some(mut stdout) = stdout {
                     Self::copy_stdout(id, &mut stdout, &mut write).await;
                 }
-            });
-        }
 
-        if let Some(mut write) = stderr_writer {
+                write
+            })
+        });
+
+        let stderr_copy = stderr_writer.map(|mut write| {
             let id = self.id().to_string();
             let stderr = child.stderr.take();
             tokio::spawn(async move {
                 if let Some(mut stderr) = stderr {
                     Self::copy_stderr(id, &mut stderr, &mut write).await;
                 }
-            });
-        }
 
-        self.set_status_and_log(Status::Process(ProcessStatus::Running))
+                write
+            })
+        });
+
+        self.set_status_and_log(Status::Process(ProcessStatus::Running), EventSource::System)
             .await;
 
         let status = tokio::select! {
-            _ = tokio::time::sleep(timeout) => {
+            _ = tokio::time::sleep(process.timeout) => {
                 tracing::debug!("Timeout");
 
-                match child.kill().await {
-                    Ok(_) => {
-                        tracing::debug!("Killed OS process");
-
-                        match child.wait().await {
-                            Ok(exit_status) => {
-                                tracing::debug!(?exit_status, "OS process exited with status");
-                                ProcessStatus::Timeout
-                            },
-                            Err(err) => {
-                                tracing::error!(?err, "Failed to wait for OS process");
-                                ProcessStatus::Failed{ operation: FailOperation::AfterTimeoutOnWait }
-                            }
-                        }
-                    },
-
-                    Err(err) => {
+                match self.terminate(&mut child, process.termination_grace, EventSource::Timeout).await {
+                    Ok(signal) => ProcessStatus::Timeout { signal },
+                    Err(TerminateError::Kill(err)) => {
                         tracing::error!(?err, "Failed to kill OS process");
                         ProcessStatus::Failed{ operation: FailOperation::AfterTimeoutOnKill }
+                    },
+                    Err(TerminateError::Wait(err)) => {
+                        tracing::error!(?err, "Failed to wait for OS process");
+                        ProcessStatus::Failed{ operation: FailOperation::AfterTimeoutOnWait }
                     }
                 }
             },
             _ = self.wait_for_cancel_signal() => {
 
-                match child.kill().await {
-                    Ok(_) => {
-                        tracing::debug!("Killed OS process");
-
-                        match child.wait().await {
-                            Ok(_) => {
-                                ProcessStatus::Canceled
-                            },
-                            Err(err) => {
-                                tracing::error!(?err, "Failed to wait for OS process");
-                                ProcessStatus::Failed{ operation: FailOperation::AfterCancelOnWait }
-                            }
-                        }
-                    },
-
-                    Err(err) => {
+                match self.terminate(&mut child, process.termination_grace, EventSource::User).await {
+                    Ok(signal) => ProcessStatus::Canceled { signal: Some(signal) },
+                    Err(TerminateError::Kill(err)) => {
                         tracing::error!(?err, "Failed to kill OS process");
                         ProcessStatus::Failed{ operation: FailOperation::AfterCancelOnKill }
+                    },
+                    Err(TerminateError::Wait(err)) => {
+                        tracing::error!(?err, "Failed to wait for OS process");
+                        ProcessStatus::Failed{ operation: FailOperation::AfterCancelOnWait }
                     }
                 }
             },
@@ -321,7 +1001,11 @@ impl Task {
                 match res {
                     Ok(exit_status) => {
                         tracing::debug!(?exit_status, "OS process exited with status");
-                        ProcessStatus::from(exit_status)
+
+                        match process.limits.exceeded(&exit_status) {
+                            Some(limit) => ProcessStatus::LimitExceeded { limit },
+                            None => ProcessStatus::from(exit_status),
+                        }
                     },
                     Err(err) => {
                         tracing::error!(?err, "Failed to wait for OS process");
@@ -331,109 +1015,383 @@ impl Task {
             }
         };
 
-        self.set_status_and_log(Status::Process(status)).await;
+        let stdout_writer = Self::join_copy(stdout_copy).await;
+        let stderr_writer = Self::join_copy(stderr_copy).await;
 
-        tracing::debug!("Terminated");
+        // Not aborted like the output copies, the copy owns the reader of the task
+        let stdin_reader = match stdin_copy {
+            Some((done_tx, copy)) => {
+                let _ = done_tx.send(());
+
+                copy.await.ok().flatten()
+            }
+            None => None,
+        };
+
+        (status, stdout_writer, stderr_writer, stdin_reader)
+    }
+
+    /// Writes input to stdin until the attempt is done. The reader is handed back for the next attempt,
+    /// unless stdin was closed.
+    ///
+    /// Returns as soon as `done` fires, even while a write is blocked on a full pipe.
+    async fn copy_stdin(
+        mut reader: mpsc::Receiver<StdinInput>,
+        mut stdin: ChildStdin,
+        mut done: oneshot::Receiver<()>,
+    ) -> Option<mpsc::Receiver<StdinInput>> {
+        loop {
+            let input = tokio::select! {
+                input = reader.recv() => input,
+                _ = &mut done => return Some(reader),
+            };
+
+            match input {
+                Some(StdinInput::Data(data)) => {
+                    let written = async {
+                        stdin.write_all(&data).await?;
+                        stdin.flush().await
+                    };
+
+                    // The OS process may never read the input
+                    let written = tokio::select! {
+                        written = written => written,
+                        _ = &mut done => return Some(reader),
+                    };
+
+                    if let Err(err) = written {
+                        // The OS process exited or closed stdin. The input is dropped
+                        tracing::debug!(%err, "Failed to write to stdin");
+
+                        return Some(reader);
+                    }
+                }
+                Some(StdinInput::Eof) => {
+                    tracing::debug!("Closing stdin");
+
+                    return None;
+                }
+                // The handle was dropped
+                None => return None,
+            }
+        }
     }
 
+    /// Waits for a copy task to hand back its writer.
+    ///
+    /// Processes that outlive the OS process may keep the pipe open. Their output is dropped.
+    async fn join_copy<W>(copy: Option<tokio::task::JoinHandle<W>>) -> Option<W> {
+        let mut copy = copy?;
+
+        match tokio::time::timeout(Duration::from_secs(1), &mut copy).await {
+            Ok(write) => write.ok(),
+            Err(_) => {
+                tracing::warn!("Pipe is still open after the OS process exited");
+
+                copy.abort();
+
+                None
+            }
+        }
+    }
+
+    /// Sends SIGTERM to the process group and SIGKILL once the grace period is over.
+    /// Returns the signal that ended the OS process.
+    ///
+    /// The group is killed in any case, so that no grandchildren are left behind.
+    #[cfg(unix)]
+    async fn terminate(
+        &self,
+        child: &mut tokio::process::Child,
+        grace: Duration,
+        source: EventSource,
+    ) -> Result<TerminationSignal, TerminateError> {
+        let Some(pgid) = child.id() else {
+            // Already reaped
+            return Ok(TerminationSignal::Sigterm);
+        };
+
+        let killpg = |signal| {
+            // SAFETY: killpg has no memory safety preconditions
+            if unsafe { libc::killpg(pgid as libc::pid_t, signal) } == 0 {
+                return Ok(());
+            }
+
+            match std::io::Error::last_os_error() {
+                // The group is already gone
+                err if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+                err => Err(err),
+            }
+        };
+
+        let sigterm = HistoryEvent::SignalSent {
+            signal: TerminationSignal::Sigterm,
+        };
+        self.record(source, sigterm).await;
+
+        killpg(libc::SIGTERM).map_err(TerminateError::Kill)?;
+
+        tracing::debug!(?grace, "Sent SIGTERM to process group");
+
+        let signal = match tokio::time::timeout(grace, child.wait()).await {
+            Ok(exit_status) => {
+                let exit_status = exit_status.map_err(TerminateError::Wait)?;
+
+                tracing::debug!(?exit_status, "OS process exited after SIGTERM");
+
+                TerminationSignal::Sigterm
+            }
+            Err(_) => {
+                tracing::debug!("Grace period is over");
+
+                let sigkill = HistoryEvent::SignalSent {
+                    signal: TerminationSignal::Sigkill,
+                };
+                self.record(source, sigkill).await;
+
+                TerminationSignal::Sigkill
+            }
+        };
+
+        killpg(libc::SIGKILL).map_err(TerminateError::Kill)?;
+
+        if signal == TerminationSignal::Sigkill {
+            let exit_status = child.wait().await.map_err(TerminateError::Wait)?;
+
+            tracing::debug!(?exit_status, "OS process exited after SIGKILL");
+        }
+
+        Ok(signal)
+    }
+
+    /// There are no signals on this platform. The OS process is killed immediately.
+    #[cfg(not(unix))]
+    async fn terminate(
+        &self,
+        child: &mut tokio::process::Child,
+        _grace: Duration,
+        source: EventSource,
+    ) -> Result<TerminationSignal, TerminateError> {
+        let sigkill = HistoryEvent::SignalSent {
+            signal: TerminationSignal::Sigkill,
+        };
+        self.record(source, sigkill).await;
+
+        child.kill().await.map_err(TerminateError::Kill)?;
+
+        tracing::debug!("Killed OS process");
+
+        Ok(TerminationSignal::Sigkill)
+    }
+
+    /// Downloads and unzips until an attempt succeeds or the retry policy gives up.
     #[tracing::instrument(skip_all, fields(id=self.id(), timeout))]
     pub async fn run_download_and_unzip_from_download_url(
         mut self,
         timeout: Duration,
-        download_url: url::Url,
-        project_dir: std::path::PathBuf,
-    ) {
-        self.set_status_and_log(Status::Download(DownloadZipFileStatus::Running))
+        retry: RetryPolicy,
+        request: DownloadRequest,
+    ) -> Status {
+        let mut attempt = 1;
+        // Kept across attempts, so that a retry resumes the download
+        let mut partial = PartialDownload::new();
+
+        let status = loop {
+            self.start_attempt(attempt).await;
+
+            self.set_status_and_log(
+                Status::Download(DownloadZipFileStatus::Running),
+                EventSource::System,
+            )
             .await;
 
-        let status = tokio::select! {
-            _ = tokio::time::sleep(timeout) => {
-                tracing::debug!("Timeout");
+            let progress = self.progress();
+            let mut checksum = None;
+
+            let (cancel, canceled) = watch::channel(false);
+            let work = download::download_and_extract(&request, &mut partial, progress, canceled);
+            tokio::pin!(work);
+
+            let (status, failure) = tokio::select! {
+                _ = tokio::time::sleep(timeout) => {
+                    tracing::debug!("Timeout");
+
+                    // A retry rewrites the temporary file, which the extraction may still read
+                    cancel.send_replace(true);
+                    let _ = work.await;
+
+                    (DownloadZipFileStatus::Timeout, Some(RetryableFailure::Timeout))
+                },
+                _ = self.wait_for_cancel_signal() => {
+                    // Dropping the download would leave the extraction writing files
+                    cancel.send_replace(true);
+                    let _ = work.await;
+
+                    (DownloadZipFileStatus::Canceled, None)
+                },
+                result = &mut work => {
+                    match result {
+                        Ok(computed) => {
+                            checksum = Some(computed);
+
+                            (DownloadZipFileStatus::Exited, None)
+                        },
+                        Err(err) => {
+                            let failure = err.retryable_failure();
+                            let reason = FailureReason::new(err.failure_code(), err.to_string());
+
+                            (DownloadZipFileStatus::Failed { reason }, failure)
+                        }
+                    }
+                },
+            };
 
-                DownloadZipFileStatus::Timeout
-            },
-            _ = self.wait_for_cancel_signal() => {
+            let status = Status::Download(status);
 
-                DownloadZipFileStatus::Canceled
-            },
-            result = Self::download_and_unzip_from_download_url(download_url, project_dir) => {
-                match result {
-                    Ok(_) => {
-                        DownloadZipFileStatus::Exited
-                    },
-                    Err(err) => {
-                        DownloadZipFileStatus::Failed { reason: err.to_string() }
-                    }
-                }
-            },
+            if let Some(checksum) = checksum {
+                self.set_checksum(&checksum).await;
+            }
+
+            self.finish_attempt(attempt, &status).await;
+
+            if !retry.should_retry(attempt, failure) {
+                break status;
+            }
+
+            attempt += 1;
+
+            if let Err(canceled) = self
+                .wait_for_retry(TaskKind::Download, attempt, &retry)
+                .await
+            {
+                break canceled;
+            }
         };
 
-        self.set_status_and_log(Status::Download(status)).await;
+        self.set_status_and_log(status.clone(), final_source(&status))
+            .await;
 
         tracing::debug!("Terminated");
-    }
 
-    async fn download_and_unzip_from_download_url(
-        download_url: url::Url,
-        project_dir: std::path::PathBuf,
-    ) -> Result<(), DownloadError> {
-        let response = reqwest::get(download_url)
-            .await
-            .map_err(DownloadError::Reqwest)?;
+        status
+    }
+}
 
-        let bytes = response.bytes().await.map_err(DownloadError::Bytes)?;
-        tracing::debug!("Zip file downloaded");
+/// What caused the final status of a task or an attempt.
+/// Cancel signals are only sent on request of a user.
+fn final_source(status: &Status) -> EventSource {
+    match status {
+        Status::Download(DownloadZipFileStatus::Timeout)
+        | Status::Process(
+            ProcessStatus::Timeout { .. }
+            | ProcessStatus::Failed {
+                operation: FailOperation::AfterTimeoutOnKill | FailOperation::AfterTimeoutOnWait,
+            },
+        ) => EventSource::Timeout,
+        Status::Download(DownloadZipFileStatus::Canceled)
+        | Status::Process(
+            ProcessStatus::Canceled { .. }
+            | ProcessStatus::Failed {
+                operation: FailOperation::AfterCancelOnKill | FailOperation::AfterCancelOnWait,
+            },
+        ) => EventSource::User,
+        _ => EventSource::System,
+    }
+}
 
-        let zip = zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(DownloadError::Zip)?;
+/// Inner error type for [`Task::terminate`]
+#[derive(Debug)]
+enum TerminateError {
+    Kill(std::io::Error),
+    Wait(std::io::Error),
+}
 
-        tracing::debug!("Unzipping files");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::process::Stdio;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stdin_copy_hands_back_the_reader_when_a_write_blocks() {
+        let mut child = Command::new("sleep")
+            .arg("10")
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel(STDIN_CAPACITY);
+        let (done_tx, done_rx) = oneshot::channel();
+        let copy = tokio::spawn(Task::copy_stdin(rx, child.stdin.take().unwrap(), done_rx));
+
+        // Larger than the pipe buffer, nobody reads it
+        tx.send(StdinInput::Data(vec![b'a'; 4 * 1024 * 1024]))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let _ = done_tx.send(());
 
-        // ZipFile is not Send -> spawn_blocking
-        tokio::task::spawn_blocking(move || Self::unzip(zip, project_dir))
+        let reader = tokio::time::timeout(Duration::from_secs(1), copy)
             .await
-            .map_err(|_| DownloadError::BlockingTask)?
+            .expect("The copy did not return")
+            .unwrap();
+        assert!(reader.is_some());
     }
 
-    fn unzip(
-        mut zip: zip::ZipArchive<std::io::Cursor<axum::body::Bytes>>,
-        project_dir: std::path::PathBuf,
-    ) -> Result<(), DownloadError> {
-        for i in 0..zip.len() {
-            let mut file = zip.by_index(i).map_err(DownloadError::Zip)?;
-            let file_name = std::path::PathBuf::from(file.name());
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stdin_eof_closes_stdin_for_the_rest_of_the_task() {
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
 
-            // Strip all directories
-            let file_name = file_name
-                .file_name()
-                .ok_or(DownloadError::Io(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Invalid file name",
-                )))?;
+        let (tx, rx) = mpsc::channel(STDIN_CAPACITY);
+        let (_done_tx, done_rx) = oneshot::channel();
 
-            let file_name = project_dir.join(file_name);
+        tx.send(StdinInput::Data(b"line\n".to_vec())).await.unwrap();
+        tx.send(StdinInput::Eof).await.unwrap();
 
-            let mut outfile = std::fs::File::create(&file_name).map_err(DownloadError::Io)?;
+        let reader = Task::copy_stdin(rx, child.stdin.take().unwrap(), done_rx).await;
+        assert!(reader.is_none());
 
-            let _ = std::io::copy(&mut file, &mut outfile).map_err(DownloadError::Io)?;
+        // cat exits once stdin is closed
+        let output = tokio::time::timeout(Duration::from_secs(1), child.wait_with_output())
+            .await
+            .expect("stdin was not closed")
+            .unwrap();
+        assert_eq!(output.stdout, b"line\n");
+    }
 
-            tracing::debug!(?file_name, "Unzipped file");
-        }
+    #[test]
+    fn failures_have_the_same_shape_for_every_kind() {
+        let download = Status::interrupted(TaskKind::Download);
+        let process = Status::interrupted(TaskKind::Process);
 
-        Ok(())
-    }
-}
+        assert_eq!(download.phase(), TaskPhase::Failed);
+        assert_eq!(process.phase(), TaskPhase::Failed);
+        assert_eq!(download.failure(), process.failure());
 
-/// Inner error type for [`Task::download_and_unzip_from_download_url`]
-#[derive(Debug, thiserror::Error)]
-enum DownloadError {
-    #[error("Reqwest error: {0}")]
-    Reqwest(reqwest::Error),
-    #[error("Failed to extract bytes: {0}")]
-    Bytes(reqwest::Error),
-    #[error("Zip error: {0}")]
-    Zip(zip::result::ZipError),
-    #[error("Io error: {0}")]
-    Io(std::io::Error),
-    #[error("Failed to spawn blocking task")]
-    BlockingTask,
+        let exited = Status::Process(ProcessStatus::Exited {
+            exit_status: ExitedStatus::Failure {
+                code: Some(2),
+                signal: None,
+            },
+        });
+        let failure = exited.failure().unwrap();
+        assert_eq!(failure.code, FailureCode::NonZeroExit);
+        assert_eq!(failure.message, "Exited with code 2");
+
+        let succeeded = Status::Download(DownloadZipFileStatus::Exited);
+        assert_eq!(succeeded.phase(), TaskPhase::Succeeded);
+        assert!(succeeded.failure().is_none());
+        assert_eq!(
+            Status::canceled(TaskKind::Process).phase(),
+            TaskPhase::Canceled
+        );
+    }
 }
@@ -1,3 +1,7 @@
+use super::{
+    output::OutputStream,
+    task::{Status, StdinError},
+};
 use serde::{Deserialize, Serialize};
 
 // #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,13 +14,36 @@ use serde::{Deserialize, Serialize};
 // }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ClientMessage {}
+#[serde(tag = "client_message", content = "content")]
+pub enum ClientMessage {
+    /// Receive the status changes and output of the given tasks.
+    /// The current status and the output so far are sent first
+    Subscribe {
+        ids: Vec<String>,
+    },
+    Unsubscribe {
+        ids: Vec<String>,
+    },
+    /// Write to the stdin of an interactive task
+    Stdin {
+        id: String,
+        data: String,
+    },
+    /// Close the stdin of an interactive task
+    StdinEof {
+        id: String,
+    },
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "server_message", content = "content")]
 pub enum ServerMessage {
     /// A Chunk of IO output from a task
     TaskIoChunk(TaskIoChunk),
+    /// The status of a task. Sent on subscribe and on every change
+    TaskStatus(TaskStatus),
+    /// A client message could not be handled
+    Error(WsError),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,3 +58,41 @@ pub enum IoType {
     Stdout,
     Stderr,
 }
+
+impl From<OutputStream> for IoType {
+    fn from(stream: OutputStream) -> Self {
+        match stream {
+            OutputStream::Stdout => Self::Stdout,
+            OutputStream::Stderr => Self::Stderr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub id: String,
+    pub status: Status,
+    /// Incremented with every status change
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "error", content = "content")]
+pub enum WsError {
+    /// The message is not a valid [`ClientMessage`]
+    InvalidMessage(String),
+    /// The task does not exist or belongs to another chat
+    TaskNotFound {
+        id: String,
+    },
+    /// Input for stdin was rejected
+    Stdin {
+        id: String,
+        error: StdinError,
+    },
+    /// The connection fell behind and missed events. Subscribe again to catch up
+    Lagged {
+        skipped: u64,
+    },
+    ServerError,
+}